@@ -1,19 +1,408 @@
-use serde::Deserialize;
-use std::collections::HashMap;
+use crate::models::{Contact, Crs, SpatialExtent, TemporalExtent};
+use indexmap::IndexMap;
+use serde::{Deserialize, Deserializer, de};
+use std::time::Duration;
 
 #[derive(Deserialize, Debug)]
 pub struct AppConfig {
     pub title: String,
     pub description: String,
     pub url_base: String,
-    #[serde(rename = "collections")]
-    pub collections: HashMap<String, CollectionConfig>,
+    /// How long, in seconds, the extents computed from the data are cached before being computed
+    /// again.
+    #[serde(default = "default_extent_refresh_interval")]
+    pub extent_refresh_interval: u64,
+    /// The largest number of features of a FlatGeobuf response for which a spatial index is
+    /// built, 0 never building one.
+    #[serde(default = "default_flatgeobuf_index_max_features")]
+    pub flatgeobuf_index_max_features: u64,
+    /// The largest number of features a GeoPackage download holds. Requests matching more
+    /// features are rejected, to bound the size of the temporary files.
+    #[serde(default = "default_geopackage_max_features")]
+    pub geopackage_max_features: u64,
+    /// The number of features of a page of items when the request has no `limit`.
+    #[serde(default = "default_limit")]
+    pub default_limit: u64,
+    /// The largest `limit` of a page of items, larger limits being lowered to it.
+    #[serde(default = "default_max_limit")]
+    pub max_limit: u64,
+    /// The largest number of database connections. Each page of items being sent holds one until
+    /// it is read by the client.
+    #[serde(default = "default_max_connections")]
+    pub max_connections: u32,
+    /// How long, in seconds, a page of items waits for the client to read it before its database
+    /// connection is released and the response is cut short.
+    #[serde(default = "default_stream_idle_timeout")]
+    pub stream_idle_timeout: u64,
+    /// Publishes the tables with a geometry column as collections, after the configured ones.
+    #[serde(default)]
+    pub discovery: Option<DiscoveryConfig>,
+    /// The collections, listed in the order they are declared in.
+    #[serde(
+        rename = "collections",
+        default,
+        deserialize_with = "deserialize_collections"
+    )]
+    pub collections: IndexMap<String, CollectionConfig>,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+/// The fields of a collection that are only optional when it is merged over a discovered one.
+const DISCOVERABLE_FIELDS: [&str; 4] = ["table", "id_column", "geometry_column", "properties"];
+
+/// Deserializes the collections, keeping the fields each one sets so that they can be merged
+/// over the collection discovered for the same table.
+fn deserialize_collections<'de, D>(
+    deserializer: D,
+) -> Result<IndexMap<String, CollectionConfig>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    IndexMap::<String, toml::Table>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(collection_id, fields)| {
+            let mut collection: CollectionConfig = fields
+                .clone()
+                .try_into()
+                .map_err(|e| de::Error::custom(format!("collection {}: {}", collection_id, e)))?;
+            collection.configured = Some(fields);
+            Ok((collection_id, collection))
+        })
+        .collect()
+}
+
+fn default_extent_refresh_interval() -> u64 {
+    300
+}
+
+fn default_flatgeobuf_index_max_features() -> u64 {
+    10_000
+}
+
+fn default_geopackage_max_features() -> u64 {
+    100_000
+}
+
+fn default_limit() -> u64 {
+    10
+}
+
+fn default_max_limit() -> u64 {
+    10_000
+}
+
+fn default_max_connections() -> u32 {
+    20
+}
+
+fn default_stream_idle_timeout() -> u64 {
+    30
+}
+
+impl AppConfig {
+    pub fn extent_refresh_interval(&self) -> Duration {
+        Duration::from_secs(self.extent_refresh_interval)
+    }
+
+    pub fn stream_idle_timeout(&self) -> Duration {
+        Duration::from_secs(self.stream_idle_timeout)
+    }
+
+    /// The default and the maximum `limit` of the items of a collection, its own settings taking
+    /// precedence over the global ones, or the global ones without a collection. The default never
+    /// exceeds the maximum.
+    pub fn limits(&self, collection: Option<&CollectionConfig>) -> (u64, u64) {
+        let max = collection
+            .and_then(|collection| collection.max_limit)
+            .unwrap_or(self.max_limit)
+            .max(1);
+        let default = collection
+            .and_then(|collection| collection.default_limit)
+            .unwrap_or(self.default_limit);
+        (default.clamp(1, max), max)
+    }
+
+    /// Checks that the configured collections that were not merged over a discovered one name
+    /// their table and columns, and that writable collections write to a plain geometry column
+    /// rather than to an expression such as the cast of a geography.
+    pub fn check_collections(&self) -> Result<(), String> {
+        for (collection_id, collection) in &self.collections {
+            if collection.writable && collection.geometry_column.contains("::") {
+                return Err(format!(
+                    "Collection {} cannot be writable, its geometry is read from {}",
+                    collection_id, collection.geometry_column
+                ));
+            }
+            let Some(configured) = &collection.configured else {
+                continue;
+            };
+            let missing: Vec<_> = DISCOVERABLE_FIELDS
+                .into_iter()
+                .filter(|field| !configured.contains_key(*field))
+                .collect();
+            if !missing.is_empty() {
+                return Err(format!(
+                    "Collection {} matches no discovered table, so it needs {}",
+                    collection_id,
+                    missing.join(", ")
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Which tables are published as collections without being configured. Schema and table
+/// patterns may use `*` for any characters and `?` for a single one; table patterns match either
+/// the table name or the schema-qualified name.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct DiscoveryConfig {
+    /// Whether tables with a `geography` column are published too, in addition to the ones with
+    /// a `geometry` column. Their collections are read-only.
+    #[serde(default)]
+    pub geography: bool,
+    /// The schemas whose tables are published. Defaults to all schemas.
+    #[serde(default)]
+    pub schemas: Vec<String>,
+    /// The schemas whose tables are never published.
+    #[serde(default)]
+    pub exclude_schemas: Vec<String>,
+    /// The tables that are published. Defaults to all tables.
+    #[serde(default)]
+    pub tables: Vec<String>,
+    /// The tables that are never published.
+    #[serde(default)]
+    pub exclude_tables: Vec<String>,
+}
+
+/// The type of a collection's ID column, which decides how feature IDs are bound and serialized.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum IdType {
+    /// `smallint`, `integer` or `bigint` columns, serialized as JSON numbers.
+    #[default]
+    #[serde(alias = "bigint")]
+    Integer,
+    /// Character columns, serialized as JSON strings.
+    Text,
+    /// `uuid` columns, serialized as JSON strings.
+    Uuid,
+}
+
+impl IdType {
+    /// Maps a PostgreSQL data type, as reported by `information_schema.columns`, to an ID type.
+    pub fn from_data_type(data_type: &str) -> Option<Self> {
+        match data_type {
+            "smallint" | "integer" | "bigint" => Some(Self::Integer),
+            "text" | "character varying" | "character" => Some(Self::Text),
+            "uuid" => Some(Self::Uuid),
+            _ => None,
+        }
+    }
+}
+
+/// The columns holding the temporal information of a collection's features.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum TemporalConfig {
+    /// A single column with the instant of each feature.
+    Instant { instant: String },
+    /// Columns with the start and end of each feature's validity period, NULL meaning unbounded.
+    Interval { start: String, end: String },
+}
+
+/// How the geometry of a collection's features is written in CSV.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum CsvGeometryConfig {
+    /// The geometry as WKT in a single column.
+    Wkt { wkt: String },
+    /// The coordinates of point geometries in two columns, left empty for other geometries.
+    Xy { x: String, y: String },
+}
+
+impl Default for CsvGeometryConfig {
+    fn default() -> Self {
+        Self::Wkt {
+            wkt: "geometry".to_string(),
+        }
+    }
+}
+
+/// The vector tiles of a collection.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct TilesConfig {
+    /// The lowest zoom level tiles are served at.
+    #[serde(default)]
+    pub min_zoom: u8,
+    /// The highest zoom level tiles are served at, clients overzooming beyond it.
+    #[serde(default = "default_tiles_max_zoom")]
+    pub max_zoom: u8,
+    /// The properties included in the tiles. Defaults to all properties.
+    #[serde(default)]
+    pub properties: Option<Vec<String>>,
+}
+
+fn default_tiles_max_zoom() -> u8 {
+    16
+}
+
+impl Default for TilesConfig {
+    fn default() -> Self {
+        Self {
+            min_zoom: 0,
+            max_zoom: default_tiles_max_zoom(),
+            properties: None,
+        }
+    }
+}
+
+/// The extent of a collection given in the configuration, replacing the one computed from the
+/// data.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ExtentConfig {
+    /// The bounding box in CRS84, as `[min lon, min lat, max lon, max lat]`.
+    #[serde(default)]
+    pub bbox: Option<[f64; 4]>,
+    /// The start and end of the temporal extent as RFC 3339 timestamps, `..` marking an open end.
+    #[serde(default)]
+    pub interval: Option<[String; 2]>,
+}
+
+impl ExtentConfig {
+    pub fn spatial(&self) -> Option<SpatialExtent> {
+        self.bbox.map(SpatialExtent::new)
+    }
+
+    pub fn temporal(&self) -> Option<TemporalExtent> {
+        let bound = |value: &String| (value != "..").then(|| value.clone());
+        self.interval
+            .as_ref()
+            .map(|[start, end]| TemporalExtent::new(bound(start), bound(end)))
+    }
+}
+
+/// A link to a document about a collection, such as its license.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct LinkConfig {
+    pub href: String,
+    #[serde(default, rename = "type")]
+    pub type_: Option<String>,
+    #[serde(default)]
+    pub title: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
 pub struct CollectionConfig {
+    /// A human readable title. Defaults to the collection ID.
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    /// The license of the data, linked with the `license` relation.
+    #[serde(default)]
+    pub license: Option<LinkConfig>,
+    /// A document describing the data, linked with the `describedby` relation.
+    #[serde(default)]
+    pub describedby: Option<LinkConfig>,
+    /// The attribution to show along with the data, such as a copyright notice.
+    #[serde(default)]
+    pub attribution: Option<String>,
+    /// Who to contact about the data.
+    #[serde(default)]
+    pub contact: Option<Contact>,
+    /// The table, along with `id_column`, `geometry_column` and `properties`, may be left out of
+    /// a collection merged over a discovered one.
+    #[serde(default)]
     pub table: String,
+    #[serde(default)]
     pub id_column: String,
+    /// The type of the ID column. Detected from the database when not set.
+    #[serde(default)]
+    pub id_type: Option<IdType>,
+    #[serde(default)]
     pub geometry_column: String,
+    #[serde(default)]
     pub properties: Vec<String>,
+    /// The CRS the geometries are stored in. Defaults to CRS84.
+    #[serde(default)]
+    pub storage_crs: Option<Crs>,
+    /// Additional CRSs features can be requested in. CRS84 and the storage CRS are always supported.
+    #[serde(default)]
+    pub crs: Vec<Crs>,
+    /// The properties the features can be sorted by. Defaults to all properties.
+    #[serde(default)]
+    pub sortables: Option<Vec<String>>,
+    /// The temporal columns features are filtered on with `datetime`.
+    #[serde(default)]
+    pub temporal: Option<TemporalConfig>,
+    /// Whether features can be created, replaced, updated and deleted. Collections are read-only
+    /// by default.
+    #[serde(default)]
+    pub writable: bool,
+    /// A column whose value changes on every write of a feature, such as a revision number or an
+    /// update timestamp maintained by the database, from which ETags are made. ETags are a hash
+    /// of the whole row otherwise.
+    #[serde(default)]
+    pub version_column: Option<String>,
+    /// The extent reported in the collection metadata. Any part left out is computed from the data.
+    #[serde(default)]
+    pub extent: ExtentConfig,
+    /// The columns the geometry is written in by CSV responses, a WKT `geometry` column by
+    /// default.
+    #[serde(default)]
+    pub csv_geometry: CsvGeometryConfig,
+    /// The number of features of a page of items when the request has no `limit`, overriding the
+    /// global `default_limit`.
+    #[serde(default)]
+    pub default_limit: Option<u64>,
+    /// The largest `limit` of a page of items, overriding the global `max_limit`.
+    #[serde(default)]
+    pub max_limit: Option<u64>,
+    /// The zoom levels and properties of the vector tiles.
+    #[serde(default)]
+    pub tiles: TilesConfig,
+    /// The fields set in the configuration file, until they are merged over the fields of a
+    /// discovered collection.
+    #[serde(skip)]
+    pub configured: Option<toml::Table>,
+}
+
+impl CollectionConfig {
+    pub fn title<'a>(&'a self, collection_id: &'a str) -> &'a str {
+        self.title.as_deref().unwrap_or(collection_id)
+    }
+
+    pub fn description(&self, collection_id: &str) -> String {
+        self.description
+            .clone()
+            .unwrap_or_else(|| format!("Collection of {}", collection_id))
+    }
+
+    pub fn storage_crs(&self) -> Crs {
+        self.storage_crs.clone().unwrap_or_else(Crs::crs84)
+    }
+
+    pub fn id_type(&self) -> IdType {
+        self.id_type.unwrap_or_default()
+    }
+
+    pub fn sortables(&self) -> &[String] {
+        self.sortables.as_deref().unwrap_or(&self.properties)
+    }
+
+    pub fn tile_properties(&self) -> &[String] {
+        self.tiles.properties.as_deref().unwrap_or(&self.properties)
+    }
+
+    pub fn supported_crs(&self) -> Vec<Crs> {
+        let mut supported = vec![Crs::crs84()];
+        for crs in std::iter::once(self.storage_crs()).chain(self.crs.iter().cloned()) {
+            if !supported.contains(&crs) {
+                supported.push(crs);
+            }
+        }
+        supported
+    }
 }
@@ -0,0 +1,121 @@
+/*
+ * The abstract syntax tree of a CQL2 filter expression.
+ *
+ * Both the text and the JSON encodings are parsed into this tree, which is then translated
+ * into SQL by the storage drivers. Negated predicates (`NOT LIKE`, `NOT IN`, ...) are
+ * represented as `Expr::Not` around the positive predicate.
+ */
+use geojson::Geometry;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+    Not(Box<Expr>),
+    Bool(bool),
+    Comparison {
+        op: ComparisonOp,
+        left: Scalar,
+        right: Scalar,
+    },
+    Like {
+        value: Scalar,
+        pattern: Scalar,
+    },
+    Between {
+        value: Scalar,
+        low: Scalar,
+        high: Scalar,
+    },
+    In {
+        value: Scalar,
+        list: Vec<Scalar>,
+    },
+    IsNull(Scalar),
+    Spatial {
+        op: SpatialOp,
+        left: SpatialOperand,
+        right: SpatialOperand,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComparisonOp {
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Scalar {
+    Property(String),
+    String(String),
+    Number(f64),
+    Bool(bool),
+    Date(String),
+    Timestamp(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpatialOp {
+    Intersects,
+    Disjoint,
+    Contains,
+    Within,
+    Touches,
+    Crosses,
+    Overlaps,
+    Equals,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SpatialOperand {
+    Property(String),
+    Geometry(Geometry),
+}
+
+impl ComparisonOp {
+    pub fn as_sql(self) -> &'static str {
+        match self {
+            ComparisonOp::Eq => "=",
+            ComparisonOp::NotEq => "<>",
+            ComparisonOp::Lt => "<",
+            ComparisonOp::LtEq => "<=",
+            ComparisonOp::Gt => ">",
+            ComparisonOp::GtEq => ">=",
+        }
+    }
+}
+
+impl SpatialOp {
+    /// Looks up a spatial function by its CQL2 name, e.g. `S_INTERSECTS`, case-insensitively.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_uppercase().as_str() {
+            "S_INTERSECTS" => Some(SpatialOp::Intersects),
+            "S_DISJOINT" => Some(SpatialOp::Disjoint),
+            "S_CONTAINS" => Some(SpatialOp::Contains),
+            "S_WITHIN" => Some(SpatialOp::Within),
+            "S_TOUCHES" => Some(SpatialOp::Touches),
+            "S_CROSSES" => Some(SpatialOp::Crosses),
+            "S_OVERLAPS" => Some(SpatialOp::Overlaps),
+            "S_EQUALS" => Some(SpatialOp::Equals),
+            _ => None,
+        }
+    }
+
+    pub fn as_postgis_function(self) -> &'static str {
+        match self {
+            SpatialOp::Intersects => "ST_Intersects",
+            SpatialOp::Disjoint => "ST_Disjoint",
+            SpatialOp::Contains => "ST_Contains",
+            SpatialOp::Within => "ST_Within",
+            SpatialOp::Touches => "ST_Touches",
+            SpatialOp::Crosses => "ST_Crosses",
+            SpatialOp::Overlaps => "ST_Overlaps",
+            SpatialOp::Equals => "ST_Equals",
+        }
+    }
+}
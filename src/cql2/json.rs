@@ -0,0 +1,250 @@
+/*
+ * A parser for the CQL2 JSON encoding.
+ *
+ * Expressions are objects with an `op` and its `args`, e.g.
+ * `{"op": "=", "args": [{"property": "name"}, "Chad"]}`. Geometry literals are GeoJSON
+ * geometry objects or `{"bbox": [...]}`.
+ * https://docs.ogc.org/is/21-065r2/21-065r2.html#cql2-json
+ */
+use super::ast::{ComparisonOp, Expr, Scalar, SpatialOp, SpatialOperand};
+use geojson::{Geometry, Value as GeometryValue};
+use serde_json::{Map, Value};
+
+fn args<'a>(op: &str, object: &'a Map<String, Value>) -> Result<&'a [Value], String> {
+    object
+        .get("args")
+        .and_then(Value::as_array)
+        .map(Vec::as_slice)
+        .ok_or_else(|| format!("operator '{}' requires an 'args' array", op))
+}
+
+fn expect_args<'a, const N: usize>(
+    op: &str,
+    object: &'a Map<String, Value>,
+) -> Result<&'a [Value; N], String> {
+    args(op, object)?
+        .try_into()
+        .map_err(|_| format!("operator '{}' expects {} arguments", op, N))
+}
+
+fn parse_expr(value: &Value) -> Result<Expr, String> {
+    let object = match value {
+        Value::Bool(b) => return Ok(Expr::Bool(*b)),
+        Value::Object(object) => object,
+        other => return Err(format!("expected a CQL2 expression but found {}", other)),
+    };
+    let op = object
+        .get("op")
+        .and_then(Value::as_str)
+        .ok_or_else(|| format!("expected an object with an 'op' member but found {}", value))?;
+
+    let comparison = match op {
+        "=" => Some(ComparisonOp::Eq),
+        "<>" => Some(ComparisonOp::NotEq),
+        "<" => Some(ComparisonOp::Lt),
+        "<=" => Some(ComparisonOp::LtEq),
+        ">" => Some(ComparisonOp::Gt),
+        ">=" => Some(ComparisonOp::GtEq),
+        _ => None,
+    };
+    if let Some(op_kind) = comparison {
+        let [left, right] = expect_args(op, object)?;
+        return Ok(Expr::Comparison {
+            op: op_kind,
+            left: parse_scalar(left)?,
+            right: parse_scalar(right)?,
+        });
+    }
+
+    if let Some(spatial_op) = SpatialOp::from_name(op) {
+        let [left, right] = expect_args(op, object)?;
+        return Ok(Expr::Spatial {
+            op: spatial_op,
+            left: parse_spatial_operand(left)?,
+            right: parse_spatial_operand(right)?,
+        });
+    }
+
+    match op {
+        "and" | "or" => {
+            let exprs = args(op, object)?
+                .iter()
+                .map(parse_expr)
+                .collect::<Result<Vec<_>, _>>()?;
+            if exprs.len() < 2 {
+                return Err(format!("operator '{}' expects at least 2 arguments", op));
+            }
+            Ok(if op == "and" {
+                Expr::And(exprs)
+            } else {
+                Expr::Or(exprs)
+            })
+        }
+        "not" => {
+            let [inner] = expect_args(op, object)?;
+            Ok(Expr::Not(Box::new(parse_expr(inner)?)))
+        }
+        "like" => {
+            let [value, pattern] = expect_args(op, object)?;
+            Ok(Expr::Like {
+                value: parse_scalar(value)?,
+                pattern: parse_scalar(pattern)?,
+            })
+        }
+        "between" => {
+            let [value, low, high] = expect_args(op, object)?;
+            Ok(Expr::Between {
+                value: parse_scalar(value)?,
+                low: parse_scalar(low)?,
+                high: parse_scalar(high)?,
+            })
+        }
+        "in" => {
+            let [value, list] = expect_args(op, object)?;
+            let list = list
+                .as_array()
+                .filter(|list| !list.is_empty())
+                .ok_or("the second argument of 'in' must be a non-empty array")?
+                .iter()
+                .map(parse_scalar)
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(Expr::In {
+                value: parse_scalar(value)?,
+                list,
+            })
+        }
+        "isNull" => {
+            let [value] = expect_args(op, object)?;
+            Ok(Expr::IsNull(parse_scalar(value)?))
+        }
+        _ => Err(format!("unsupported operator '{}'", op)),
+    }
+}
+
+fn parse_scalar(value: &Value) -> Result<Scalar, String> {
+    match value {
+        Value::String(s) => Ok(Scalar::String(s.clone())),
+        Value::Number(n) => n
+            .as_f64()
+            .map(Scalar::Number)
+            .ok_or_else(|| format!("invalid number {}", n)),
+        Value::Bool(b) => Ok(Scalar::Bool(*b)),
+        Value::Object(object) => {
+            if let Some(property) = object.get("property").and_then(Value::as_str) {
+                Ok(Scalar::Property(property.to_string()))
+            } else if let Some(date) = object.get("date").and_then(Value::as_str) {
+                Ok(Scalar::Date(date.to_string()))
+            } else if let Some(timestamp) = object.get("timestamp").and_then(Value::as_str) {
+                Ok(Scalar::Timestamp(timestamp.to_string()))
+            } else {
+                Err(format!(
+                    "expected a property or literal but found {}",
+                    value
+                ))
+            }
+        }
+        other => Err(format!(
+            "expected a property or literal but found {}",
+            other
+        )),
+    }
+}
+
+fn parse_spatial_operand(value: &Value) -> Result<SpatialOperand, String> {
+    let Value::Object(object) = value else {
+        return Err(format!("expected a geometry but found {}", value));
+    };
+
+    if let Some(property) = object.get("property").and_then(Value::as_str) {
+        return Ok(SpatialOperand::Property(property.to_string()));
+    }
+
+    if let Some(bbox) = object.get("bbox") {
+        let bbox: Vec<f64> =
+            serde_json::from_value(bbox.clone()).map_err(|e| format!("invalid bbox: {}", e))?;
+        let (min_x, min_y, max_x, max_y) = match bbox[..] {
+            [min_x, min_y, max_x, max_y] => (min_x, min_y, max_x, max_y),
+            [min_x, min_y, _, max_x, max_y, _] => (min_x, min_y, max_x, max_y),
+            _ => return Err("bbox must have 4 or 6 components".to_string()),
+        };
+        return Ok(SpatialOperand::Geometry(Geometry::new(
+            GeometryValue::Polygon(vec![vec![
+                vec![min_x, min_y],
+                vec![max_x, min_y],
+                vec![max_x, max_y],
+                vec![min_x, max_y],
+                vec![min_x, min_y],
+            ]]),
+        )));
+    }
+
+    Geometry::from_json_object(object.clone())
+        .map(SpatialOperand::Geometry)
+        .map_err(|e| format!("invalid geometry: {}", e))
+}
+
+/// Parses a CQL2 JSON expression.
+pub fn parse(value: &Value) -> Result<Expr, String> {
+    parse_expr(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cql2::parse_text;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_matches_text_encoding() {
+        let json = json!({
+            "op": "and",
+            "args": [
+                {"op": ">", "args": [{"property": "pop_est"}, 1000000]},
+                {"op": "not", "args": [{"op": "like", "args": [{"property": "name"}, "A%"]}]},
+                {"op": "in", "args": [{"property": "name"}, ["Chad", "Peru"]]},
+                {"op": "between", "args": [{"property": "pop_est"}, 1, 2]},
+                {"op": "isNull", "args": [{"property": "name"}]},
+                {"op": "=", "args": [{"property": "updated"}, {"date": "2024-01-01"}]},
+                {"op": "s_intersects", "args": [
+                    {"property": "geometry"},
+                    {"type": "Point", "coordinates": [7.0, 46.0]}
+                ]}
+            ]
+        });
+        let text = "pop_est > 1000000 AND name NOT LIKE 'A%' AND name IN ('Chad', 'Peru') \
+            AND pop_est BETWEEN 1 AND 2 AND name IS NULL AND updated = DATE('2024-01-01') \
+            AND S_INTERSECTS(geometry, POINT(7 46))";
+
+        assert_eq!(parse(&json).unwrap(), parse_text(text).unwrap());
+    }
+
+    #[test]
+    fn test_parse_bbox_literal() {
+        let json =
+            json!({"op": "s_within", "args": [{"property": "geometry"}, {"bbox": [0, 0, 1, 1]}]});
+        assert_eq!(
+            parse(&json).unwrap(),
+            parse_text("S_WITHIN(geometry, BBOX(0, 0, 1, 1))").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            parse(&json!({"op": "=", "args": [{"property": "a"}]})).unwrap_err(),
+            "operator '=' expects 2 arguments"
+        );
+        assert_eq!(
+            parse(&json!({"op": "t_before", "args": []})).unwrap_err(),
+            "unsupported operator 't_before'"
+        );
+        assert_eq!(
+            parse(&json!({"op": "in", "args": [{"property": "name"}, []]})).unwrap_err(),
+            "the second argument of 'in' must be a non-empty array"
+        );
+        assert_eq!(
+            parse(&json!("name")).unwrap_err(),
+            "expected a CQL2 expression but found \"name\""
+        );
+    }
+}
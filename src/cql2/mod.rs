@@ -0,0 +1,7 @@
+mod ast;
+mod json;
+mod text;
+
+pub use ast::{Expr, Scalar, SpatialOperand};
+pub use json::parse as parse_json;
+pub use text::parse as parse_text;
@@ -0,0 +1,771 @@
+/*
+ * A recursive descent parser for the CQL2 text encoding.
+ *
+ * The supported grammar covers the basic CQL2, advanced comparison operators and spatial
+ * functions conformance classes:
+ *
+ *   expression = term { OR term }
+ *   term       = factor { AND factor }
+ *   factor     = [ NOT ] primary
+ *   primary    = "(" expression ")" | spatial function | scalar predicate | TRUE | FALSE
+ *
+ * Geometry literals are written in WKT (plus the `BBOX(...)` shorthand) and are parsed into
+ * GeoJSON geometries, so that both CQL2 encodings share the same AST.
+ * https://docs.ogc.org/is/21-065r2/21-065r2.html#cql2-text
+ */
+use super::ast::{ComparisonOp, Expr, Scalar, SpatialOp, SpatialOperand};
+use geojson::{Geometry, Position, Value};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    QuotedIdent(String),
+    String(String),
+    Number(f64),
+    LParen,
+    RParen,
+    Comma,
+    Op(ComparisonOp),
+}
+
+const RESERVED_WORDS: &[&str] = &[
+    "AND", "OR", "NOT", "TRUE", "FALSE", "LIKE", "BETWEEN", "IN", "IS", "NULL",
+];
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Ident(ident) => format!("'{}'", ident),
+        Token::QuotedIdent(ident) => format!("'\"{}\"'", ident),
+        Token::String(s) => format!("string '{}'", s),
+        Token::Number(n) => format!("number {}", n),
+        Token::LParen => "'('".to_string(),
+        Token::RParen => "')'".to_string(),
+        Token::Comma => "','".to_string(),
+        Token::Op(op) => format!("'{}'", op.as_sql()),
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<(Token, usize)>, String> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        match c {
+            c if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '(' => {
+                tokens.push((Token::LParen, start));
+                i += 1;
+            }
+            ')' => {
+                tokens.push((Token::RParen, start));
+                i += 1;
+            }
+            ',' => {
+                tokens.push((Token::Comma, start));
+                i += 1;
+            }
+            '=' => {
+                tokens.push((Token::Op(ComparisonOp::Eq), start));
+                i += 1;
+            }
+            '<' | '>' => {
+                let next = chars.get(i + 1).copied();
+                let (op, len) = match (c, next) {
+                    ('<', Some('>')) => (ComparisonOp::NotEq, 2),
+                    ('<', Some('=')) => (ComparisonOp::LtEq, 2),
+                    ('>', Some('=')) => (ComparisonOp::GtEq, 2),
+                    ('<', _) => (ComparisonOp::Lt, 1),
+                    _ => (ComparisonOp::Gt, 1),
+                };
+                tokens.push((Token::Op(op), start));
+                i += len;
+            }
+            '\'' => {
+                let mut value = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        Some('\'') if chars.get(i + 1) == Some(&'\'') => {
+                            value.push('\'');
+                            i += 2;
+                        }
+                        Some('\'') => {
+                            i += 1;
+                            break;
+                        }
+                        Some(c) => {
+                            value.push(*c);
+                            i += 1;
+                        }
+                        None => {
+                            return Err(format!(
+                                "unterminated string starting at position {}",
+                                start
+                            ));
+                        }
+                    }
+                }
+                tokens.push((Token::String(value), start));
+            }
+            '"' => {
+                let end = chars[i + 1..]
+                    .iter()
+                    .position(|c| *c == '"')
+                    .ok_or_else(|| {
+                        format!("unterminated quoted identifier at position {}", start)
+                    })?;
+                let ident: String = chars[i + 1..i + 1 + end].iter().collect();
+                tokens.push((Token::QuotedIdent(ident), start));
+                i += end + 2;
+            }
+            c if c.is_ascii_digit()
+                || ((c == '-' || c == '+' || c == '.')
+                    && chars
+                        .get(i + 1)
+                        .is_some_and(|n| n.is_ascii_digit() || *n == '.')) =>
+            {
+                i += 1;
+                while let Some(n) = chars.get(i) {
+                    let is_exponent_sign = (*n == '-' || *n == '+')
+                        && matches!(chars.get(i - 1), Some('e') | Some('E'));
+                    if n.is_ascii_digit() || *n == '.' || *n == 'e' || *n == 'E' || is_exponent_sign
+                    {
+                        i += 1;
+                    } else {
+                        break;
+                    }
+                }
+                let literal: String = chars[start..i].iter().collect();
+                let number = literal
+                    .parse()
+                    .map_err(|_| format!("invalid number '{}' at position {}", literal, start))?;
+                tokens.push((Token::Number(number), start));
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                while chars
+                    .get(i)
+                    .is_some_and(|n| n.is_alphanumeric() || *n == '_' || *n == '.' || *n == ':')
+                {
+                    i += 1;
+                }
+                tokens.push((Token::Ident(chars[start..i].iter().collect()), start));
+            }
+            other => {
+                return Err(format!(
+                    "unexpected character '{}' at position {}",
+                    other, start
+                ));
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// The deepest nesting of parentheses and geometry collections a filter may have, which bounds
+/// the recursion of the parser.
+const MAX_DEPTH: usize = 64;
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    input_len: usize,
+    /// The current nesting of parentheses and geometry collections.
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(token, _)| token)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<&Token> {
+        self.tokens.get(self.pos + offset).map(|(token, _)| token)
+    }
+
+    fn position(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .map_or(self.input_len, |(_, position)| *position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).map(|(token, _)| token.clone());
+        self.pos += 1;
+        token
+    }
+
+    fn error(&self, expected: &str) -> String {
+        match self.peek() {
+            Some(token) => format!(
+                "expected {} but found {} at position {}",
+                expected,
+                describe(token),
+                self.position()
+            ),
+            None => format!("expected {} but reached the end of the filter", expected),
+        }
+    }
+
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Ident(ident)) if ident.eq_ignore_ascii_case(keyword))
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        if self.peek_keyword(keyword) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), String> {
+        if self.eat_keyword(keyword) {
+            Ok(())
+        } else {
+            Err(self.error(keyword))
+        }
+    }
+
+    /// Runs `parse` one nesting level deeper, failing past `MAX_DEPTH`.
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, String>,
+    ) -> Result<T, String> {
+        if self.depth == MAX_DEPTH {
+            return Err(format!(
+                "filter is nested more than {} levels deep at position {}",
+                MAX_DEPTH,
+                self.position()
+            ));
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), String> {
+        if self.peek() == Some(&expected) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(&describe(&expected)))
+        }
+    }
+
+    fn parse_expression(&mut self) -> Result<Expr, String> {
+        let mut terms = vec![self.parse_term()?];
+        while self.eat_keyword("OR") {
+            terms.push(self.parse_term()?);
+        }
+        Ok(if terms.len() == 1 {
+            terms.remove(0)
+        } else {
+            Expr::Or(terms)
+        })
+    }
+
+    fn parse_term(&mut self) -> Result<Expr, String> {
+        let mut factors = vec![self.parse_factor()?];
+        while self.eat_keyword("AND") {
+            factors.push(self.parse_factor()?);
+        }
+        Ok(if factors.len() == 1 {
+            factors.remove(0)
+        } else {
+            Expr::And(factors)
+        })
+    }
+
+    fn parse_factor(&mut self) -> Result<Expr, String> {
+        if self.eat_keyword("NOT") {
+            Ok(Expr::Not(Box::new(self.parse_primary()?)))
+        } else {
+            self.parse_primary()
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, String> {
+        if self.peek() == Some(&Token::LParen) {
+            return self.nested(|parser| {
+                parser.pos += 1;
+                let expr = parser.parse_expression()?;
+                parser.expect(Token::RParen)?;
+                Ok(expr)
+            });
+        }
+
+        if let Some(Token::Ident(name)) = self.peek()
+            && let Some(op) = SpatialOp::from_name(name)
+        {
+            self.pos += 1;
+            self.expect(Token::LParen)?;
+            let left = self.parse_spatial_operand()?;
+            self.expect(Token::Comma)?;
+            let right = self.parse_spatial_operand()?;
+            self.expect(Token::RParen)?;
+            return Ok(Expr::Spatial { op, left, right });
+        }
+
+        let value = self.parse_scalar()?;
+        self.parse_predicate(value)
+    }
+
+    fn parse_predicate(&mut self, value: Scalar) -> Result<Expr, String> {
+        if let Some(Token::Op(op)) = self.peek().cloned() {
+            self.pos += 1;
+            let right = self.parse_scalar()?;
+            return Ok(Expr::Comparison {
+                op,
+                left: value,
+                right,
+            });
+        }
+
+        if self.eat_keyword("IS") {
+            let negated = self.eat_keyword("NOT");
+            self.expect_keyword("NULL")?;
+            let expr = Expr::IsNull(value);
+            return Ok(if negated {
+                Expr::Not(Box::new(expr))
+            } else {
+                expr
+            });
+        }
+
+        let negated = self.peek_keyword("NOT")
+            && matches!(self.peek_at(1), Some(Token::Ident(ident))
+                if ["LIKE", "BETWEEN", "IN"].iter().any(|k| ident.eq_ignore_ascii_case(k)));
+        if negated {
+            self.pos += 1;
+        }
+
+        let expr = if self.eat_keyword("LIKE") {
+            Expr::Like {
+                value,
+                pattern: self.parse_scalar()?,
+            }
+        } else if self.eat_keyword("BETWEEN") {
+            let low = self.parse_scalar()?;
+            self.expect_keyword("AND")?;
+            let high = self.parse_scalar()?;
+            Expr::Between { value, low, high }
+        } else if self.eat_keyword("IN") {
+            self.expect(Token::LParen)?;
+            let mut list = vec![self.parse_scalar()?];
+            while self.peek() == Some(&Token::Comma) {
+                self.pos += 1;
+                list.push(self.parse_scalar()?);
+            }
+            self.expect(Token::RParen)?;
+            Expr::In { value, list }
+        } else if let Scalar::Bool(b) = value {
+            Expr::Bool(b)
+        } else {
+            return Err(self.error("a comparison operator, LIKE, BETWEEN, IN or IS"));
+        };
+
+        Ok(if negated {
+            Expr::Not(Box::new(expr))
+        } else {
+            expr
+        })
+    }
+
+    fn parse_scalar(&mut self) -> Result<Scalar, String> {
+        let position = self.position();
+        match self.next() {
+            Some(Token::String(s)) => Ok(Scalar::String(s)),
+            Some(Token::Number(n)) => Ok(Scalar::Number(n)),
+            Some(Token::QuotedIdent(ident)) => Ok(Scalar::Property(ident)),
+            Some(Token::Ident(ident)) => {
+                let upper = ident.to_ascii_uppercase();
+                match upper.as_str() {
+                    "TRUE" => Ok(Scalar::Bool(true)),
+                    "FALSE" => Ok(Scalar::Bool(false)),
+                    "DATE" | "TIMESTAMP" if self.peek() == Some(&Token::LParen) => {
+                        self.pos += 1;
+                        let position = self.position();
+                        let Some(Token::String(literal)) = self.next() else {
+                            return Err(format!(
+                                "expected a quoted {} literal at position {}",
+                                upper, position
+                            ));
+                        };
+                        self.expect(Token::RParen)?;
+                        Ok(if upper == "DATE" {
+                            Scalar::Date(literal)
+                        } else {
+                            Scalar::Timestamp(literal)
+                        })
+                    }
+                    _ if RESERVED_WORDS.contains(&upper.as_str()) => Err(format!(
+                        "expected a property or literal but found '{}' at position {}",
+                        ident, position
+                    )),
+                    _ => Ok(Scalar::Property(ident)),
+                }
+            }
+            Some(token) => Err(format!(
+                "expected a property or literal but found {} at position {}",
+                describe(&token),
+                position
+            )),
+            None => Err("expected a property or literal but reached the end of the filter".into()),
+        }
+    }
+
+    fn parse_spatial_operand(&mut self) -> Result<SpatialOperand, String> {
+        match self.peek().cloned() {
+            Some(Token::QuotedIdent(ident)) => {
+                self.pos += 1;
+                Ok(SpatialOperand::Property(ident))
+            }
+            Some(Token::Ident(ident)) => {
+                if ident.eq_ignore_ascii_case("BBOX") {
+                    self.pos += 1;
+                    Ok(SpatialOperand::Geometry(self.parse_bbox()?))
+                } else if let Some(geometry) = self.parse_wkt()? {
+                    Ok(SpatialOperand::Geometry(geometry))
+                } else {
+                    self.pos += 1;
+                    Ok(SpatialOperand::Property(ident))
+                }
+            }
+            _ => Err(self.error("a geometry property or literal")),
+        }
+    }
+
+    fn parse_bbox(&mut self) -> Result<Geometry, String> {
+        self.expect(Token::LParen)?;
+        let mut values = vec![self.parse_number()?];
+        while self.peek() == Some(&Token::Comma) {
+            self.pos += 1;
+            values.push(self.parse_number()?);
+        }
+        self.expect(Token::RParen)?;
+
+        let (min_x, min_y, max_x, max_y) = match values[..] {
+            [min_x, min_y, max_x, max_y] => (min_x, min_y, max_x, max_y),
+            [min_x, min_y, _, max_x, max_y, _] => (min_x, min_y, max_x, max_y),
+            _ => return Err("BBOX must have 4 or 6 components".to_string()),
+        };
+        Ok(Geometry::new(Value::Polygon(vec![vec![
+            vec![min_x, min_y],
+            vec![max_x, min_y],
+            vec![max_x, max_y],
+            vec![min_x, max_y],
+            vec![min_x, min_y],
+        ]])))
+    }
+
+    fn parse_number(&mut self) -> Result<f64, String> {
+        match self.peek() {
+            Some(Token::Number(n)) => {
+                let n = *n;
+                self.pos += 1;
+                Ok(n)
+            }
+            _ => Err(self.error("a number")),
+        }
+    }
+
+    /// Parses a WKT geometry if the next token is a geometry keyword.
+    fn parse_wkt(&mut self) -> Result<Option<Geometry>, String> {
+        let Some(Token::Ident(keyword)) = self.peek() else {
+            return Ok(None);
+        };
+        let keyword = keyword.to_ascii_uppercase();
+        let is_geometry_keyword = matches!(
+            keyword.as_str(),
+            "POINT"
+                | "LINESTRING"
+                | "POLYGON"
+                | "MULTIPOINT"
+                | "MULTILINESTRING"
+                | "MULTIPOLYGON"
+                | "GEOMETRYCOLLECTION"
+        );
+        let starts_wkt = match self.peek_at(1) {
+            Some(Token::LParen) => true,
+            Some(Token::Ident(ident)) => ident.eq_ignore_ascii_case("Z"),
+            _ => false,
+        };
+        if !is_geometry_keyword || !starts_wkt {
+            return Ok(None);
+        }
+        self.pos += 1;
+        self.eat_keyword("Z");
+
+        let value = match keyword.as_str() {
+            "POINT" => {
+                self.expect(Token::LParen)?;
+                let position = self.parse_position()?;
+                self.expect(Token::RParen)?;
+                Value::Point(position)
+            }
+            "LINESTRING" => Value::LineString(self.parse_positions()?),
+            "POLYGON" => Value::Polygon(self.parse_list(Self::parse_positions)?),
+            "MULTIPOINT" => Value::MultiPoint(self.parse_list(|parser| {
+                if parser.peek() == Some(&Token::LParen) {
+                    parser.pos += 1;
+                    let position = parser.parse_position()?;
+                    parser.expect(Token::RParen)?;
+                    Ok(position)
+                } else {
+                    parser.parse_position()
+                }
+            })?),
+            "MULTILINESTRING" => Value::MultiLineString(self.parse_list(Self::parse_positions)?),
+            "MULTIPOLYGON" => Value::MultiPolygon(
+                self.parse_list(|parser| parser.parse_list(Self::parse_positions))?,
+            ),
+            _ => Value::GeometryCollection(self.parse_list(|parser| {
+                parser
+                    .nested(Self::parse_wkt)?
+                    .ok_or_else(|| parser.error("a WKT geometry"))
+            })?),
+        };
+
+        Ok(Some(Geometry::new(value)))
+    }
+
+    fn parse_position(&mut self) -> Result<Position, String> {
+        let mut position = vec![self.parse_number()?, self.parse_number()?];
+        if let Some(Token::Number(z)) = self.peek() {
+            position.push(*z);
+            self.pos += 1;
+        }
+        Ok(position)
+    }
+
+    fn parse_positions(&mut self) -> Result<Vec<Position>, String> {
+        self.parse_list(Self::parse_position)
+    }
+
+    /// Parses a parenthesized, comma-separated list of items.
+    fn parse_list<T>(
+        &mut self,
+        mut parse_item: impl FnMut(&mut Self) -> Result<T, String>,
+    ) -> Result<Vec<T>, String> {
+        self.expect(Token::LParen)?;
+        let mut items = vec![parse_item(self)?];
+        while self.peek() == Some(&Token::Comma) {
+            self.pos += 1;
+            items.push(parse_item(self)?);
+        }
+        self.expect(Token::RParen)?;
+        Ok(items)
+    }
+}
+
+/// Parses a CQL2 text expression, e.g. `pop_est > 1000000 AND name LIKE 'A%'`.
+pub fn parse(input: &str) -> Result<Expr, String> {
+    let mut parser = Parser {
+        tokens: tokenize(input)?,
+        pos: 0,
+        input_len: input.chars().count(),
+        depth: 0,
+    };
+    if parser.peek().is_none() {
+        return Err("filter is empty".to_string());
+    }
+
+    let expr = parser.parse_expression()?;
+    if parser.peek().is_some() {
+        return Err(parser.error("the end of the filter"));
+    }
+    Ok(expr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn property(name: &str) -> Scalar {
+        Scalar::Property(name.to_string())
+    }
+
+    #[test]
+    fn test_parse_comparison() {
+        assert_eq!(
+            parse("pop_est >= 1000000").unwrap(),
+            Expr::Comparison {
+                op: ComparisonOp::GtEq,
+                left: property("pop_est"),
+                right: Scalar::Number(1000000.0),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_boolean_precedence() {
+        let expr = parse("a = 1 OR b = 'x' AND NOT (c <> -2.5)").unwrap();
+        assert_eq!(
+            expr,
+            Expr::Or(vec![
+                Expr::Comparison {
+                    op: ComparisonOp::Eq,
+                    left: property("a"),
+                    right: Scalar::Number(1.0),
+                },
+                Expr::And(vec![
+                    Expr::Comparison {
+                        op: ComparisonOp::Eq,
+                        left: property("b"),
+                        right: Scalar::String("x".to_string()),
+                    },
+                    Expr::Not(Box::new(Expr::Comparison {
+                        op: ComparisonOp::NotEq,
+                        left: property("c"),
+                        right: Scalar::Number(-2.5),
+                    })),
+                ]),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_parse_advanced_comparison_operators() {
+        assert_eq!(
+            parse("name NOT LIKE 'A%'").unwrap(),
+            Expr::Not(Box::new(Expr::Like {
+                value: property("name"),
+                pattern: Scalar::String("A%".to_string()),
+            }))
+        );
+        assert_eq!(
+            parse("pop_est BETWEEN 10 AND 20").unwrap(),
+            Expr::Between {
+                value: property("pop_est"),
+                low: Scalar::Number(10.0),
+                high: Scalar::Number(20.0),
+            }
+        );
+        assert_eq!(
+            parse("\"name\" in ('a', 'it''s')").unwrap(),
+            Expr::In {
+                value: property("name"),
+                list: vec![
+                    Scalar::String("a".to_string()),
+                    Scalar::String("it's".to_string())
+                ],
+            }
+        );
+        assert_eq!(
+            parse("name IS NOT NULL").unwrap(),
+            Expr::Not(Box::new(Expr::IsNull(property("name"))))
+        );
+    }
+
+    #[test]
+    fn test_parse_temporal_literals() {
+        assert_eq!(
+            parse("updated > TIMESTAMP('2024-01-01T00:00:00Z')").unwrap(),
+            Expr::Comparison {
+                op: ComparisonOp::Gt,
+                left: property("updated"),
+                right: Scalar::Timestamp("2024-01-01T00:00:00Z".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_spatial_function() {
+        let expr = parse("S_INTERSECTS(geometry, POLYGON((0 0, 1 0, 1 1, 0 0)))").unwrap();
+        assert_eq!(
+            expr,
+            Expr::Spatial {
+                op: SpatialOp::Intersects,
+                left: SpatialOperand::Property("geometry".to_string()),
+                right: SpatialOperand::Geometry(Geometry::new(Value::Polygon(vec![vec![
+                    vec![0.0, 0.0],
+                    vec![1.0, 0.0],
+                    vec![1.0, 1.0],
+                    vec![0.0, 0.0],
+                ]]))),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_wkt_variants() {
+        let geometry = |filter: &str| match parse(filter).unwrap() {
+            Expr::Spatial {
+                right: SpatialOperand::Geometry(geometry),
+                ..
+            } => geometry.value,
+            other => panic!("unexpected expression {:?}", other),
+        };
+
+        assert_eq!(
+            geometry("S_WITHIN(geom, POINT(7.5 46.9))"),
+            Value::Point(vec![7.5, 46.9])
+        );
+        assert_eq!(
+            geometry("S_WITHIN(geom, MULTIPOINT((1 2), (3 4)))"),
+            geometry("S_WITHIN(geom, MULTIPOINT(1 2, 3 4))")
+        );
+        assert_eq!(
+            geometry("S_INTERSECTS(geom, BBOX(0, 0, 1, 1))"),
+            Value::Polygon(vec![vec![
+                vec![0.0, 0.0],
+                vec![1.0, 0.0],
+                vec![1.0, 1.0],
+                vec![0.0, 1.0],
+                vec![0.0, 0.0],
+            ]])
+        );
+        assert!(matches!(
+            geometry("S_INTERSECTS(geom, GEOMETRYCOLLECTION(POINT(0 0), LINESTRING(0 0, 1 1)))"),
+            Value::GeometryCollection(geometries) if geometries.len() == 2
+        ));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(parse("").unwrap_err(), "filter is empty");
+        assert_eq!(
+            parse("name = ").unwrap_err(),
+            "expected a property or literal but reached the end of the filter"
+        );
+        assert_eq!(
+            parse("name 'x'").unwrap_err(),
+            "expected a comparison operator, LIKE, BETWEEN, IN or IS but found string 'x' at position 5"
+        );
+        assert_eq!(
+            parse("(a = 1").unwrap_err(),
+            "expected ')' but reached the end of the filter"
+        );
+        assert_eq!(
+            parse("name = 'x").unwrap_err(),
+            "unterminated string starting at position 7"
+        );
+    }
+
+    #[test]
+    fn test_parse_nesting_limit() {
+        let nested = |depth: usize| format!("{}a = 1{}", "(".repeat(depth), ")".repeat(depth));
+        assert!(parse(&nested(MAX_DEPTH)).is_ok());
+        assert_eq!(
+            parse(&nested(MAX_DEPTH + 1)).unwrap_err(),
+            "filter is nested more than 64 levels deep at position 64"
+        );
+        assert!(parse(&nested(50_000)).is_err());
+
+        let collection = format!(
+            "S_INTERSECTS(geom, {}POINT(0 0){})",
+            "GEOMETRYCOLLECTION(".repeat(50_000),
+            ")".repeat(50_000)
+        );
+        assert!(parse(&collection).is_err());
+    }
+}
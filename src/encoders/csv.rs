@@ -0,0 +1,365 @@
+use super::{
+    Encoder, Items, Resource, ResourceKind,
+    html::{format_id, format_value},
+};
+use crate::{
+    config::{AppConfig, CsvGeometryConfig},
+    error::AppError,
+    models::Link,
+};
+use axum::{
+    http::{HeaderValue, header},
+    response::{IntoResponse, Response},
+};
+use geojson::{Position, Value};
+use std::collections::HashMap;
+use utoipa::openapi::{ObjectBuilder, RefOr, Type, schema::Schema};
+
+/// CSV, with a header row, for feature collections only. The links of the page, which CSV has
+/// nowhere to put, are sent in the `Link` header.
+pub struct CsvEncoder {
+    geometries: HashMap<String, CsvGeometryConfig>,
+}
+
+impl CsvEncoder {
+    pub fn new(config: &AppConfig) -> Self {
+        Self {
+            geometries: config
+                .collections
+                .iter()
+                .map(|(id, collection)| (id.clone(), collection.csv_geometry.clone()))
+                .collect(),
+        }
+    }
+
+    fn write(&self, items: &Items<'_>) -> String {
+        let geometry = self
+            .geometries
+            .get(items.collection_id)
+            .cloned()
+            .unwrap_or_default();
+        let mut header = vec!["id"];
+        header.extend(
+            items
+                .properties
+                .iter()
+                .map(|property| property.name.as_str()),
+        );
+        match &geometry {
+            CsvGeometryConfig::Wkt { wkt } => header.push(wkt),
+            CsvGeometryConfig::Xy { x, y } => header.extend([x.as_str(), y.as_str()]),
+        }
+
+        let mut csv = String::new();
+        write_record(&mut csv, header);
+        for feature in &items.collection.features {
+            let mut record = vec![format_id(feature.id.as_ref())];
+            record.extend(items.properties.iter().map(|property| {
+                format_value(
+                    feature
+                        .properties
+                        .as_ref()
+                        .and_then(|properties| properties.get(&property.name)),
+                )
+            }));
+            let value = feature.geometry.as_ref().map(|geometry| &geometry.value);
+            match &geometry {
+                CsvGeometryConfig::Wkt { .. } => record.push(value.map(wkt).unwrap_or_default()),
+                CsvGeometryConfig::Xy { .. } => {
+                    let (x, y) = match value {
+                        Some(Value::Point(position)) => (
+                            position.first().map(f64::to_string),
+                            position.get(1).map(f64::to_string),
+                        ),
+                        _ => (None, None),
+                    };
+                    record.extend([x.unwrap_or_default(), y.unwrap_or_default()]);
+                }
+            }
+            write_record(&mut csv, record.iter().map(String::as_str));
+        }
+        csv
+    }
+}
+
+impl Encoder for CsvEncoder {
+    fn name(&self) -> &'static str {
+        "csv"
+    }
+
+    fn title(&self) -> &'static str {
+        "CSV"
+    }
+
+    fn media_types(&self, kind: ResourceKind) -> &'static [&'static str] {
+        match kind {
+            ResourceKind::Items => &["text/csv"],
+            _ => &[],
+        }
+    }
+
+    fn schema(&self) -> Option<RefOr<Schema>> {
+        Some(ObjectBuilder::new().schema_type(Type::String).into())
+    }
+
+    fn needs_schema(&self) -> bool {
+        true
+    }
+
+    fn encode(&self, resource: Resource<'_>, _url_base: &str) -> Result<Response, AppError> {
+        let Resource::Items(items) = resource else {
+            return Err(AppError::NotAcceptable(
+                "CSV is only available for feature collections".to_string(),
+            ));
+        };
+        let csv = self.write(&items);
+        let link = HeaderValue::from_str(&link_header(&items.collection.links))
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+        Ok((
+            [
+                (
+                    header::CONTENT_TYPE,
+                    HeaderValue::from_static("text/csv; charset=utf-8; header=present"),
+                ),
+                (header::LINK, link),
+            ],
+            csv,
+        )
+            .into_response())
+    }
+}
+
+/// Formats links as the value of a `Link` header (RFC 8288).
+fn link_header(links: &[Link]) -> String {
+    links
+        .iter()
+        .map(|link| {
+            let mut value = format!("<{}>; rel=\"{}\"", link.href, link.rel.as_str());
+            if let Some(type_) = &link.type_ {
+                value.push_str(&format!("; type=\"{}\"", type_));
+            }
+            if let Some(title) = &link.title {
+                value.push_str(&format!("; title=\"{}\"", title.replace('"', "'")));
+            }
+            value
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Appends a record, quoting the fields that contain separators, quotes or line breaks as in
+/// RFC 4180.
+fn write_record<'a>(csv: &mut String, fields: impl IntoIterator<Item = &'a str>) {
+    for (i, field) in fields.into_iter().enumerate() {
+        if i > 0 {
+            csv.push(',');
+        }
+        if field.contains([',', '"', '\n', '\r']) {
+            csv.push('"');
+            csv.push_str(&field.replace('"', "\"\""));
+            csv.push('"');
+        } else {
+            csv.push_str(field);
+        }
+    }
+    csv.push_str("\r\n");
+}
+
+/// Formats a geometry as WKT, with a `Z` when its positions have three coordinates.
+fn wkt(value: &Value) -> String {
+    fn position(position: &Position) -> String {
+        position
+            .iter()
+            .map(f64::to_string)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn list<T>(items: &[T], format: impl Fn(&T) -> String) -> String {
+        if items.is_empty() {
+            return "EMPTY".to_string();
+        }
+        let items: Vec<_> = items.iter().map(format).collect();
+        format!("({})", items.join(", "))
+    }
+
+    let line = |positions: &Vec<Position>| list(positions, position);
+    let polygon = |rings: &Vec<Vec<Position>>| list(rings, line);
+    let (tag, first, body) = match value {
+        Value::Point(p) => ("POINT", Some(p), list(std::slice::from_ref(p), position)),
+        Value::MultiPoint(points) => (
+            "MULTIPOINT",
+            points.first(),
+            list(points, |p| format!("({})", position(p))),
+        ),
+        Value::LineString(positions) => ("LINESTRING", positions.first(), line(positions)),
+        Value::MultiLineString(lines) => (
+            "MULTILINESTRING",
+            lines.iter().flatten().next(),
+            list(lines, line),
+        ),
+        Value::Polygon(rings) => ("POLYGON", rings.iter().flatten().next(), polygon(rings)),
+        Value::MultiPolygon(polygons) => (
+            "MULTIPOLYGON",
+            polygons.iter().flatten().flatten().next(),
+            list(polygons, polygon),
+        ),
+        Value::GeometryCollection(geometries) => (
+            "GEOMETRYCOLLECTION",
+            None,
+            list(geometries, |geometry| wkt(&geometry.value)),
+        ),
+    };
+    match first {
+        Some(first) if first.len() > 2 => format!("{} Z {}", tag, body),
+        _ => format!("{} {}", tag, body),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Crs, LinkRel, OgcApiFeatureCollection, QueryableProperty};
+    use serde_json::json;
+
+    fn feature(value: serde_json::Value) -> geojson::Feature {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn geometry(value: serde_json::Value) -> Value {
+        serde_json::from_value::<geojson::Geometry>(value)
+            .unwrap()
+            .value
+    }
+
+    #[test]
+    fn test_wkt() {
+        assert_eq!(
+            wkt(&geometry(
+                json!({"type": "Point", "coordinates": [7.5, 47]})
+            )),
+            "POINT (7.5 47)"
+        );
+        assert_eq!(
+            wkt(&geometry(
+                json!({"type": "MultiPoint", "coordinates": [[1, 2, 3], [4, 5, 6]]})
+            )),
+            "MULTIPOINT Z ((1 2 3), (4 5 6))"
+        );
+        assert_eq!(
+            wkt(&geometry(json!({"type": "MultiPolygon", "coordinates": [
+                [[[0, 0], [1, 0], [1, 1], [0, 0]]],
+                [[[2, 2], [3, 2], [3, 3], [2, 2]]]
+            ]}))),
+            "MULTIPOLYGON (((0 0, 1 0, 1 1, 0 0)), ((2 2, 3 2, 3 3, 2 2)))"
+        );
+        assert_eq!(
+            wkt(&geometry(
+                json!({"type": "GeometryCollection", "geometries": [
+                    {"type": "LineString", "coordinates": []},
+                    {"type": "Point", "coordinates": [1, 2]}
+                ]})
+            )),
+            "GEOMETRYCOLLECTION (LINESTRING EMPTY, POINT (1 2))"
+        );
+    }
+
+    fn encoder(csv_geometry: &str) -> CsvEncoder {
+        let config: AppConfig = toml::from_str(&format!(
+            r#"
+            title = "test"
+            description = "test"
+            url_base = "http://localhost:3000"
+
+            [collections.cities]
+            table = "cities"
+            id_column = "id"
+            geometry_column = "geom"
+            properties = ["pop", "name"]
+            {}
+            "#,
+            csv_geometry
+        ))
+        .unwrap();
+        CsvEncoder::new(&config)
+    }
+
+    fn write(encoder: &CsvEncoder) -> String {
+        let collection = OgcApiFeatureCollection::new(
+            vec![
+                feature(json!({
+                    "type": "Feature",
+                    "id": 1,
+                    "geometry": {"type": "Point", "coordinates": [7.44, 46.95]},
+                    "properties": {"name": "Bern, \"the capital\"", "pop": 134000}
+                })),
+                feature(json!({
+                    "type": "Feature",
+                    "id": 2,
+                    "geometry": null,
+                    "properties": {"name": "Nowhere", "pop": null}
+                })),
+            ],
+            2,
+            2,
+            vec![],
+            None,
+        );
+        let properties = [
+            QueryableProperty::new("pop", Some("integer"), None),
+            QueryableProperty::new("name", Some("string"), None),
+        ];
+        encoder.write(&Items {
+            collection_id: "cities",
+            crs: &Crs::crs84(),
+            collection: &collection,
+            properties: &properties,
+            crs_definition: None,
+        })
+    }
+
+    #[test]
+    fn test_write_wkt() {
+        assert_eq!(
+            write(&encoder("")),
+            "id,pop,name,geometry\r\n\
+             1,134000,\"Bern, \"\"the capital\"\"\",POINT (7.44 46.95)\r\n\
+             2,,Nowhere,\r\n"
+        );
+    }
+
+    #[test]
+    fn test_write_xy() {
+        assert_eq!(
+            write(&encoder(r#"csv_geometry = { x = "lon", y = "lat" }"#)),
+            "id,pop,name,lon,lat\r\n\
+             1,134000,\"Bern, \"\"the capital\"\"\",7.44,46.95\r\n\
+             2,,Nowhere,,\r\n"
+        );
+    }
+
+    #[test]
+    fn test_link_header() {
+        let links = [
+            Link {
+                href: "http://localhost:3000/collections/cities/items?f=csv".to_string(),
+                rel: LinkRel::Self_,
+                type_: Some("text/csv".to_string()),
+                title: Some("this document".to_string()),
+            },
+            Link {
+                href: "http://localhost:3000/collections/cities/items?limit=10&cursor=abc&f=csv"
+                    .to_string(),
+                rel: LinkRel::Next,
+                type_: None,
+                title: None,
+            },
+        ];
+        assert_eq!(
+            link_header(&links),
+            "<http://localhost:3000/collections/cities/items?f=csv>; rel=\"self\"; \
+             type=\"text/csv\"; title=\"this document\", \
+             <http://localhost:3000/collections/cities/items?limit=10&cursor=abc&f=csv>; rel=\"next\""
+        );
+    }
+}
@@ -0,0 +1,1092 @@
+/*
+ * FlatGeobuf encoding of feature collections.
+ *
+ * A FlatGeobuf file is the magic bytes, a size prefixed `Header` flatbuffer, an optional packed
+ * Hilbert R-tree and the size prefixed `Feature` flatbuffers. The tables are written with the
+ * low level flatbuffers builder, following the field order of the FlatGeobuf schemas
+ * (https://github.com/flatgeobuf/flatgeobuf/tree/master/src/fbs). Only the pages small enough
+ * to be indexed are buffered, larger ones are written as their features are read.
+ */
+use super::{Encoder, ItemsStream, Resource, ResourceKind};
+use crate::{
+    error::AppError,
+    models::{Crs, QueryableProperty},
+    storage::StreamedFeature,
+};
+use axum::{
+    body::{Body, Bytes},
+    http::header,
+    response::{IntoResponse, Response},
+};
+use flatbuffers::{FlatBufferBuilder, TableFinishedWIPOffset, VOffsetT, WIPOffset};
+use futures_util::{StreamExt, stream::BoxStream};
+use geojson::{Feature, PolygonType, Position, Value};
+use serde_json::Value as JsonValue;
+use utoipa::openapi::{ObjectBuilder, RefOr, Type, schema::Schema};
+
+const MAGIC_BYTES: [u8; 8] = [b'f', b'g', b'b', 3, b'f', b'g', b'b', 0];
+
+/// The number of children of the nodes of the R-tree.
+const INDEX_NODE_SIZE: u16 = 16;
+
+/// The byte size of an R-tree node: its bounding box and offset.
+const NODE_ITEM_SIZE: usize = 40;
+
+/// The offset of the field at `index` in a table's vtable.
+const fn field(index: VOffsetT) -> VOffsetT {
+    4 + 2 * index
+}
+
+mod header_fields {
+    use super::field;
+    use flatbuffers::VOffsetT;
+
+    pub const NAME: VOffsetT = field(0);
+    pub const ENVELOPE: VOffsetT = field(1);
+    pub const GEOMETRY_TYPE: VOffsetT = field(2);
+    pub const COLUMNS: VOffsetT = field(7);
+    pub const FEATURES_COUNT: VOffsetT = field(8);
+    pub const INDEX_NODE_SIZE: VOffsetT = field(9);
+    pub const CRS: VOffsetT = field(10);
+}
+
+mod crs_fields {
+    use super::field;
+    use flatbuffers::VOffsetT;
+
+    pub const ORG: VOffsetT = field(0);
+    pub const CODE: VOffsetT = field(1);
+    pub const WKT: VOffsetT = field(4);
+}
+
+mod column_fields {
+    use super::field;
+    use flatbuffers::VOffsetT;
+
+    pub const NAME: VOffsetT = field(0);
+    pub const TYPE: VOffsetT = field(1);
+}
+
+mod geometry_fields {
+    use super::field;
+    use flatbuffers::VOffsetT;
+
+    pub const ENDS: VOffsetT = field(0);
+    pub const XY: VOffsetT = field(1);
+    pub const TYPE: VOffsetT = field(6);
+    pub const PARTS: VOffsetT = field(7);
+}
+
+mod feature_fields {
+    use super::field;
+    use flatbuffers::VOffsetT;
+
+    pub const GEOMETRY: VOffsetT = field(0);
+    pub const PROPERTIES: VOffsetT = field(1);
+}
+
+/// The `GeometryType` enum of FlatGeobuf. The header declares `Unknown`, as a collection may mix
+/// geometry types, so every feature states the type of its geometry.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u8)]
+enum GeometryType {
+    Unknown = 0,
+    Point = 1,
+    LineString = 2,
+    Polygon = 3,
+    MultiPoint = 4,
+    MultiLineString = 5,
+    MultiPolygon = 6,
+    GeometryCollection = 7,
+}
+
+/// The subset of the `ColumnType` enum of FlatGeobuf the property types map to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u8)]
+enum ColumnType {
+    Bool = 2,
+    Long = 7,
+    Double = 10,
+    String = 11,
+    Json = 12,
+    DateTime = 13,
+}
+
+impl ColumnType {
+    /// Maps the JSON Schema type of a property to a column type, properties of unknown types
+    /// being written as strings.
+    fn from_property(property: &QueryableProperty) -> Self {
+        match (property.type_.as_deref(), property.format.as_deref()) {
+            (Some("integer"), _) => Self::Long,
+            (Some("number"), _) => Self::Double,
+            (Some("boolean"), _) => Self::Bool,
+            (Some("string"), Some("date-time")) => Self::DateTime,
+            (Some("object" | "array"), _) => Self::Json,
+            _ => Self::String,
+        }
+    }
+}
+
+struct Column {
+    name: String,
+    type_: ColumnType,
+}
+
+/// The layer of a file: its name, CRS and columns, declared by the header.
+struct Layer {
+    name: String,
+    srid: i32,
+    crs_definition: Option<String>,
+    columns: Vec<Column>,
+}
+
+impl Layer {
+    fn new(
+        name: &str,
+        crs: &Crs,
+        crs_definition: Option<&str>,
+        properties: &[QueryableProperty],
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            srid: crs.srid(),
+            crs_definition: crs_definition.map(str::to_string),
+            columns: properties
+                .iter()
+                .map(|property| Column {
+                    name: property.name.clone(),
+                    type_: ColumnType::from_property(property),
+                })
+                .collect(),
+        }
+    }
+
+    /// Writes the magic bytes and the header of a file without index, which the features
+    /// follow as they are read.
+    fn write_start(&self) -> Vec<u8> {
+        let mut bytes = MAGIC_BYTES.to_vec();
+        bytes.extend(write_header(self, None));
+        bytes
+    }
+
+    /// Writes a whole file, indexed unless there are more than `index_max_features` features or
+    /// none of them has a geometry.
+    fn write(&self, features: &[Feature], index_max_features: u64) -> Vec<u8> {
+        let count = features.len() as u64;
+        if count > 0
+            && count <= index_max_features
+            && let Some(bytes) = self.write_indexed(features)
+        {
+            return bytes;
+        }
+        let mut bytes = self.write_start();
+        for feature in features {
+            bytes.extend(write_feature(&self.columns, feature));
+        }
+        bytes
+    }
+
+    /// Writes a file with a packed Hilbert R-tree, `None` when no feature has a geometry.
+    fn write_indexed(&self, features: &[Feature]) -> Option<Vec<u8>> {
+        let bboxes: Vec<_> = features.iter().map(feature_bbox).collect();
+        let mut extent = NodeItem::empty(0);
+        for bbox in &bboxes {
+            extent.expand(bbox);
+        }
+        if extent.is_empty() {
+            return None;
+        }
+
+        // Features without geometry are indexed at the corner of the extent, as the tree needs a
+        // leaf per feature and an infinite box would spread to all its ancestors.
+        let corner = NodeItem {
+            max_x: extent.min_x,
+            max_y: extent.min_y,
+            ..extent
+        };
+        // The features are written in the order of the Hilbert curve through the centers of their
+        // bounding boxes, which the leaves of the tree follow.
+        let mut leaves: Vec<_> = bboxes
+            .into_iter()
+            .zip(features)
+            .map(|(bbox, feature)| {
+                let bbox = if bbox.is_empty() { corner } else { bbox };
+                (bbox, write_feature(&self.columns, feature))
+            })
+            .collect();
+        leaves.sort_by_cached_key(|(bbox, _)| std::cmp::Reverse(hilbert_value(bbox, &extent)));
+        let mut offset = 0;
+        for (bbox, feature) in leaves.iter_mut() {
+            bbox.offset = offset;
+            offset += feature.len() as u64;
+        }
+
+        let leaf_items: Vec<_> = leaves.iter().map(|(bbox, _)| *bbox).collect();
+        let mut bytes = MAGIC_BYTES.to_vec();
+        bytes.extend(write_header(self, Some((&extent, features.len() as u64))));
+        let nodes = build_tree(&leaf_items, INDEX_NODE_SIZE);
+        bytes.reserve(nodes.len() * NODE_ITEM_SIZE + offset as usize);
+        for node in nodes {
+            node.write(&mut bytes);
+        }
+        for (_, feature) in leaves {
+            bytes.extend(feature);
+        }
+        Some(bytes)
+    }
+}
+
+/// A bounding box of the R-tree, along with the offset of the feature it bounds for the leaves,
+/// or of the first child node otherwise.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct NodeItem {
+    min_x: f64,
+    min_y: f64,
+    max_x: f64,
+    max_y: f64,
+    offset: u64,
+}
+
+impl NodeItem {
+    fn empty(offset: u64) -> Self {
+        Self {
+            min_x: f64::INFINITY,
+            min_y: f64::INFINITY,
+            max_x: f64::NEG_INFINITY,
+            max_y: f64::NEG_INFINITY,
+            offset,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.min_x > self.max_x
+    }
+
+    fn expand(&mut self, other: &NodeItem) {
+        self.min_x = self.min_x.min(other.min_x);
+        self.min_y = self.min_y.min(other.min_y);
+        self.max_x = self.max_x.max(other.max_x);
+        self.max_y = self.max_y.max(other.max_y);
+    }
+
+    fn expand_position(&mut self, position: &[f64]) {
+        if let [x, y, ..] = position {
+            self.min_x = self.min_x.min(*x);
+            self.min_y = self.min_y.min(*y);
+            self.max_x = self.max_x.max(*x);
+            self.max_y = self.max_y.max(*y);
+        }
+    }
+
+    fn write(&self, bytes: &mut Vec<u8>) {
+        for value in [self.min_x, self.min_y, self.max_x, self.max_y] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.extend_from_slice(&self.offset.to_le_bytes());
+    }
+}
+
+/// FlatGeobuf, for feature collections only.
+pub struct FlatgeobufEncoder {
+    /// The largest number of features a spatial index is built for. Building it needs all the
+    /// features of the response at once.
+    pub index_max_features: u64,
+}
+
+impl Encoder for FlatgeobufEncoder {
+    fn name(&self) -> &'static str {
+        "fgb"
+    }
+
+    fn title(&self) -> &'static str {
+        "FlatGeobuf"
+    }
+
+    fn media_types(&self, kind: ResourceKind) -> &'static [&'static str] {
+        match kind {
+            ResourceKind::Items => &["application/flatgeobuf"],
+            _ => &[],
+        }
+    }
+
+    fn schema(&self) -> Option<RefOr<Schema>> {
+        Some(
+            ObjectBuilder::new()
+                .schema_type(Type::String)
+                .format(Some(utoipa::openapi::SchemaFormat::KnownFormat(
+                    utoipa::openapi::KnownFormat::Binary,
+                )))
+                .into(),
+        )
+    }
+
+    fn needs_schema(&self) -> bool {
+        true
+    }
+
+    fn streams_items(&self) -> bool {
+        true
+    }
+
+    fn encode_stream(&self, items: ItemsStream) -> Result<Response, AppError> {
+        let writer = FileWriter {
+            layer: Layer::new(
+                &items.collection_id,
+                &items.crs,
+                items.crs_definition.as_deref(),
+                &items.properties,
+            ),
+            features: Some(items.features),
+            index_max_features: self.index_max_features,
+            started: false,
+        };
+        let body = Body::from_stream(futures_util::stream::try_unfold(writer, FileWriter::next));
+        Ok(([(header::CONTENT_TYPE, "application/flatgeobuf")], body).into_response())
+    }
+
+    fn encode(&self, resource: Resource<'_>, _url_base: &str) -> Result<Response, AppError> {
+        let Resource::Items(items) = resource else {
+            return Err(AppError::NotAcceptable(
+                "FlatGeobuf is only available for feature collections".to_string(),
+            ));
+        };
+        let layer = Layer::new(
+            items.collection_id,
+            items.crs,
+            items.crs_definition,
+            items.properties,
+        );
+        let body = layer.write(&items.collection.features, self.index_max_features);
+        Ok(([(header::CONTENT_TYPE, "application/flatgeobuf")], body).into_response())
+    }
+}
+
+/// Writes a file while its features are read. Up to `index_max_features` features are buffered
+/// first: when the stream ends by then, the file is written at once with an index, otherwise the
+/// header and the buffered features are sent and the rest follows as it is read.
+struct FileWriter {
+    layer: Layer,
+    /// Taken once the features are all read.
+    features: Option<BoxStream<'static, Result<StreamedFeature, AppError>>>,
+    index_max_features: u64,
+    started: bool,
+}
+
+impl FileWriter {
+    async fn next(mut self) -> Result<Option<(Bytes, Self)>, std::io::Error> {
+        let Some(features) = self.features.as_mut() else {
+            return Ok(None);
+        };
+
+        if !self.started {
+            self.started = true;
+            let mut buffered = Vec::new();
+            while buffered.len() as u64 <= self.index_max_features {
+                match features.next().await.transpose().map_err(read_error)? {
+                    Some(streamed) => buffered.push(streamed.feature),
+                    None => {
+                        self.features = None;
+                        let bytes = self.layer.write(&buffered, self.index_max_features);
+                        return Ok(Some((bytes.into(), self)));
+                    }
+                }
+            }
+            let mut bytes = self.layer.write_start();
+            for feature in &buffered {
+                bytes.extend(write_feature(&self.layer.columns, feature));
+            }
+            return Ok(Some((bytes.into(), self)));
+        }
+
+        match features.next().await.transpose().map_err(read_error)? {
+            Some(streamed) => {
+                let bytes = write_feature(&self.layer.columns, &streamed.feature);
+                Ok(Some((bytes.into(), self)))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+/// The status is already sent when the features fail to be read, so the response can only be
+/// cut short.
+fn read_error(error: AppError) -> std::io::Error {
+    tracing::error!("Failed to read the features of a page: {}", error);
+    std::io::Error::other(error)
+}
+
+/// Writes the size prefixed header, declaring the extent and the number of features when the
+/// features are indexed.
+fn write_header(layer: &Layer, index: Option<(&NodeItem, u64)>) -> Vec<u8> {
+    let mut fbb = FlatBufferBuilder::new();
+    let name = fbb.create_string(&layer.name);
+    let columns: Vec<_> = layer
+        .columns
+        .iter()
+        .map(|column| {
+            let name = fbb.create_string(&column.name);
+            let start = fbb.start_table();
+            fbb.push_slot_always(column_fields::NAME, name);
+            fbb.push_slot(column_fields::TYPE, column.type_ as u8, 0);
+            fbb.end_table(start)
+        })
+        .collect();
+    let columns = fbb.create_vector(&columns);
+    let org = fbb.create_string("EPSG");
+    let wkt = layer
+        .crs_definition
+        .as_deref()
+        .map(|wkt| fbb.create_string(wkt));
+    let crs = {
+        let start = fbb.start_table();
+        fbb.push_slot_always(crs_fields::ORG, org);
+        fbb.push_slot(crs_fields::CODE, layer.srid, 0);
+        if let Some(wkt) = wkt {
+            fbb.push_slot_always(crs_fields::WKT, wkt);
+        }
+        fbb.end_table(start)
+    };
+    let envelope = index
+        .filter(|(extent, _)| !extent.is_empty())
+        .map(|(extent, _)| {
+            fbb.create_vector(&[extent.min_x, extent.min_y, extent.max_x, extent.max_y])
+        });
+
+    let start = fbb.start_table();
+    fbb.push_slot_always(header_fields::NAME, name);
+    if let Some(envelope) = envelope {
+        fbb.push_slot_always(header_fields::ENVELOPE, envelope);
+    }
+    fbb.push_slot(header_fields::GEOMETRY_TYPE, GeometryType::Unknown as u8, 0);
+    fbb.push_slot_always(header_fields::COLUMNS, columns);
+    match index {
+        Some((_, count)) => {
+            fbb.push_slot(header_fields::FEATURES_COUNT, count, 0);
+            fbb.push_slot(header_fields::INDEX_NODE_SIZE, INDEX_NODE_SIZE, 16);
+        }
+        None => fbb.push_slot(header_fields::INDEX_NODE_SIZE, 0u16, 16),
+    }
+    fbb.push_slot_always(header_fields::CRS, crs);
+    let header = fbb.end_table(start);
+    fbb.finish_size_prefixed(header, None);
+    fbb.finished_data().to_vec()
+}
+
+/// Writes a size prefixed feature, with the properties of the columns only.
+fn write_feature(columns: &[Column], feature: &Feature) -> Vec<u8> {
+    let mut fbb = FlatBufferBuilder::new();
+    let geometry = feature
+        .geometry
+        .as_ref()
+        .map(|geometry| write_geometry(&mut fbb, &geometry.value));
+    let properties = feature
+        .properties
+        .as_ref()
+        .map(|properties| encode_properties(columns, properties))
+        .filter(|properties| !properties.is_empty())
+        .map(|properties| fbb.create_vector(&properties));
+
+    let start = fbb.start_table();
+    if let Some(geometry) = geometry {
+        fbb.push_slot_always(feature_fields::GEOMETRY, geometry);
+    }
+    if let Some(properties) = properties {
+        fbb.push_slot_always(feature_fields::PROPERTIES, properties);
+    }
+    let feature = fbb.end_table(start);
+    fbb.finish_size_prefixed(feature, None);
+    fbb.finished_data().to_vec()
+}
+
+/// Encodes the properties as the index of their column followed by their value, leaving out
+/// null values and values that do not fit the type of their column.
+fn encode_properties(
+    columns: &[Column],
+    properties: &serde_json::Map<String, JsonValue>,
+) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for (index, column) in columns.iter().enumerate() {
+        let Some(value) = properties
+            .get(&column.name)
+            .filter(|value| !value.is_null())
+        else {
+            continue;
+        };
+        let encoded = match (column.type_, value) {
+            (ColumnType::Bool, JsonValue::Bool(value)) => vec![*value as u8],
+            (ColumnType::Long, value) => match value.as_i64() {
+                Some(value) => value.to_le_bytes().to_vec(),
+                None => continue,
+            },
+            (ColumnType::Double, value) => match value.as_f64() {
+                Some(value) => value.to_le_bytes().to_vec(),
+                None => continue,
+            },
+            (ColumnType::String | ColumnType::DateTime, JsonValue::String(value)) => {
+                length_prefixed(value)
+            }
+            (ColumnType::Bool, _) => continue,
+            (_, value) => length_prefixed(&value.to_string()),
+        };
+        bytes.extend_from_slice(&(index as u16).to_le_bytes());
+        bytes.extend(encoded);
+    }
+    bytes
+}
+
+fn length_prefixed(value: &str) -> Vec<u8> {
+    let mut bytes = (value.len() as u32).to_le_bytes().to_vec();
+    bytes.extend_from_slice(value.as_bytes());
+    bytes
+}
+
+/// Writes a geometry table, keeping the first two coordinates of the positions. Multi-part
+/// geometries keep the coordinates of all their parts together, with the index after the last
+/// position of each part in `ends`, except multi-polygons and collections whose parts are
+/// geometry tables of their own.
+fn write_geometry<'a>(
+    fbb: &mut FlatBufferBuilder<'a>,
+    value: &Value,
+) -> WIPOffset<TableFinishedWIPOffset> {
+    let (type_, lines, parts) = match value {
+        Value::Point(position) => (
+            GeometryType::Point,
+            vec![std::slice::from_ref(position)],
+            None,
+        ),
+        Value::MultiPoint(positions) => (GeometryType::MultiPoint, vec![&positions[..]], None),
+        Value::LineString(positions) => (GeometryType::LineString, vec![&positions[..]], None),
+        Value::MultiLineString(lines) => (
+            GeometryType::MultiLineString,
+            lines.iter().map(|line| &line[..]).collect(),
+            None,
+        ),
+        Value::Polygon(rings) => (
+            GeometryType::Polygon,
+            rings.iter().map(|ring| &ring[..]).collect(),
+            None,
+        ),
+        Value::MultiPolygon(polygons) => {
+            let parts: Vec<_> = polygons
+                .iter()
+                .map(|polygon| write_polygon(fbb, polygon))
+                .collect();
+            (GeometryType::MultiPolygon, Vec::new(), Some(parts))
+        }
+        Value::GeometryCollection(geometries) => {
+            let parts: Vec<_> = geometries
+                .iter()
+                .map(|geometry| write_geometry(fbb, &geometry.value))
+                .collect();
+            (GeometryType::GeometryCollection, Vec::new(), Some(parts))
+        }
+    };
+    write_geometry_table(fbb, type_, &lines, parts)
+}
+
+fn write_polygon<'a>(
+    fbb: &mut FlatBufferBuilder<'a>,
+    rings: &PolygonType,
+) -> WIPOffset<TableFinishedWIPOffset> {
+    let rings: Vec<_> = rings.iter().map(|ring| &ring[..]).collect();
+    write_geometry_table(fbb, GeometryType::Polygon, &rings, None)
+}
+
+fn write_geometry_table<'a>(
+    fbb: &mut FlatBufferBuilder<'a>,
+    type_: GeometryType,
+    lines: &[&[Position]],
+    parts: Option<Vec<WIPOffset<TableFinishedWIPOffset>>>,
+) -> WIPOffset<TableFinishedWIPOffset> {
+    let mut xy = Vec::new();
+    let mut ends = Vec::new();
+    for line in lines {
+        for position in line.iter() {
+            if let [x, y, ..] = position[..] {
+                xy.extend([x, y]);
+            }
+        }
+        ends.push((xy.len() / 2) as u32);
+    }
+    let ends = (ends.len() > 1).then(|| fbb.create_vector(&ends));
+    let xy = (!xy.is_empty()).then(|| fbb.create_vector(&xy));
+    let parts = parts.map(|parts| fbb.create_vector(&parts));
+
+    let start = fbb.start_table();
+    if let Some(ends) = ends {
+        fbb.push_slot_always(geometry_fields::ENDS, ends);
+    }
+    if let Some(xy) = xy {
+        fbb.push_slot_always(geometry_fields::XY, xy);
+    }
+    fbb.push_slot(geometry_fields::TYPE, type_ as u8, 0);
+    if let Some(parts) = parts {
+        fbb.push_slot_always(geometry_fields::PARTS, parts);
+    }
+    fbb.end_table(start)
+}
+
+/// The bounding box of the geometry of a feature, empty without geometry.
+fn feature_bbox(feature: &Feature) -> NodeItem {
+    fn expand(bbox: &mut NodeItem, value: &Value) {
+        match value {
+            Value::Point(position) => bbox.expand_position(position),
+            Value::MultiPoint(positions) | Value::LineString(positions) => {
+                positions.iter().for_each(|p| bbox.expand_position(p))
+            }
+            Value::MultiLineString(lines) | Value::Polygon(lines) => {
+                lines.iter().flatten().for_each(|p| bbox.expand_position(p))
+            }
+            Value::MultiPolygon(polygons) => polygons
+                .iter()
+                .flatten()
+                .flatten()
+                .for_each(|p| bbox.expand_position(p)),
+            Value::GeometryCollection(geometries) => geometries
+                .iter()
+                .for_each(|geometry| expand(bbox, &geometry.value)),
+        }
+    }
+
+    let mut bbox = NodeItem::empty(0);
+    if let Some(geometry) = &feature.geometry {
+        expand(&mut bbox, &geometry.value);
+    }
+    bbox
+}
+
+/// The position along a Hilbert curve of the center of a bounding box within the extent.
+fn hilbert_value(bbox: &NodeItem, extent: &NodeItem) -> u32 {
+    const HILBERT_MAX: f64 = ((1 << 16) - 1) as f64;
+    let scale = |value: f64, min: f64, max: f64| {
+        let width = max - min;
+        if width > 0.0 {
+            // NaN, from empty boxes, saturates to 0.
+            (HILBERT_MAX * (value - min) / width) as u32
+        } else {
+            0
+        }
+    };
+    let x = scale((bbox.min_x + bbox.max_x) / 2.0, extent.min_x, extent.max_x);
+    let y = scale((bbox.min_y + bbox.max_y) / 2.0, extent.min_y, extent.max_y);
+    hilbert(x, y)
+}
+
+/// Maps a point of a 2^16 x 2^16 grid to its position along a Hilbert curve, with the
+/// branchless algorithm used by FlatGeobuf (https://github.com/rawrunprotected/hilbert_curves).
+fn hilbert(x: u32, y: u32) -> u32 {
+    let mut a = x ^ y;
+    let mut b = 0xFFFF ^ a;
+    let mut c = 0xFFFF ^ (x | y);
+    let mut d = x & (y ^ 0xFFFF);
+
+    let mut a1 = a | (b >> 1);
+    let mut b1 = (a >> 1) ^ a;
+    let mut c1 = ((c >> 1) ^ (b & (d >> 1))) ^ c;
+    let mut d1 = ((a & (c >> 1)) ^ (d >> 1)) ^ d;
+
+    a = a1;
+    b = b1;
+    c = c1;
+    d = d1;
+    a1 = (a & (a >> 2)) ^ (b & (b >> 2));
+    b1 = (a & (b >> 2)) ^ (b & ((a ^ b) >> 2));
+    c1 ^= (a & (c >> 2)) ^ (b & (d >> 2));
+    d1 ^= (b & (c >> 2)) ^ ((a ^ b) & (d >> 2));
+
+    a = a1;
+    b = b1;
+    c = c1;
+    d = d1;
+    a1 = (a & (a >> 4)) ^ (b & (b >> 4));
+    b1 = (a & (b >> 4)) ^ (b & ((a ^ b) >> 4));
+    c1 ^= (a & (c >> 4)) ^ (b & (d >> 4));
+    d1 ^= (b & (c >> 4)) ^ ((a ^ b) & (d >> 4));
+
+    a = a1;
+    b = b1;
+    c = c1;
+    d = d1;
+    c1 ^= (a & (c >> 8)) ^ (b & (d >> 8));
+    d1 ^= (b & (c >> 8)) ^ ((a ^ b) & (d >> 8));
+
+    a = c1 ^ (c1 >> 1);
+    b = d1 ^ (d1 >> 1);
+
+    let mut i0 = x ^ y;
+    let mut i1 = b | (0xFFFF ^ (i0 | a));
+
+    i0 = (i0 | (i0 << 8)) & 0x00FF00FF;
+    i0 = (i0 | (i0 << 4)) & 0x0F0F0F0F;
+    i0 = (i0 | (i0 << 2)) & 0x33333333;
+    i0 = (i0 | (i0 << 1)) & 0x55555555;
+
+    i1 = (i1 | (i1 << 8)) & 0x00FF00FF;
+    i1 = (i1 | (i1 << 4)) & 0x0F0F0F0F;
+    i1 = (i1 | (i1 << 2)) & 0x33333333;
+    i1 = (i1 | (i1 << 1)) & 0x55555555;
+
+    (i1 << 1) | i0
+}
+
+/// The ranges of the nodes of each level of a packed R-tree within its nodes, from the leaves up
+/// to the root. The nodes are stored from the root down, the leaves last.
+fn level_bounds(num_items: usize, node_size: u16) -> Vec<std::ops::Range<usize>> {
+    let node_size = node_size as usize;
+    let mut level_num_nodes = vec![num_items];
+    let mut n = num_items;
+    let mut num_nodes = n;
+    loop {
+        n = n.div_ceil(node_size);
+        num_nodes += n;
+        level_num_nodes.push(n);
+        if n == 1 {
+            break;
+        }
+    }
+
+    let mut end = num_nodes;
+    level_num_nodes
+        .into_iter()
+        .map(|size| {
+            let start = end - size;
+            let bounds = start..end;
+            end = start;
+            bounds
+        })
+        .collect()
+}
+
+/// Builds the nodes of a packed R-tree over the leaves, in storage order. Parent nodes bound
+/// their children and point to the first of them.
+fn build_tree(leaves: &[NodeItem], node_size: u16) -> Vec<NodeItem> {
+    let level_bounds = level_bounds(leaves.len(), node_size);
+    let mut nodes = vec![NodeItem::empty(0); level_bounds[0].end];
+    nodes[level_bounds[0].clone()].copy_from_slice(leaves);
+
+    for levels in level_bounds.windows(2) {
+        let (children, parents) = (&levels[0], &levels[1]);
+        for (parent, first_child) in parents
+            .clone()
+            .zip(children.clone().step_by(node_size as usize))
+        {
+            let last_child = (first_child + node_size as usize).min(children.end);
+            let mut node = NodeItem::empty(first_child as u64);
+            for child in &nodes[first_child..last_child] {
+                node.expand(child);
+            }
+            nodes[parent] = node;
+        }
+    }
+    nodes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::to_bytes;
+    use flatbuffers::{ForwardsUOffset, Table, Vector};
+    use serde_json::json;
+
+    fn feature(value: JsonValue) -> Feature {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn properties() -> Vec<QueryableProperty> {
+        vec![
+            QueryableProperty::new("name", Some("string"), None),
+            QueryableProperty::new("pop_est", Some("integer"), None),
+            QueryableProperty::new("area", Some("number"), None),
+        ]
+    }
+
+    fn layer() -> Layer {
+        Layer::new(
+            "countries",
+            &Crs::crs84(),
+            Some("GEOGCS[\"WGS 84\"]"),
+            &properties(),
+        )
+    }
+
+    fn encode(features: Vec<Feature>, index_max_features: u64) -> Vec<u8> {
+        layer().write(&features, index_max_features)
+    }
+
+    async fn encode_stream(features: Vec<Feature>, index_max_features: u64) -> Vec<u8> {
+        let features = features.into_iter().map(|feature| {
+            Ok(StreamedFeature {
+                feature,
+                next_cursor: None,
+            })
+        });
+        let response = FlatgeobufEncoder { index_max_features }
+            .encode_stream(ItemsStream {
+                collection_id: "countries".to_string(),
+                crs: Crs::crs84(),
+                properties: properties(),
+                crs_definition: Some("GEOGCS[\"WGS 84\"]".to_string()),
+                number_matched: 0,
+                bbox: None,
+                features: futures_util::stream::iter(features).boxed(),
+                links: Box::new(|_| Vec::new()),
+            })
+            .unwrap();
+        to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap()
+            .to_vec()
+    }
+
+    /// Reads the size prefixed table starting at `offset`, returning it and the offset after it.
+    fn read_table(bytes: &[u8], offset: usize) -> (Table<'_>, usize) {
+        let size = u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap()) as usize;
+        let buffer = &bytes[offset + 4..offset + 4 + size];
+        let root = u32::from_le_bytes(buffer[..4].try_into().unwrap()) as usize;
+        (unsafe { Table::new(buffer, root) }, offset + 4 + size)
+    }
+
+    fn countries() -> Vec<Feature> {
+        vec![
+            feature(json!({
+                "type": "Feature",
+                "geometry": {"type": "Point", "coordinates": [10.0, 50.0]},
+                "properties": {"name": "A", "pop_est": 12, "area": null}
+            })),
+            feature(json!({
+                "type": "Feature",
+                "geometry": {"type": "MultiPolygon", "coordinates": [
+                    [[[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 0.0]]],
+                    [[[2.0, 2.0], [3.0, 2.0], [3.0, 3.0], [2.0, 2.0]],
+                     [[2.2, 2.2], [2.8, 2.2], [2.8, 2.8], [2.2, 2.2]]]
+                ]},
+                "properties": {"name": "B", "pop_est": 3, "area": 1.5}
+            })),
+        ]
+    }
+
+    #[test]
+    fn test_write_header_and_features() {
+        let bytes = encode(countries(), 0);
+        assert_eq!(bytes[..8], MAGIC_BYTES);
+
+        let (header, mut offset) = read_table(&bytes, 8);
+        unsafe {
+            assert_eq!(
+                header.get::<ForwardsUOffset<&str>>(header_fields::NAME, None),
+                Some("countries")
+            );
+            assert_eq!(
+                header.get::<u16>(header_fields::INDEX_NODE_SIZE, Some(16)),
+                Some(0)
+            );
+            let columns = header
+                .get::<ForwardsUOffset<Vector<ForwardsUOffset<Table>>>>(
+                    header_fields::COLUMNS,
+                    None,
+                )
+                .unwrap();
+            let columns: Vec<_> = columns
+                .iter()
+                .map(|column| {
+                    (
+                        column
+                            .get::<ForwardsUOffset<&str>>(column_fields::NAME, None)
+                            .unwrap(),
+                        column.get::<u8>(column_fields::TYPE, Some(0)).unwrap(),
+                    )
+                })
+                .collect();
+            assert_eq!(columns, [("name", 11), ("pop_est", 7), ("area", 10)]);
+            let crs = header
+                .get::<ForwardsUOffset<Table>>(header_fields::CRS, None)
+                .unwrap();
+            assert_eq!(crs.get::<i32>(crs_fields::CODE, Some(0)), Some(4326));
+            assert_eq!(
+                crs.get::<ForwardsUOffset<&str>>(crs_fields::WKT, None),
+                Some("GEOGCS[\"WGS 84\"]")
+            );
+        }
+
+        let (point, next) = read_table(&bytes, offset);
+        offset = next;
+        unsafe {
+            let geometry = point
+                .get::<ForwardsUOffset<Table>>(feature_fields::GEOMETRY, None)
+                .unwrap();
+            assert_eq!(geometry.get::<u8>(geometry_fields::TYPE, Some(0)), Some(1));
+            let xy = geometry
+                .get::<ForwardsUOffset<Vector<f64>>>(geometry_fields::XY, None)
+                .unwrap();
+            assert_eq!(xy.iter().collect::<Vec<_>>(), [10.0, 50.0]);
+            let properties = point
+                .get::<ForwardsUOffset<Vector<u8>>>(feature_fields::PROPERTIES, None)
+                .unwrap();
+            let mut expected = vec![0, 0, 1, 0, 0, 0, b'A', 1, 0];
+            expected.extend(12i64.to_le_bytes());
+            assert_eq!(properties.bytes(), expected);
+        }
+
+        let (multipolygon, next) = read_table(&bytes, offset);
+        assert_eq!(next, bytes.len());
+        unsafe {
+            let geometry = multipolygon
+                .get::<ForwardsUOffset<Table>>(feature_fields::GEOMETRY, None)
+                .unwrap();
+            assert_eq!(geometry.get::<u8>(geometry_fields::TYPE, Some(0)), Some(6));
+            let parts = geometry
+                .get::<ForwardsUOffset<Vector<ForwardsUOffset<Table>>>>(
+                    geometry_fields::PARTS,
+                    None,
+                )
+                .unwrap();
+            assert_eq!(parts.len(), 2);
+            let ends = parts
+                .get(1)
+                .get::<ForwardsUOffset<Vector<u32>>>(geometry_fields::ENDS, None)
+                .unwrap();
+            assert_eq!(ends.iter().collect::<Vec<_>>(), [4, 8]);
+        }
+    }
+
+    #[test]
+    fn test_write_index() {
+        let bytes = encode(countries(), 10);
+        let (header, offset) = read_table(&bytes, 8);
+        unsafe {
+            assert_eq!(
+                header.get::<u64>(header_fields::FEATURES_COUNT, Some(0)),
+                Some(2)
+            );
+            assert_eq!(
+                header.get::<u16>(header_fields::INDEX_NODE_SIZE, Some(16)),
+                Some(16)
+            );
+            let envelope = header
+                .get::<ForwardsUOffset<Vector<f64>>>(header_fields::ENVELOPE, None)
+                .unwrap();
+            assert_eq!(envelope.iter().collect::<Vec<_>>(), [0.0, 0.0, 10.0, 50.0]);
+        }
+
+        // A root and two leaves, pointing to the features that follow.
+        let features = offset + 3 * NODE_ITEM_SIZE;
+        let node = |index: usize| {
+            let start = offset + index * NODE_ITEM_SIZE;
+            let values: Vec<_> = (0..4)
+                .map(|i| {
+                    f64::from_le_bytes(bytes[start + i * 8..start + i * 8 + 8].try_into().unwrap())
+                })
+                .collect();
+            let offset = u64::from_le_bytes(bytes[start + 32..start + 40].try_into().unwrap());
+            (values, offset as usize)
+        };
+        assert_eq!(node(0), (vec![0.0, 0.0, 10.0, 50.0], 1));
+        for leaf in [1, 2] {
+            let (bbox, feature_offset) = node(leaf);
+            let (feature, _) = read_table(&bytes, features + feature_offset);
+            let geometry = unsafe {
+                feature
+                    .get::<ForwardsUOffset<Table>>(feature_fields::GEOMETRY, None)
+                    .unwrap()
+            };
+            let type_ = unsafe { geometry.get::<u8>(geometry_fields::TYPE, Some(0)) };
+            let expected = if type_ == Some(1) {
+                vec![10.0, 50.0, 10.0, 50.0]
+            } else {
+                vec![0.0, 0.0, 3.0, 3.0]
+            };
+            assert_eq!(bbox, expected);
+        }
+    }
+
+    #[test]
+    fn test_write_index_without_geometry() {
+        let mut features = countries();
+        features[0].geometry = None;
+        let bytes = encode(features, 10);
+        let (_, offset) = read_table(&bytes, 8);
+        for index in 0..3 {
+            let start = offset + index * NODE_ITEM_SIZE;
+            let values: Vec<_> = (0..4)
+                .map(|i| {
+                    f64::from_le_bytes(bytes[start + i * 8..start + i * 8 + 8].try_into().unwrap())
+                })
+                .collect();
+            assert!(values.iter().all(|value| value.is_finite()));
+            assert!(values.iter().all(|value| (0.0..=3.0).contains(value)));
+        }
+
+        let mut features = countries();
+        features
+            .iter_mut()
+            .for_each(|feature| feature.geometry = None);
+        let bytes = encode(features, 10);
+        let (header, _) = read_table(&bytes, 8);
+        assert_eq!(
+            unsafe { header.get::<u16>(header_fields::INDEX_NODE_SIZE, Some(16)) },
+            Some(0)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_encode_stream() {
+        assert_eq!(
+            encode_stream(countries(), 10).await,
+            encode(countries(), 10)
+        );
+        // Past the largest indexed size, the features follow the header as they are read.
+        assert_eq!(encode_stream(countries(), 1).await, encode(countries(), 0));
+        assert_eq!(encode_stream(Vec::new(), 10).await, encode(Vec::new(), 10));
+    }
+
+    #[test]
+    fn test_level_bounds() {
+        assert_eq!(level_bounds(1, 16), [1..2, 0..1]);
+        assert_eq!(level_bounds(20, 16), [3..23, 1..3, 0..1]);
+        assert_eq!(level_bounds(300, 16), [22..322, 3..22, 1..3, 0..1]);
+    }
+
+    #[test]
+    fn test_build_tree() {
+        let leaves: Vec<_> = (0..20)
+            .map(|i| NodeItem {
+                min_x: i as f64,
+                min_y: 0.0,
+                max_x: i as f64 + 1.0,
+                max_y: 1.0,
+                offset: i,
+            })
+            .collect();
+        let nodes = build_tree(&leaves, 16);
+        assert_eq!(nodes.len(), 23);
+        assert_eq!(
+            (nodes[0].min_x, nodes[0].max_x, nodes[0].offset),
+            (0.0, 20.0, 1)
+        );
+        assert_eq!(
+            (nodes[1].min_x, nodes[1].max_x, nodes[1].offset),
+            (0.0, 16.0, 3)
+        );
+        assert_eq!(
+            (nodes[2].min_x, nodes[2].max_x, nodes[2].offset),
+            (16.0, 20.0, 19)
+        );
+        assert_eq!(nodes[3..], leaves[..]);
+    }
+
+    #[test]
+    fn test_hilbert() {
+        // The curve visits every cell of a corner of the grid in turn, moving to a neighbour
+        // at each step.
+        let mut cells: Vec<_> = (0..4)
+            .flat_map(|x| (0..4).map(move |y| (hilbert(x, y), x, y)))
+            .collect();
+        cells.sort();
+        assert_eq!(cells[0], (0, 0, 0));
+        for (i, step) in cells.windows(2).enumerate() {
+            let ((_, x0, y0), (value, x1, y1)) = (step[0], step[1]);
+            assert_eq!(value, i as u32 + 1);
+            assert_eq!(x0.abs_diff(x1) + y0.abs_diff(y1), 1);
+        }
+    }
+}
@@ -0,0 +1,793 @@
+/*
+ * GeoPackage encoding of feature collections.
+ *
+ * The features are written into a GeoPackage 1.4 (http://www.geopackage.org/spec140/) in a
+ * temporary file: the SRS, contents and geometry columns tables, a feature table named after the
+ * collection with a column per property, and an R-tree spatial index. The file is then streamed
+ * back and removed once sent.
+ */
+use super::{Encoder, Items, Resource, ResourceKind};
+use crate::{error::AppError, models::QueryableProperty};
+use axum::{
+    body::Body,
+    http::{HeaderValue, header},
+    response::{IntoResponse, Response},
+};
+use geojson::{Feature, Value, feature::Id};
+use rusqlite::{Connection, params, types::Value as SqlValue};
+use serde_json::Value as JsonValue;
+use std::collections::HashSet;
+use std::io::{Seek, SeekFrom};
+use tokio_util::io::ReaderStream;
+use utoipa::openapi::{KnownFormat, ObjectBuilder, RefOr, SchemaFormat, Type, schema::Schema};
+
+/// "GPKG" as a big endian integer.
+const APPLICATION_ID: i32 = 0x47504B47;
+const USER_VERSION: i32 = 10400;
+const GEOMETRY_COLUMN: &str = "geom";
+
+const WGS84_DEFINITION: &str = r#"GEOGCS["WGS 84",DATUM["WGS_1984",SPHEROID["WGS 84",6378137,298.257223563,AUTHORITY["EPSG","7030"]],AUTHORITY["EPSG","6326"]],PRIMEM["Greenwich",0,AUTHORITY["EPSG","8901"]],UNIT["degree",0.0174532925199433,AUTHORITY["EPSG","9122"]],AXIS["Latitude",NORTH],AXIS["Longitude",EAST],AUTHORITY["EPSG","4326"]]"#;
+
+/// The tables every GeoPackage with features has, along with the SRS entries it must hold.
+const SCHEMA_SQL: &str = r#"
+CREATE TABLE gpkg_spatial_ref_sys (
+    srs_name TEXT NOT NULL,
+    srs_id INTEGER PRIMARY KEY,
+    organization TEXT NOT NULL,
+    organization_coordsys_id INTEGER NOT NULL,
+    definition TEXT NOT NULL,
+    description TEXT
+);
+CREATE TABLE gpkg_contents (
+    table_name TEXT NOT NULL PRIMARY KEY,
+    data_type TEXT NOT NULL,
+    identifier TEXT UNIQUE,
+    description TEXT DEFAULT '',
+    last_change DATETIME NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    min_x DOUBLE,
+    min_y DOUBLE,
+    max_x DOUBLE,
+    max_y DOUBLE,
+    srs_id INTEGER,
+    CONSTRAINT fk_gc_r_srs_id FOREIGN KEY (srs_id) REFERENCES gpkg_spatial_ref_sys(srs_id)
+);
+CREATE TABLE gpkg_geometry_columns (
+    table_name TEXT NOT NULL,
+    column_name TEXT NOT NULL,
+    geometry_type_name TEXT NOT NULL,
+    srs_id INTEGER NOT NULL,
+    z TINYINT NOT NULL,
+    m TINYINT NOT NULL,
+    CONSTRAINT pk_geom_cols PRIMARY KEY (table_name, column_name),
+    CONSTRAINT uk_gc_table_name UNIQUE (table_name),
+    CONSTRAINT fk_gc_tn FOREIGN KEY (table_name) REFERENCES gpkg_contents(table_name),
+    CONSTRAINT fk_gc_srs FOREIGN KEY (srs_id) REFERENCES gpkg_spatial_ref_sys (srs_id)
+);
+CREATE TABLE gpkg_extensions (
+    table_name TEXT,
+    column_name TEXT,
+    extension_name TEXT NOT NULL,
+    definition TEXT NOT NULL,
+    scope TEXT NOT NULL,
+    CONSTRAINT ge_tce UNIQUE (table_name, column_name, extension_name)
+);
+INSERT INTO gpkg_spatial_ref_sys VALUES
+    ('Undefined cartesian SRS', -1, 'NONE', -1, 'undefined', 'undefined cartesian coordinate reference system'),
+    ('Undefined geographic SRS', 0, 'NONE', 0, 'undefined', 'undefined geographic coordinate reference system');
+"#;
+
+/// The triggers keeping the R-tree of a feature table up to date, from the RTree Spatial Indexes
+/// extension, with `{t}` the table, `{c}` the geometry column and `{i}` the primary key.
+const RTREE_TRIGGERS_SQL: &str = r#"
+CREATE TRIGGER "rtree_{t}_{c}_insert" AFTER INSERT ON "{t}"
+WHEN (new."{c}" NOT NULL AND NOT ST_IsEmpty(NEW."{c}"))
+BEGIN
+    INSERT OR REPLACE INTO "rtree_{t}_{c}" VALUES (
+        NEW."{i}", ST_MinX(NEW."{c}"), ST_MaxX(NEW."{c}"), ST_MinY(NEW."{c}"), ST_MaxY(NEW."{c}")
+    );
+END;
+CREATE TRIGGER "rtree_{t}_{c}_update1" AFTER UPDATE OF "{c}" ON "{t}"
+WHEN OLD."{i}" = NEW."{i}" AND (NEW."{c}" NOTNULL AND NOT ST_IsEmpty(NEW."{c}"))
+BEGIN
+    INSERT OR REPLACE INTO "rtree_{t}_{c}" VALUES (
+        NEW."{i}", ST_MinX(NEW."{c}"), ST_MaxX(NEW."{c}"), ST_MinY(NEW."{c}"), ST_MaxY(NEW."{c}")
+    );
+END;
+CREATE TRIGGER "rtree_{t}_{c}_update2" AFTER UPDATE OF "{c}" ON "{t}"
+WHEN OLD."{i}" = NEW."{i}" AND (NEW."{c}" ISNULL OR ST_IsEmpty(NEW."{c}"))
+BEGIN
+    DELETE FROM "rtree_{t}_{c}" WHERE id = OLD."{i}";
+END;
+CREATE TRIGGER "rtree_{t}_{c}_update3" AFTER UPDATE ON "{t}"
+WHEN OLD."{i}" != NEW."{i}" AND (NEW."{c}" NOTNULL AND NOT ST_IsEmpty(NEW."{c}"))
+BEGIN
+    DELETE FROM "rtree_{t}_{c}" WHERE id = OLD."{i}";
+    INSERT OR REPLACE INTO "rtree_{t}_{c}" VALUES (
+        NEW."{i}", ST_MinX(NEW."{c}"), ST_MaxX(NEW."{c}"), ST_MinY(NEW."{c}"), ST_MaxY(NEW."{c}")
+    );
+END;
+CREATE TRIGGER "rtree_{t}_{c}_update4" AFTER UPDATE ON "{t}"
+WHEN OLD."{i}" != NEW."{i}" AND (NEW."{c}" ISNULL OR ST_IsEmpty(NEW."{c}"))
+BEGIN
+    DELETE FROM "rtree_{t}_{c}" WHERE id IN (OLD."{i}", NEW."{i}");
+END;
+CREATE TRIGGER "rtree_{t}_{c}_delete" AFTER DELETE ON "{t}"
+WHEN old."{c}" NOT NULL
+BEGIN
+    DELETE FROM "rtree_{t}_{c}" WHERE id = OLD."{i}";
+END;
+"#;
+
+/// GeoPackage, for feature collections only. Unlike the other encodings, it holds all the
+/// features matching a request rather than a page of them.
+pub struct GeopackageEncoder {
+    /// The largest number of features of a GeoPackage, bounding the size of the temporary files.
+    pub max_features: u64,
+}
+
+impl Encoder for GeopackageEncoder {
+    fn name(&self) -> &'static str {
+        "gpkg"
+    }
+
+    fn title(&self) -> &'static str {
+        "GeoPackage"
+    }
+
+    fn media_types(&self, kind: ResourceKind) -> &'static [&'static str] {
+        match kind {
+            ResourceKind::Items => &["application/geopackage+sqlite3"],
+            _ => &[],
+        }
+    }
+
+    fn schema(&self) -> Option<RefOr<Schema>> {
+        Some(
+            ObjectBuilder::new()
+                .schema_type(Type::String)
+                .format(Some(SchemaFormat::KnownFormat(KnownFormat::Binary)))
+                .into(),
+        )
+    }
+
+    fn needs_schema(&self) -> bool {
+        true
+    }
+
+    fn download_limit(&self) -> Option<u64> {
+        Some(self.max_features)
+    }
+
+    fn blocks(&self) -> bool {
+        true
+    }
+
+    fn encode(&self, resource: Resource<'_>, _url_base: &str) -> Result<Response, AppError> {
+        let Resource::Items(items) = resource else {
+            return Err(AppError::NotAcceptable(
+                "GeoPackage is only available for feature collections".to_string(),
+            ));
+        };
+        let internal_error = |e: &dyn std::fmt::Display| {
+            AppError::Internal(format!("Failed to write the GeoPackage: {}", e))
+        };
+
+        let temp_file = tempfile::Builder::new()
+            .suffix(".gpkg")
+            .tempfile()
+            .map_err(|e| internal_error(&e))?;
+        write(temp_file.path(), &items).map_err(|e| internal_error(&e))?;
+        // The file stays readable through its handle once its path is removed.
+        let (mut file, path) = temp_file.into_parts();
+        file.seek(SeekFrom::Start(0))
+            .map_err(|e| internal_error(&e))?;
+        drop(path);
+
+        let stream = ReaderStream::new(tokio::fs::File::from_std(file));
+        let disposition = HeaderValue::from_str(&format!(
+            "attachment; filename=\"{}.gpkg\"",
+            items.collection_id.replace('"', "")
+        ))
+        .map_err(|e| internal_error(&e))?;
+        Ok((
+            [
+                (
+                    header::CONTENT_TYPE,
+                    HeaderValue::from_static("application/geopackage+sqlite3"),
+                ),
+                (header::CONTENT_DISPOSITION, disposition),
+            ],
+            Body::from_stream(stream),
+        )
+            .into_response())
+    }
+}
+
+/// Quotes an SQLite identifier.
+fn quote(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+/// Maps the JSON Schema type of a property to a GeoPackage column type.
+fn column_type(property: &QueryableProperty) -> &'static str {
+    match (property.type_.as_deref(), property.format.as_deref()) {
+        (Some("integer"), _) => "INTEGER",
+        (Some("number"), _) => "DOUBLE",
+        (Some("boolean"), _) => "BOOLEAN",
+        (Some("string"), Some("date")) => "DATE",
+        (Some("string"), Some("date-time")) => "DATETIME",
+        _ => "TEXT",
+    }
+}
+
+/// The names of the columns of the properties. Properties named like a generated column are
+/// suffixed with underscores, comparing names case-insensitively as SQLite does.
+fn property_columns(properties: &[QueryableProperty], generated: &[&str]) -> Vec<String> {
+    let mut taken: HashSet<String> = generated
+        .iter()
+        .map(|name| name.to_lowercase())
+        .chain(
+            properties
+                .iter()
+                .map(|property| property.name.to_lowercase()),
+        )
+        .collect();
+    properties
+        .iter()
+        .map(|property| {
+            let mut name = property.name.clone();
+            if generated
+                .iter()
+                .any(|generated| generated.eq_ignore_ascii_case(&name))
+            {
+                while taken.contains(&name.to_lowercase()) {
+                    name.push('_');
+                }
+                taken.insert(name.to_lowercase());
+            }
+            name
+        })
+        .collect()
+}
+
+fn column_value(value: Option<&JsonValue>) -> SqlValue {
+    match value {
+        None | Some(JsonValue::Null) => SqlValue::Null,
+        Some(JsonValue::Bool(value)) => SqlValue::Integer(*value as i64),
+        Some(JsonValue::Number(number)) => match number.as_i64() {
+            Some(value) => SqlValue::Integer(value),
+            None => number.as_f64().map_or(SqlValue::Null, SqlValue::Real),
+        },
+        Some(JsonValue::String(value)) => SqlValue::Text(value.clone()),
+        Some(value) => SqlValue::Text(value.to_string()),
+    }
+}
+
+/// Writes the features into a new GeoPackage at `path`.
+fn write(path: &std::path::Path, items: &Items<'_>) -> rusqlite::Result<()> {
+    let mut connection = Connection::open(path)?;
+    connection.pragma_update(None, "application_id", APPLICATION_ID)?;
+    connection.pragma_update(None, "user_version", USER_VERSION)?;
+    let transaction = connection.transaction()?;
+    transaction.execute_batch(SCHEMA_SQL)?;
+
+    let srs_id = items.crs.srid();
+    transaction.execute(
+        "INSERT INTO gpkg_spatial_ref_sys VALUES ('WGS 84 geodetic', 4326, 'EPSG', 4326, ?1, \
+         'longitude/latitude coordinates in decimal degrees on the WGS 84 spheroid')",
+        params![WGS84_DEFINITION],
+    )?;
+    if srs_id != 4326 {
+        transaction.execute(
+            "INSERT INTO gpkg_spatial_ref_sys VALUES (?1, ?2, 'EPSG', ?2, ?3, NULL)",
+            params![
+                format!("EPSG:{}", srs_id),
+                srs_id,
+                items.crs_definition.unwrap_or("undefined")
+            ],
+        )?;
+    }
+
+    // Integer feature IDs are kept as the primary key, others in a column of their own.
+    let features = &items.collection.features;
+    let integer_ids = features
+        .iter()
+        .all(|feature| matches!(&feature.id, Some(Id::Number(id)) if id.is_i64()));
+    let table = items.collection_id;
+    let generated: &[&str] = if integer_ids {
+        &["fid", GEOMETRY_COLUMN]
+    } else {
+        &["fid", GEOMETRY_COLUMN, "id"]
+    };
+    let property_columns = property_columns(items.properties, generated);
+    let mut columns = vec![
+        "fid INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL".to_string(),
+        format!("{} GEOMETRY", quote(GEOMETRY_COLUMN)),
+    ];
+    if !integer_ids {
+        columns.push("id TEXT".to_string());
+    }
+    columns.extend(
+        items
+            .properties
+            .iter()
+            .zip(&property_columns)
+            .map(|(property, column)| format!("{} {}", quote(column), column_type(property))),
+    );
+    transaction.execute_batch(&format!(
+        "CREATE TABLE {} ({});\n\
+         CREATE VIRTUAL TABLE {} USING rtree(id, minx, maxx, miny, maxy);",
+        quote(table),
+        columns.join(", "),
+        quote(&format!("rtree_{}_{}", table, GEOMETRY_COLUMN)),
+    ))?;
+
+    let mut names = vec!["fid".to_string(), quote(GEOMETRY_COLUMN)];
+    if !integer_ids {
+        names.push("id".to_string());
+    }
+    names.extend(property_columns.iter().map(|column| quote(column)));
+    let placeholders: Vec<_> = (1..=names.len()).map(|i| format!("?{}", i)).collect();
+    let mut extent: Option<Envelope> = None;
+    let mut has_z = false;
+    {
+        let mut insert = transaction.prepare(&format!(
+            "INSERT INTO {} ({}) VALUES ({})",
+            quote(table),
+            names.join(", "),
+            placeholders.join(", ")
+        ))?;
+        let mut insert_rtree = transaction.prepare(&format!(
+            "INSERT INTO {} VALUES (?1, ?2, ?3, ?4, ?5)",
+            quote(&format!("rtree_{}_{}", table, GEOMETRY_COLUMN)),
+        ))?;
+        for feature in features {
+            let geometry = feature
+                .geometry
+                .as_ref()
+                .map(|geometry| GeometryBlob::new(&geometry.value, srs_id, items.crs.is_lat_lon()));
+            let mut values = vec![
+                match (&feature.id, integer_ids) {
+                    (Some(Id::Number(id)), true) => {
+                        id.as_i64().map_or(SqlValue::Null, SqlValue::Integer)
+                    }
+                    _ => SqlValue::Null,
+                },
+                geometry.as_ref().map_or(SqlValue::Null, |geometry| {
+                    SqlValue::Blob(geometry.bytes.clone())
+                }),
+            ];
+            if !integer_ids {
+                values.push(feature_id(feature));
+            }
+            values.extend(items.properties.iter().map(|property| {
+                column_value(
+                    feature
+                        .properties
+                        .as_ref()
+                        .and_then(|properties| properties.get(&property.name)),
+                )
+            }));
+            insert.execute(rusqlite::params_from_iter(values))?;
+
+            if let Some(GeometryBlob {
+                envelope: Some(envelope),
+                z,
+                ..
+            }) = geometry
+            {
+                let fid = transaction.last_insert_rowid();
+                insert_rtree.execute(params![
+                    fid,
+                    envelope.min_x,
+                    envelope.max_x,
+                    envelope.min_y,
+                    envelope.max_y
+                ])?;
+                has_z |= z;
+                extent = Some(match extent {
+                    Some(extent) => extent.union(&envelope),
+                    None => envelope,
+                });
+            }
+        }
+    }
+
+    transaction.execute(
+        "INSERT INTO gpkg_contents (table_name, data_type, identifier, min_x, min_y, max_x, max_y, srs_id) \
+         VALUES (?1, 'features', ?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            table,
+            extent.as_ref().map(|extent| extent.min_x),
+            extent.as_ref().map(|extent| extent.min_y),
+            extent.as_ref().map(|extent| extent.max_x),
+            extent.as_ref().map(|extent| extent.max_y),
+            srs_id
+        ],
+    )?;
+    transaction.execute(
+        "INSERT INTO gpkg_geometry_columns VALUES (?1, ?2, 'GEOMETRY', ?3, ?4, 0)",
+        params![table, GEOMETRY_COLUMN, srs_id, if has_z { 2 } else { 0 }],
+    )?;
+    transaction.execute(
+        "INSERT INTO gpkg_extensions VALUES (?1, ?2, 'gpkg_rtree_index', \
+         'http://www.geopackage.org/spec120/#extension_rtree', 'write-only')",
+        params![table, GEOMETRY_COLUMN],
+    )?;
+    // The triggers use functions provided by the GeoPackage clients, so they are only created
+    // once the features and their index are written.
+    transaction.execute_batch(
+        &RTREE_TRIGGERS_SQL
+            .replace("{t}", &table.replace('"', "\"\""))
+            .replace("{c}", GEOMETRY_COLUMN)
+            .replace("{i}", "fid"),
+    )?;
+    transaction.commit()
+}
+
+fn feature_id(feature: &Feature) -> SqlValue {
+    match &feature.id {
+        Some(Id::String(id)) => SqlValue::Text(id.clone()),
+        Some(Id::Number(id)) => SqlValue::Text(id.to_string()),
+        None => SqlValue::Null,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Envelope {
+    min_x: f64,
+    max_x: f64,
+    min_y: f64,
+    max_y: f64,
+}
+
+impl Envelope {
+    fn union(&self, other: &Envelope) -> Envelope {
+        Envelope {
+            min_x: self.min_x.min(other.min_x),
+            max_x: self.max_x.max(other.max_x),
+            min_y: self.min_y.min(other.min_y),
+            max_y: self.max_y.max(other.max_y),
+        }
+    }
+}
+
+/// A geometry in the GeoPackage binary format: a header with the SRS and envelope of the
+/// geometry, followed by its ISO WKB.
+struct GeometryBlob {
+    bytes: Vec<u8>,
+    /// The envelope, `None` for empty geometries.
+    envelope: Option<Envelope>,
+    z: bool,
+}
+
+impl GeometryBlob {
+    /// Encodes a geometry, swapping the axes of latitude/longitude CRSs since GeoPackages always
+    /// store the longitude first.
+    fn new(value: &Value, srs_id: i32, swap_axes: bool) -> Self {
+        let mut wkb = WkbWriter {
+            bytes: Vec::new(),
+            envelope: None,
+            z: first_position(value).is_some_and(|position| position.len() > 2),
+            swap_axes,
+        };
+        wkb.write(value);
+
+        let mut bytes = vec![b'G', b'P', 0];
+        // Little endian, with an [minx, maxx, miny, maxy] envelope or flagged as empty.
+        let flags = match wkb.envelope {
+            Some(_) => 0b0000_0011,
+            None => 0b0001_0001,
+        };
+        bytes.push(flags);
+        bytes.extend_from_slice(&srs_id.to_le_bytes());
+        if let Some(envelope) = &wkb.envelope {
+            for value in [
+                envelope.min_x,
+                envelope.max_x,
+                envelope.min_y,
+                envelope.max_y,
+            ] {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        bytes.extend(wkb.bytes);
+        Self {
+            bytes,
+            envelope: wkb.envelope,
+            z: wkb.z,
+        }
+    }
+}
+
+fn first_position(value: &Value) -> Option<&Vec<f64>> {
+    match value {
+        Value::Point(position) => Some(position),
+        Value::MultiPoint(positions) | Value::LineString(positions) => positions.first(),
+        Value::MultiLineString(lines) | Value::Polygon(lines) => lines.iter().flatten().next(),
+        Value::MultiPolygon(polygons) => polygons.iter().flatten().flatten().next(),
+        Value::GeometryCollection(geometries) => geometries
+            .iter()
+            .find_map(|geometry| first_position(&geometry.value)),
+    }
+}
+
+/// Writes little endian ISO WKB, with all positions in the dimension of the first one.
+struct WkbWriter {
+    bytes: Vec<u8>,
+    envelope: Option<Envelope>,
+    z: bool,
+    swap_axes: bool,
+}
+
+impl WkbWriter {
+    fn header(&mut self, geometry_type: u32) {
+        self.bytes.push(1);
+        let geometry_type = if self.z {
+            geometry_type + 1000
+        } else {
+            geometry_type
+        };
+        self.bytes.extend_from_slice(&geometry_type.to_le_bytes());
+    }
+
+    fn count(&mut self, count: usize) {
+        self.bytes.extend_from_slice(&(count as u32).to_le_bytes());
+    }
+
+    fn position(&mut self, position: &[f64]) {
+        let (mut x, mut y) = (
+            position.first().copied().unwrap_or(f64::NAN),
+            position.get(1).copied().unwrap_or(f64::NAN),
+        );
+        if self.swap_axes {
+            (x, y) = (y, x);
+        }
+        let point = Envelope {
+            min_x: x,
+            max_x: x,
+            min_y: y,
+            max_y: y,
+        };
+        self.envelope = Some(match self.envelope {
+            Some(envelope) => envelope.union(&point),
+            None => point,
+        });
+        self.bytes.extend_from_slice(&x.to_le_bytes());
+        self.bytes.extend_from_slice(&y.to_le_bytes());
+        if self.z {
+            let z = position.get(2).copied().unwrap_or(0.0);
+            self.bytes.extend_from_slice(&z.to_le_bytes());
+        }
+    }
+
+    fn positions(&mut self, positions: &[Vec<f64>]) {
+        self.count(positions.len());
+        for position in positions {
+            self.position(position);
+        }
+    }
+
+    fn rings(&mut self, rings: &[Vec<Vec<f64>>]) {
+        self.count(rings.len());
+        for ring in rings {
+            self.positions(ring);
+        }
+    }
+
+    fn write(&mut self, value: &Value) {
+        match value {
+            Value::Point(position) => {
+                self.header(1);
+                self.position(position);
+            }
+            Value::LineString(positions) => {
+                self.header(2);
+                self.positions(positions);
+            }
+            Value::Polygon(rings) => {
+                self.header(3);
+                self.rings(rings);
+            }
+            Value::MultiPoint(positions) => {
+                self.header(4);
+                self.count(positions.len());
+                for position in positions {
+                    self.header(1);
+                    self.position(position);
+                }
+            }
+            Value::MultiLineString(lines) => {
+                self.header(5);
+                self.count(lines.len());
+                for line in lines {
+                    self.header(2);
+                    self.positions(line);
+                }
+            }
+            Value::MultiPolygon(polygons) => {
+                self.header(6);
+                self.count(polygons.len());
+                for polygon in polygons {
+                    self.header(3);
+                    self.rings(polygon);
+                }
+            }
+            Value::GeometryCollection(geometries) => {
+                self.header(7);
+                self.count(geometries.len());
+                for geometry in geometries {
+                    self.write(&geometry.value);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Crs, OgcApiFeatureCollection};
+    use serde_json::json;
+
+    fn feature(value: JsonValue) -> Feature {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn write_collection(
+        features: Vec<Feature>,
+        crs: &Crs,
+    ) -> (tempfile::NamedTempFile, Connection) {
+        let collection = OgcApiFeatureCollection::new(features, 2, 2, vec![], None);
+        let properties = [
+            QueryableProperty::new("name", Some("string"), None),
+            QueryableProperty::new("pop_est", Some("integer"), None),
+        ];
+        let file = tempfile::NamedTempFile::new().unwrap();
+        write(
+            file.path(),
+            &Items {
+                collection_id: "countries",
+                crs,
+                collection: &collection,
+                properties: &properties,
+                crs_definition: Some("PROJCS[\"CH1903+ / LV95\"]"),
+            },
+        )
+        .unwrap();
+        let connection = Connection::open(file.path()).unwrap();
+        (file, connection)
+    }
+
+    fn countries() -> Vec<Feature> {
+        vec![
+            feature(json!({
+                "type": "Feature",
+                "id": 7,
+                "geometry": {"type": "Point", "coordinates": [2600000.0, 1200000.0]},
+                "properties": {"name": "A", "pop_est": 12}
+            })),
+            feature(json!({
+                "type": "Feature",
+                "id": 9,
+                "geometry": {"type": "Polygon", "coordinates": [
+                    [[2500000.0, 1100000.0], [2700000.0, 1100000.0], [2700000.0, 1300000.0], [2500000.0, 1100000.0]]
+                ]},
+                "properties": {"name": "B", "pop_est": null}
+            })),
+        ]
+    }
+
+    #[test]
+    fn test_write() {
+        let crs = Crs::from_uri("http://www.opengis.net/def/crs/EPSG/0/2056").unwrap();
+        let (_file, connection) = write_collection(countries(), &crs);
+
+        let application_id: i32 = connection
+            .pragma_query_value(None, "application_id", |row| row.get(0))
+            .unwrap();
+        assert_eq!(application_id, APPLICATION_ID);
+        let srs_ids: Vec<i32> = connection
+            .prepare("SELECT srs_id FROM gpkg_spatial_ref_sys ORDER BY srs_id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(srs_ids, [-1, 0, 2056, 4326]);
+
+        let contents: (String, f64, f64, f64, f64, i32) = connection
+            .query_row(
+                "SELECT data_type, min_x, min_y, max_x, max_y, srs_id FROM gpkg_contents WHERE table_name = 'countries'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?)),
+            )
+            .unwrap();
+        assert_eq!(
+            contents,
+            (
+                "features".to_string(),
+                2500000.0,
+                1100000.0,
+                2700000.0,
+                1300000.0,
+                2056
+            )
+        );
+
+        let rows: Vec<(i64, String, Option<i64>, Vec<u8>)> = connection
+            .prepare("SELECT fid, name, pop_est, geom FROM countries ORDER BY fid")
+            .unwrap()
+            .query_map([], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+            })
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            rows.iter()
+                .map(|(fid, name, pop, _)| (*fid, name.as_str(), *pop))
+                .collect::<Vec<_>>(),
+            [(7, "A", Some(12)), (9, "B", None)]
+        );
+        let point = &rows[0].3;
+        assert_eq!(&point[..4], [b'G', b'P', 0, 3]);
+        assert_eq!(i32::from_le_bytes(point[4..8].try_into().unwrap()), 2056);
+        // The WKB of the point follows the 32 bytes of its envelope.
+        assert_eq!(point[40], 1);
+        assert_eq!(u32::from_le_bytes(point[41..45].try_into().unwrap()), 1);
+        assert_eq!(
+            f64::from_le_bytes(point[45..53].try_into().unwrap()),
+            2600000.0
+        );
+
+        let indexed: Vec<i64> = connection
+            .prepare("SELECT id FROM rtree_countries_geom WHERE minx <= 2650000 AND maxx >= 2650000 ORDER BY id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(indexed, [9]);
+    }
+
+    #[test]
+    fn test_property_columns() {
+        let properties = [
+            QueryableProperty::new("name", Some("string"), None),
+            QueryableProperty::new("FID", Some("integer"), None),
+            QueryableProperty::new("fid_", Some("integer"), None),
+            QueryableProperty::new("geom", Some("string"), None),
+            QueryableProperty::new("id", Some("string"), None),
+        ];
+        assert_eq!(
+            property_columns(&properties, &["fid", GEOMETRY_COLUMN]),
+            ["name", "FID__", "fid_", "geom_", "id"]
+        );
+        assert_eq!(
+            property_columns(&properties, &["fid", GEOMETRY_COLUMN, "id"]),
+            ["name", "FID__", "fid_", "geom_", "id_"]
+        );
+    }
+
+    #[test]
+    fn test_write_text_ids_and_lat_lon() {
+        let mut features = countries();
+        features[0].id = Some(Id::String("a".to_string()));
+        features[0].geometry = Some(geojson::Geometry::new(Value::Point(vec![46.9, 7.4])));
+        let crs = Crs::from_uri("http://www.opengis.net/def/crs/EPSG/0/4326").unwrap();
+        let (_file, connection) = write_collection(features, &crs);
+
+        let (fid, id, min_x, min_y): (i64, String, f64, f64) = connection
+            .query_row(
+                "SELECT fid, countries.id, minx, miny FROM countries \
+                 JOIN rtree_countries_geom r ON r.id = fid WHERE countries.id = 'a'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .unwrap();
+        assert_eq!((fid, id.as_str()), (1, "a"));
+        // The R-tree stores 32 bit floats, rounded outwards.
+        assert!((min_x - 7.4).abs() < 1e-5 && (min_y - 46.9).abs() < 1e-5);
+    }
+}
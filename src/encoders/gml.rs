@@ -0,0 +1,610 @@
+/*
+ * GML 3.2 encoding of features, following the Simple Features profile
+ * (http://www.opengis.net/doc/IS/GMLSF/2.0).
+ *
+ * Features are elements of an application schema generated per collection from its properties,
+ * served as XSD from `/collections/{collection_id}/schema.xsd`. The documents only use simple
+ * property types, at most once per feature, so they comply with level 0 of the profile and
+ * therefore with level 2 as well.
+ */
+use super::{
+    Encoder, Resource, ResourceKind,
+    html::{format_id, format_value},
+};
+use crate::{
+    error::AppError,
+    models::{Crs, Link, QueryableProperty},
+};
+use axum::{
+    http::header,
+    response::{IntoResponse, Response},
+};
+use geojson::{Feature, Position, Value};
+use utoipa::openapi::{ObjectBuilder, RefOr, Type, schema::Schema};
+
+const GML_NAMESPACE: &str = "http://www.opengis.net/gml/3.2";
+const SF_NAMESPACE: &str = "http://www.opengis.net/ogcapi-features-1/1.0/sf";
+const SF_SCHEMA: &str = "http://schemas.opengis.net/ogcapi/features/part1/1.0/xml/core-sf.xsd";
+const ATOM_NAMESPACE: &str = "http://www.w3.org/2005/Atom";
+const XSI_NAMESPACE: &str = "http://www.w3.org/2001/XMLSchema-instance";
+
+/// The element of the geometry of the features.
+const GEOMETRY_ELEMENT: &str = "geometry";
+
+/// A level of the GML Simple Features profile.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GmlProfile {
+    Sf0,
+    Sf2,
+}
+
+/// GML 3.2, for features only.
+pub struct GmlEncoder {
+    pub profile: GmlProfile,
+}
+
+impl Encoder for GmlEncoder {
+    fn name(&self) -> &'static str {
+        match self.profile {
+            GmlProfile::Sf0 => "gmlsf0",
+            GmlProfile::Sf2 => "gmlsf2",
+        }
+    }
+
+    fn title(&self) -> &'static str {
+        match self.profile {
+            GmlProfile::Sf0 => "GML SF-0",
+            GmlProfile::Sf2 => "GML SF-2",
+        }
+    }
+
+    fn media_types(&self, kind: ResourceKind) -> &'static [&'static str] {
+        match (kind, self.profile) {
+            (ResourceKind::Items | ResourceKind::Item, GmlProfile::Sf0) => &[
+                "application/gml+xml; version=3.2; profile=http://www.opengis.net/def/profile/ogc/2.0/gml-sf0",
+            ],
+            (ResourceKind::Items | ResourceKind::Item, GmlProfile::Sf2) => &[
+                "application/gml+xml; version=3.2; profile=http://www.opengis.net/def/profile/ogc/2.0/gml-sf2",
+            ],
+            _ => &[],
+        }
+    }
+
+    fn conformance_classes(&self) -> &'static [&'static str] {
+        match self.profile {
+            GmlProfile::Sf0 => &["http://www.opengis.net/spec/ogcapi-features-1/1.0/conf/gmlsf0"],
+            GmlProfile::Sf2 => &["http://www.opengis.net/spec/ogcapi-features-1/1.0/conf/gmlsf2"],
+        }
+    }
+
+    fn schema(&self) -> Option<RefOr<Schema>> {
+        Some(ObjectBuilder::new().schema_type(Type::String).into())
+    }
+
+    fn needs_schema(&self) -> bool {
+        true
+    }
+
+    fn encode(&self, resource: Resource<'_>, url_base: &str) -> Result<Response, AppError> {
+        let (kind, document) = match resource {
+            Resource::Items(items) => {
+                let namespace = ApplicationNamespace::new(url_base, items.collection_id);
+                let collection = items.collection;
+                let mut xml = String::new();
+                xml.push_str(&format!(
+                    "<sf:FeatureCollection xmlns:sf=\"{}\" xmlns:gml=\"{}\" xmlns:atom=\"{}\" \
+                     xmlns:xsi=\"{}\" xmlns:app=\"{}\" xsi:schemaLocation=\"{} {} {} {}\" \
+                     numberMatched=\"{}\" numberReturned=\"{}\">",
+                    SF_NAMESPACE,
+                    GML_NAMESPACE,
+                    ATOM_NAMESPACE,
+                    XSI_NAMESPACE,
+                    escape(&namespace.uri),
+                    SF_NAMESPACE,
+                    SF_SCHEMA,
+                    escape(&namespace.uri),
+                    escape(&namespace.schema_location),
+                    collection.number_matched,
+                    collection.number_returned,
+                ));
+                for link in &collection.links {
+                    write_link(&mut xml, link);
+                }
+                let properties: Vec<_> = items
+                    .properties
+                    .iter()
+                    .map(|property| property.name.as_str())
+                    .collect();
+                for feature in &collection.features {
+                    xml.push_str("<sf:featureMember>");
+                    write_feature(
+                        &mut xml,
+                        items.collection_id,
+                        items.crs,
+                        feature,
+                        &properties,
+                        None,
+                    );
+                    xml.push_str("</sf:featureMember>");
+                }
+                xml.push_str("</sf:FeatureCollection>");
+                (ResourceKind::Items, xml)
+            }
+            Resource::Item(item) => {
+                let namespace = ApplicationNamespace::new(url_base, item.collection_id);
+                let mut xml = String::new();
+                write_feature(
+                    &mut xml,
+                    item.collection_id,
+                    item.crs,
+                    item.feature,
+                    &item
+                        .properties
+                        .iter()
+                        .map(String::as_str)
+                        .collect::<Vec<_>>(),
+                    Some(&namespace),
+                );
+                (ResourceKind::Item, xml)
+            }
+            _ => {
+                return Err(AppError::NotAcceptable(
+                    "GML is only available for features".to_string(),
+                ));
+            }
+        };
+        Ok((
+            [(header::CONTENT_TYPE, self.media_type(kind))],
+            format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n{}", document),
+        )
+            .into_response())
+    }
+}
+
+/// The namespace of the application schema of a collection, and where its XSD is served.
+struct ApplicationNamespace {
+    uri: String,
+    schema_location: String,
+}
+
+impl ApplicationNamespace {
+    fn new(url_base: &str, collection_id: &str) -> Self {
+        let uri = format!("{}/collections/{}", url_base, collection_id);
+        Self {
+            schema_location: format!("{}/schema.xsd", uri),
+            uri,
+        }
+    }
+}
+
+/// Escapes text for XML content and attribute values.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Turns a collection or property name into an XML name without a prefix, replacing the
+/// characters that are not allowed.
+fn xml_name(name: &str) -> String {
+    let mut xml_name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || matches!(c, '_' | '-' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if !xml_name.starts_with(|c: char| c.is_alphabetic() || c == '_') {
+        xml_name.insert(0, '_');
+    }
+    xml_name
+}
+
+fn write_link(xml: &mut String, link: &Link) {
+    xml.push_str(&format!(
+        "<atom:link href=\"{}\" rel=\"{}\"",
+        escape(&link.href),
+        link.rel.as_str()
+    ));
+    if let Some(type_) = &link.type_ {
+        xml.push_str(&format!(" type=\"{}\"", escape(type_)));
+    }
+    if let Some(title) = &link.title {
+        xml.push_str(&format!(" title=\"{}\"", escape(title)));
+    }
+    xml.push_str("/>");
+}
+
+/// Writes a feature as an element of the application schema, declaring the namespaces when it
+/// is the root of the document. The properties are written in the order of `properties`, which
+/// the sequence of the schema follows.
+fn write_feature(
+    xml: &mut String,
+    collection_id: &str,
+    crs: &Crs,
+    feature: &Feature,
+    properties: &[&str],
+    root: Option<&ApplicationNamespace>,
+) {
+    let element = format!("app:{}", xml_name(collection_id));
+    let gml_id = xml_name(&format!(
+        "{}.{}",
+        collection_id,
+        format_id(feature.id.as_ref())
+    ));
+    xml.push_str(&format!("<{}", element));
+    if let Some(namespace) = root {
+        xml.push_str(&format!(
+            " xmlns:app=\"{}\" xmlns:gml=\"{}\" xmlns:xsi=\"{}\" xsi:schemaLocation=\"{} {}\"",
+            escape(&namespace.uri),
+            GML_NAMESPACE,
+            XSI_NAMESPACE,
+            escape(&namespace.uri),
+            escape(&namespace.schema_location)
+        ));
+    }
+    xml.push_str(&format!(" gml:id=\"{}\">", escape(&gml_id)));
+
+    for name in properties {
+        let Some(value) = feature
+            .properties
+            .as_ref()
+            .and_then(|values| values.get(*name))
+            .filter(|value| !value.is_null())
+        else {
+            continue;
+        };
+        let name = xml_name(name);
+        xml.push_str(&format!(
+            "<app:{}>{}</app:{}>",
+            name,
+            escape(&format_value(Some(value))),
+            name
+        ));
+    }
+    if let Some(geometry) = &feature.geometry {
+        xml.push_str(&format!("<app:{}>", GEOMETRY_ELEMENT));
+        let mut writer = GeometryWriter {
+            xml,
+            srs_name: crs.uri(),
+            gml_id: &gml_id,
+            count: 0,
+        };
+        writer.write(&geometry.value, true);
+        xml.push_str(&format!("</app:{}>", GEOMETRY_ELEMENT));
+    }
+    xml.push_str(&format!("</{}>", element));
+}
+
+/// Writes GML geometries, numbering their `gml:id`s after the feature's.
+struct GeometryWriter<'a> {
+    xml: &'a mut String,
+    srs_name: &'a str,
+    gml_id: &'a str,
+    count: usize,
+}
+
+impl GeometryWriter<'_> {
+    /// Opens a geometry element, with the CRS on the outermost one only.
+    fn start(&mut self, element: &str, outermost: bool) {
+        self.count += 1;
+        self.xml.push_str(&format!(
+            "<gml:{} gml:id=\"{}.geom.{}\"",
+            element,
+            escape(self.gml_id),
+            self.count
+        ));
+        if outermost {
+            self.xml
+                .push_str(&format!(" srsName=\"{}\"", escape(self.srs_name)));
+        }
+        self.xml.push('>');
+    }
+
+    fn end(&mut self, element: &str) {
+        self.xml.push_str(&format!("</gml:{}>", element));
+    }
+
+    fn positions(&mut self, element: &str, positions: &[Position]) {
+        let dimension = positions.first().map_or(2, |position| position.len());
+        let coordinates: Vec<_> = positions
+            .iter()
+            .flat_map(|position| position.iter().map(f64::to_string))
+            .collect();
+        self.xml.push_str(&format!("<gml:{}", element));
+        if dimension > 2 {
+            self.xml
+                .push_str(&format!(" srsDimension=\"{}\"", dimension));
+        }
+        self.xml
+            .push_str(&format!(">{}</gml:{}>", coordinates.join(" "), element));
+    }
+
+    fn point(&mut self, position: &Position, outermost: bool) {
+        self.start("Point", outermost);
+        self.positions("pos", std::slice::from_ref(position));
+        self.end("Point");
+    }
+
+    fn line_string(&mut self, positions: &[Position], outermost: bool) {
+        self.start("LineString", outermost);
+        self.positions("posList", positions);
+        self.end("LineString");
+    }
+
+    fn polygon(&mut self, rings: &[Vec<Position>], outermost: bool) {
+        self.start("Polygon", outermost);
+        for (i, ring) in rings.iter().enumerate() {
+            let boundary = if i == 0 { "exterior" } else { "interior" };
+            self.xml
+                .push_str(&format!("<gml:{}><gml:LinearRing>", boundary));
+            self.positions("posList", ring);
+            self.xml
+                .push_str(&format!("</gml:LinearRing></gml:{}>", boundary));
+        }
+        self.end("Polygon");
+    }
+
+    fn write(&mut self, value: &Value, outermost: bool) {
+        match value {
+            Value::Point(position) => self.point(position, outermost),
+            Value::LineString(positions) => self.line_string(positions, outermost),
+            Value::Polygon(rings) => self.polygon(rings, outermost),
+            Value::MultiPoint(positions) => {
+                self.start("MultiPoint", outermost);
+                for position in positions {
+                    self.xml.push_str("<gml:pointMember>");
+                    self.point(position, false);
+                    self.xml.push_str("</gml:pointMember>");
+                }
+                self.end("MultiPoint");
+            }
+            Value::MultiLineString(lines) => {
+                self.start("MultiCurve", outermost);
+                for line in lines {
+                    self.xml.push_str("<gml:curveMember>");
+                    self.line_string(line, false);
+                    self.xml.push_str("</gml:curveMember>");
+                }
+                self.end("MultiCurve");
+            }
+            Value::MultiPolygon(polygons) => {
+                self.start("MultiSurface", outermost);
+                for polygon in polygons {
+                    self.xml.push_str("<gml:surfaceMember>");
+                    self.polygon(polygon, false);
+                    self.xml.push_str("</gml:surfaceMember>");
+                }
+                self.end("MultiSurface");
+            }
+            Value::GeometryCollection(geometries) => {
+                self.start("MultiGeometry", outermost);
+                for geometry in geometries {
+                    self.xml.push_str("<gml:geometryMember>");
+                    self.write(&geometry.value, false);
+                    self.xml.push_str("</gml:geometryMember>");
+                }
+                self.end("MultiGeometry");
+            }
+        }
+    }
+}
+
+/// Maps the JSON Schema type of a property to an XML Schema type.
+fn xsd_type(property: &QueryableProperty) -> &'static str {
+    match (property.type_.as_deref(), property.format.as_deref()) {
+        (Some("integer"), _) => "xs:integer",
+        (Some("number"), _) => "xs:double",
+        (Some("boolean"), _) => "xs:boolean",
+        (Some("string"), Some("date")) => "xs:date",
+        (Some("string"), Some("date-time")) => "xs:dateTime",
+        (Some("string"), Some("time")) => "xs:time",
+        _ => "xs:string",
+    }
+}
+
+/// Generates the XSD of the application schema of a collection, with an optional element per
+/// property followed by the geometry. Properties of other types than the XML Schema ones, such
+/// as JSON objects, are strings.
+pub fn application_schema(
+    url_base: &str,
+    collection_id: &str,
+    properties: &[QueryableProperty],
+) -> String {
+    let namespace = ApplicationNamespace::new(url_base, collection_id);
+    let name = xml_name(collection_id);
+    let mut elements: Vec<_> = properties
+        .iter()
+        .map(|property| {
+            format!(
+                "          <xs:element name=\"{}\" type=\"{}\" minOccurs=\"0\"/>\n",
+                xml_name(&property.name),
+                xsd_type(property)
+            )
+        })
+        .collect();
+    elements.push(format!(
+        "          <xs:element name=\"{}\" type=\"gml:GeometryPropertyType\" minOccurs=\"0\"/>\n",
+        GEOMETRY_ELEMENT
+    ));
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<xs:schema xmlns:xs="http://www.w3.org/2001/XMLSchema" xmlns:gml="{gml}" xmlns:gmlsf="http://www.opengis.net/gmlsf/2.0" xmlns:app="{namespace}" targetNamespace="{namespace}" elementFormDefault="qualified" version="1.0">
+  <xs:annotation>
+    <xs:appinfo source="http://schemas.opengis.net/gmlsfProfile/2.0/gmlsfLevels.xsd">
+      <gmlsf:ComplianceLevel>0</gmlsf:ComplianceLevel>
+    </xs:appinfo>
+  </xs:annotation>
+  <xs:import namespace="{gml}" schemaLocation="http://schemas.opengis.net/gml/3.2.1/gml.xsd"/>
+  <xs:import namespace="http://www.opengis.net/gmlsf/2.0" schemaLocation="http://schemas.opengis.net/gmlsfProfile/2.0/gmlsfLevels.xsd"/>
+  <xs:element name="{name}" type="app:{name}Type" substitutionGroup="gml:AbstractFeature"/>
+  <xs:complexType name="{name}Type">
+    <xs:complexContent>
+      <xs:extension base="gml:AbstractFeatureType">
+        <xs:sequence>
+{elements}        </xs:sequence>
+      </xs:extension>
+    </xs:complexContent>
+  </xs:complexType>
+</xs:schema>
+"#,
+        gml = GML_NAMESPACE,
+        namespace = escape(&namespace.uri),
+        name = name,
+        elements = elements.concat(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        encoders::{Item, Items},
+        models::{LinkRel, OgcApiFeatureCollection},
+    };
+    use axum::body::to_bytes;
+    use serde_json::json;
+
+    fn feature() -> Feature {
+        serde_json::from_value(json!({
+            "type": "Feature",
+            "id": 3,
+            "geometry": {"type": "MultiPolygon", "coordinates": [
+                [[[0, 0], [1, 0], [1, 1], [0, 0]], [[0.2, 0.1], [0.8, 0.1], [0.8, 0.7], [0.2, 0.1]]]
+            ]},
+            "properties": {"name": "Fish & Chips", "pop_est": 12, "note": null}
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_encode_item() {
+        let links = [Link {
+            href: "http://localhost:3000/collections/countries/items/3".to_string(),
+            rel: LinkRel::Self_,
+            type_: None,
+            title: None,
+        }];
+        let encoder = GmlEncoder {
+            profile: GmlProfile::Sf2,
+        };
+        let response = encoder
+            .encode(
+                Resource::Item(Item {
+                    collection_id: "countries",
+                    crs: &Crs::crs84(),
+                    feature: &feature(),
+                    properties: &["pop_est".to_string(), "name".to_string()],
+                    links: &links,
+                }),
+                "http://localhost:3000",
+            )
+            .unwrap();
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "application/gml+xml; version=3.2; profile=http://www.opengis.net/def/profile/ogc/2.0/gml-sf2"
+        );
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(
+            String::from_utf8(body.to_vec()).unwrap(),
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <app:countries xmlns:app=\"http://localhost:3000/collections/countries\" \
+             xmlns:gml=\"http://www.opengis.net/gml/3.2\" \
+             xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\" \
+             xsi:schemaLocation=\"http://localhost:3000/collections/countries \
+             http://localhost:3000/collections/countries/schema.xsd\" gml:id=\"countries.3\">\
+             <app:pop_est>12</app:pop_est><app:name>Fish &amp; Chips</app:name>\
+             <app:geometry>\
+             <gml:MultiSurface gml:id=\"countries.3.geom.1\" srsName=\"http://www.opengis.net/def/crs/OGC/1.3/CRS84\">\
+             <gml:surfaceMember><gml:Polygon gml:id=\"countries.3.geom.2\">\
+             <gml:exterior><gml:LinearRing><gml:posList>0 0 1 0 1 1 0 0</gml:posList></gml:LinearRing></gml:exterior>\
+             <gml:interior><gml:LinearRing><gml:posList>0.2 0.1 0.8 0.1 0.8 0.7 0.2 0.1</gml:posList></gml:LinearRing></gml:interior>\
+             </gml:Polygon></gml:surfaceMember></gml:MultiSurface>\
+             </app:geometry></app:countries>"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_encode_items_in_schema_order() {
+        let collection =
+            OgcApiFeatureCollection::new(vec![feature(), feature()], 2, 2, Vec::new(), None);
+        let properties = [
+            QueryableProperty::new("pop_est", Some("integer"), None),
+            QueryableProperty::new("name", Some("string"), None),
+        ];
+        let response = GmlEncoder {
+            profile: GmlProfile::Sf0,
+        }
+        .encode(
+            Resource::Items(Items {
+                collection_id: "countries",
+                crs: &Crs::crs84(),
+                collection: &collection,
+                properties: &properties,
+                crs_definition: None,
+            }),
+            "http://localhost:3000",
+        )
+        .unwrap();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let xml = String::from_utf8(body.to_vec()).unwrap();
+        assert_eq!(
+            xml.matches("<app:pop_est>12</app:pop_est><app:name>Fish &amp; Chips</app:name>")
+                .count(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_xml_name() {
+        assert_eq!(xml_name("pop_est"), "pop_est");
+        assert_eq!(xml_name("2020 census"), "_2020_census");
+        assert_eq!(xml_name("countries.1"), "countries.1");
+    }
+
+    #[test]
+    fn test_application_schema() {
+        let schema = application_schema(
+            "http://localhost:3000",
+            "countries",
+            &[
+                QueryableProperty::new("name", Some("string"), None),
+                QueryableProperty::new("founded", Some("string"), Some("date")),
+                QueryableProperty::new("tags", Some("object"), None),
+            ],
+        );
+        assert!(schema.contains("targetNamespace=\"http://localhost:3000/collections/countries\""));
+        assert!(schema.contains(
+            "<xs:element name=\"countries\" type=\"app:countriesType\" substitutionGroup=\"gml:AbstractFeature\"/>"
+        ));
+        let elements: Vec<_> = schema
+            .lines()
+            .filter(|line| {
+                line.trim_start().starts_with("<xs:element name=") && line.contains("minOccurs")
+            })
+            .map(str::trim)
+            .collect();
+        assert_eq!(
+            elements,
+            [
+                "<xs:element name=\"name\" type=\"xs:string\" minOccurs=\"0\"/>",
+                "<xs:element name=\"founded\" type=\"xs:date\" minOccurs=\"0\"/>",
+                "<xs:element name=\"tags\" type=\"xs:string\" minOccurs=\"0\"/>",
+                "<xs:element name=\"geometry\" type=\"gml:GeometryPropertyType\" minOccurs=\"0\"/>",
+            ]
+        );
+    }
+}
@@ -0,0 +1,255 @@
+/*
+ * HTML encoding of the resources (OGC API Features, HTML conformance class).
+ *
+ * Pages are rendered from the same models as the JSON responses with the templates of the
+ * `templates` directory, which are compiled into the binary.
+ */
+use super::{Encoder, Item, Items, Resource, ResourceKind};
+use crate::{
+    error::AppError,
+    models::{Collection, Collections, Conformance, Crs, LandingPage, Link},
+};
+use askama::Template;
+use axum::response::{Html, IntoResponse, Response};
+use geojson::{Feature, feature::Id};
+use serde::Serialize;
+use serde_json::Value;
+use utoipa::openapi::{ObjectBuilder, RefOr, Type, schema::Schema};
+
+/// HTML pages, for browsing the API.
+pub struct HtmlEncoder;
+
+impl Encoder for HtmlEncoder {
+    fn name(&self) -> &'static str {
+        "html"
+    }
+
+    fn title(&self) -> &'static str {
+        "HTML"
+    }
+
+    fn media_types(&self, _kind: ResourceKind) -> &'static [&'static str] {
+        &["text/html"]
+    }
+
+    fn conformance_classes(&self) -> &'static [&'static str] {
+        &["http://www.opengis.net/spec/ogcapi-features-1/1.0/conf/html"]
+    }
+
+    fn schema(&self) -> Option<RefOr<Schema>> {
+        Some(ObjectBuilder::new().schema_type(Type::String).into())
+    }
+
+    fn encode(&self, resource: Resource<'_>, url_base: &str) -> Result<Response, AppError> {
+        match resource {
+            Resource::Landing(page) => render(&LandingTemplate { page }),
+            Resource::Conformance(conformance) => render(&ConformanceTemplate { conformance }),
+            Resource::Collections(collections) => render(&CollectionsTemplate {
+                url_base,
+                collections,
+            }),
+            Resource::Collection(collection) => render(&CollectionTemplate {
+                url_base,
+                collection,
+            }),
+            Resource::Items(items) => render(&ItemsTemplate::new(url_base, &items)),
+            Resource::Item(item) => render(&ItemTemplate::new(url_base, &item)),
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "landing.html")]
+struct LandingTemplate<'a> {
+    page: &'a LandingPage,
+}
+
+#[derive(Template)]
+#[template(path = "conformance.html")]
+struct ConformanceTemplate<'a> {
+    conformance: &'a Conformance,
+}
+
+#[derive(Template)]
+#[template(path = "collections.html")]
+struct CollectionsTemplate<'a> {
+    url_base: &'a str,
+    collections: &'a Collections,
+}
+
+#[derive(Template)]
+#[template(path = "collection.html")]
+struct CollectionTemplate<'a> {
+    url_base: &'a str,
+    collection: &'a Collection,
+}
+
+/// A feature as a row of the items table.
+struct FeatureRow {
+    id: String,
+    values: Vec<String>,
+}
+
+#[derive(Template)]
+#[template(path = "items.html")]
+struct ItemsTemplate<'a> {
+    url_base: &'a str,
+    collection_id: &'a str,
+    number_matched: u64,
+    number_returned: u64,
+    columns: Vec<String>,
+    rows: Vec<FeatureRow>,
+    links: &'a [Link],
+    /// The GeoJSON shown on the map, only set for CRS84 geometries.
+    map: Option<String>,
+}
+
+impl<'a> ItemsTemplate<'a> {
+    fn new(url_base: &'a str, items: &Items<'a>) -> Self {
+        let features = &items.collection.features;
+        let mut columns: Vec<String> = Vec::new();
+        for properties in features.iter().filter_map(|f| f.properties.as_ref()) {
+            for name in properties.keys() {
+                if !columns.contains(name) {
+                    columns.push(name.clone());
+                }
+            }
+        }
+        let rows = features
+            .iter()
+            .map(|feature| FeatureRow {
+                id: format_id(feature.id.as_ref()),
+                values: columns
+                    .iter()
+                    .map(|column| {
+                        format_value(feature.properties.as_ref().and_then(|p| p.get(column)))
+                    })
+                    .collect(),
+            })
+            .collect();
+        let map = (*items.crs == Crs::crs84()).then(|| {
+            map_data(&geojson::FeatureCollection {
+                bbox: None,
+                features: features.to_vec(),
+                foreign_members: None,
+            })
+        });
+
+        Self {
+            url_base,
+            collection_id: items.collection_id,
+            number_matched: items.collection.number_matched,
+            number_returned: items.collection.number_returned,
+            columns,
+            rows,
+            links: &items.collection.links,
+            map,
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "item.html")]
+struct ItemTemplate<'a> {
+    url_base: &'a str,
+    collection_id: &'a str,
+    id: String,
+    properties: Vec<(String, String)>,
+    links: &'a [Link],
+    /// The GeoJSON shown on the map, only set for CRS84 geometries.
+    map: Option<String>,
+}
+
+impl<'a> ItemTemplate<'a> {
+    fn new(url_base: &'a str, item: &Item<'a>) -> Self {
+        let feature = item.feature;
+        let properties = feature
+            .properties
+            .iter()
+            .flatten()
+            .map(|(name, value)| (name.clone(), format_value(Some(value))))
+            .collect();
+        let map = (*item.crs == Crs::crs84()).then(|| {
+            map_data(&Feature {
+                foreign_members: None,
+                ..feature.clone()
+            })
+        });
+
+        Self {
+            url_base,
+            collection_id: item.collection_id,
+            id: format_id(feature.id.as_ref()),
+            properties,
+            links: item.links,
+            map,
+        }
+    }
+}
+
+pub(super) fn format_id(id: Option<&Id>) -> String {
+    match id {
+        Some(Id::String(id)) => id.clone(),
+        Some(Id::Number(id)) => id.to_string(),
+        None => String::new(),
+    }
+}
+
+/// Formats a property value for display, leaving strings unquoted and nulls empty.
+pub(super) fn format_value(value: Option<&Value>) -> String {
+    match value {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(value)) => value.clone(),
+        Some(value) => value.to_string(),
+    }
+}
+
+/// Serializes the data of a map so that it can be embedded in a `<script>` element.
+fn map_data(value: &impl Serialize) -> String {
+    serde_json::to_string(value)
+        .unwrap_or_else(|_| "null".to_string())
+        .replace("</", "<\\/")
+}
+
+/// Renders a page, mapping template errors to a server error.
+fn render(template: &impl Template) -> Result<Response, AppError> {
+    template
+        .render()
+        .map(|html| Html(html).into_response())
+        .map_err(|e| AppError::Internal(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::OgcApiFeatureCollection;
+    use serde_json::json;
+
+    #[test]
+    fn test_items_template() {
+        let feature: Feature = serde_json::from_value(json!({
+            "type": "Feature",
+            "id": 1,
+            "geometry": {"type": "Point", "coordinates": [7.4, 46.9]},
+            "properties": {"name": "</script><b>Bern</b>", "pop_est": null}
+        }))
+        .unwrap();
+        let collection = OgcApiFeatureCollection::new(vec![feature], 1, 1, vec![], None);
+        let items = Items {
+            collection_id: "cities",
+            crs: &Crs::crs84(),
+            collection: &collection,
+            properties: &[],
+            crs_definition: None,
+        };
+        let template = ItemsTemplate::new("http://localhost:3000", &items);
+        assert_eq!(template.columns, ["name", "pop_est"]);
+        assert_eq!(template.rows[0].id, "1");
+        assert_eq!(template.rows[0].values, ["</script><b>Bern</b>", ""]);
+
+        let html = template.render().unwrap();
+        assert!(html.contains("&#60;/script&#62;&#60;b&#62;Bern&#60;/b&#62;"));
+        assert!(html.contains(r#"<\/script><b>Bern<\/b>"#));
+        assert!(html.contains("http://localhost:3000/collections/cities/items/1?f=html"));
+    }
+}
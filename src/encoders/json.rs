@@ -0,0 +1,246 @@
+use super::{BuildLinks, Encoder, ItemsStream, Resource, ResourceKind};
+use crate::{
+    error::AppError,
+    models::{Cursor, Link},
+    storage::StreamedFeature,
+};
+use axum::{
+    Json,
+    body::{Body, Bytes},
+    http::header,
+    response::{IntoResponse, Response},
+};
+use futures_util::{StreamExt, stream::BoxStream};
+use geojson::Feature;
+use serde::Serialize;
+
+/// JSON, with GeoJSON for features.
+pub struct JsonEncoder;
+
+/// A GeoJSON feature with the links of OGC API Features.
+#[derive(Serialize)]
+struct FeatureWithLinks<'a> {
+    #[serde(flatten)]
+    feature: &'a Feature,
+    links: &'a [Link],
+}
+
+impl Encoder for JsonEncoder {
+    fn name(&self) -> &'static str {
+        "json"
+    }
+
+    fn title(&self) -> &'static str {
+        "JSON"
+    }
+
+    fn media_types(&self, kind: ResourceKind) -> &'static [&'static str] {
+        match kind {
+            ResourceKind::Items | ResourceKind::Item => {
+                &["application/geo+json", "application/json"]
+            }
+            _ => &["application/json"],
+        }
+    }
+
+    fn conformance_classes(&self) -> &'static [&'static str] {
+        &["http://www.opengis.net/spec/ogcapi-features-1/1.0/conf/geojson"]
+    }
+
+    fn streams_items(&self) -> bool {
+        true
+    }
+
+    fn encode_stream(&self, items: ItemsStream) -> Result<Response, AppError> {
+        let header = format!(
+            r#"{{"type":"FeatureCollection","bbox":{},"numberMatched":{},"features":["#,
+            serde_json::to_string(&items.bbox).map_err(|e| AppError::Internal(e.to_string()))?,
+            items.number_matched
+        );
+        let writer = CollectionWriter {
+            header: Some(header),
+            features: items.features,
+            number_returned: 0,
+            next_cursor: None,
+            links: Some(items.links),
+        };
+        let body = Body::from_stream(futures_util::stream::try_unfold(
+            writer,
+            CollectionWriter::next,
+        ));
+        Ok(geojson(body))
+    }
+
+    fn encode(&self, resource: Resource<'_>, _url_base: &str) -> Result<Response, AppError> {
+        let response = match resource {
+            Resource::Landing(page) => Json(page).into_response(),
+            Resource::Conformance(conformance) => Json(conformance).into_response(),
+            Resource::Collections(collections) => Json(collections).into_response(),
+            Resource::Collection(collection) => Json(collection).into_response(),
+            Resource::Items(items) => geojson(Json(items.collection)),
+            Resource::Item(item) => geojson(Json(FeatureWithLinks {
+                feature: item.feature,
+                links: item.links,
+            })),
+        };
+        Ok(response)
+    }
+}
+
+fn geojson(body: impl IntoResponse) -> Response {
+    ([(header::CONTENT_TYPE, "application/geo+json")], body).into_response()
+}
+
+/// Writes a feature collection while its features are read: the members known upfront, then a
+/// chunk per feature, then the members that depend on the features.
+struct CollectionWriter {
+    header: Option<String>,
+    features: BoxStream<'static, Result<StreamedFeature, AppError>>,
+    number_returned: u64,
+    next_cursor: Option<Cursor>,
+    /// Taken once the features are written.
+    links: Option<BuildLinks>,
+}
+
+impl CollectionWriter {
+    async fn next(mut self) -> Result<Option<(Bytes, Self)>, std::io::Error> {
+        if let Some(header) = self.header.take() {
+            return Ok(Some((header.into(), self)));
+        }
+        if self.links.is_none() {
+            return Ok(None);
+        }
+
+        let chunk = match self.features.next().await {
+            Some(Ok(streamed)) => {
+                let mut chunk = if self.number_returned > 0 {
+                    b",".to_vec()
+                } else {
+                    Vec::new()
+                };
+                serde_json::to_writer(&mut chunk, &streamed.feature)
+                    .map_err(std::io::Error::other)?;
+                self.number_returned += 1;
+                self.next_cursor = streamed.next_cursor;
+                chunk
+            }
+            Some(Err(error)) => {
+                // The status is already sent, so the response can only be cut short.
+                tracing::error!("Failed to read the features of a page: {}", error);
+                return Err(std::io::Error::other(error));
+            }
+            None => {
+                let links = self.links.take().expect("links are taken once")(
+                    self.next_cursor.as_ref().map(Cursor::encode),
+                );
+                format!(
+                    r#"],"numberReturned":{},"links":{}}}"#,
+                    self.number_returned,
+                    serde_json::to_string(&links).map_err(std::io::Error::other)?
+                )
+                .into_bytes()
+            }
+        };
+        Ok(Some((chunk.into(), self)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Crs, LinkRel};
+    use axum::body::to_bytes;
+    use serde_json::{Map, json};
+
+    fn streamed(id: u64, next_cursor: Option<Cursor>) -> Result<StreamedFeature, AppError> {
+        Ok(StreamedFeature {
+            feature: serde_json::from_value(json!({
+                "type": "Feature",
+                "id": id,
+                "geometry": {"type": "Point", "coordinates": [7.44, 46.95]},
+                "properties": {"name": "Bern"}
+            }))
+            .unwrap(),
+            next_cursor,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_encode_stream() {
+        let cursor = Cursor {
+            offset: 2,
+            sortby: None,
+            after: Map::from_iter([("id".to_string(), json!(2))]),
+        };
+        let items = ItemsStream {
+            collection_id: "cities".to_string(),
+            crs: Crs::crs84(),
+            properties: Vec::new(),
+            crs_definition: None,
+            number_matched: 5,
+            bbox: None,
+            features: futures_util::stream::iter([
+                streamed(1, None),
+                streamed(2, Some(cursor.clone())),
+            ])
+            .boxed(),
+            links: Box::new(|next_cursor| {
+                vec![Link {
+                    href: format!(
+                        "http://localhost:3000/collections/cities/items?cursor={}",
+                        next_cursor.unwrap()
+                    ),
+                    rel: LinkRel::Next,
+                    type_: None,
+                    title: None,
+                }]
+            }),
+        };
+        let response = JsonEncoder.encode_stream(items).unwrap();
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "application/geo+json"
+        );
+
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let collection: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(collection["type"], "FeatureCollection");
+        assert_eq!(collection["numberMatched"], 5);
+        assert_eq!(collection["numberReturned"], 2);
+        assert_eq!(collection["features"][1]["id"], 2);
+        assert_eq!(
+            collection["links"][0]["href"],
+            format!(
+                "http://localhost:3000/collections/cities/items?cursor={}",
+                cursor.encode()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_encode_empty_stream() {
+        let items = ItemsStream {
+            collection_id: "cities".to_string(),
+            crs: Crs::crs84(),
+            properties: Vec::new(),
+            crs_definition: None,
+            number_matched: 0,
+            bbox: Some(vec![5.9, 45.8, 10.5, 47.8]),
+            features: futures_util::stream::empty().boxed(),
+            links: Box::new(|_| Vec::new()),
+        };
+        let response = JsonEncoder.encode_stream(items).unwrap();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+            json!({
+                "type": "FeatureCollection",
+                "bbox": [5.9, 45.8, 10.5, 47.8],
+                "numberMatched": 0,
+                "features": [],
+                "numberReturned": 0,
+                "links": []
+            })
+        );
+    }
+}
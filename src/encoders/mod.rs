@@ -0,0 +1,585 @@
+/*
+ * Output encodings of the resources.
+ *
+ * Handlers build the models of a resource and hand them to the encoder picked from the `f`
+ * parameter or the `Accept` header. The registry also derives the `alternate` links, the
+ * conformance classes and the response media types of the OpenAPI document from the encoders,
+ * so that adding a format only takes a new `Encoder` registered in `Encoders::new`.
+ */
+mod csv;
+mod flatgeobuf;
+mod geopackage;
+mod gml;
+mod html;
+mod json;
+
+use crate::{
+    config::AppConfig,
+    error::AppError,
+    models::{
+        Collection, Collections, Conformance, Crs, LandingPage, Link, LinkRel,
+        OgcApiFeatureCollection, QueryableProperty,
+    },
+    storage::StreamedFeature,
+};
+use axum::{
+    extract::Request,
+    http::{HeaderMap, HeaderValue, header},
+    middleware::Next,
+    response::Response,
+};
+use csv::CsvEncoder;
+use flatgeobuf::FlatgeobufEncoder;
+use futures_util::stream::BoxStream;
+use geojson::{Bbox, Feature};
+use geopackage::GeopackageEncoder;
+pub use gml::application_schema;
+use gml::{GmlEncoder, GmlProfile};
+use html::HtmlEncoder;
+use json::JsonEncoder;
+use utoipa::openapi::{RefOr, schema::Schema};
+
+/// The kinds of resources served, which encoders may support only some of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResourceKind {
+    Landing,
+    Conformance,
+    Collections,
+    Collection,
+    Items,
+    Item,
+}
+
+/// A page of features of a collection, in `crs`.
+pub struct Items<'a> {
+    pub collection_id: &'a str,
+    pub crs: &'a Crs,
+    pub collection: &'a OgcApiFeatureCollection,
+    /// The returned properties along with their types, only filled in for the encoders that
+    /// need them.
+    pub properties: &'a [QueryableProperty],
+    /// The WKT definition of `crs`, only looked up for the encoders that need it.
+    pub crs_definition: Option<&'a str>,
+}
+
+/// Builds the links of a page from the cursor of its next page.
+pub type BuildLinks = Box<dyn FnOnce(Option<String>) -> Vec<Link> + Send>;
+
+/// A page of features of a collection, written while it is read from the storage.
+pub struct ItemsStream {
+    pub collection_id: String,
+    pub crs: Crs,
+    /// The returned properties along with their types, only filled in for the encoders that
+    /// need them.
+    pub properties: Vec<QueryableProperty>,
+    /// The WKT definition of `crs`, only looked up for the encoders that need it.
+    pub crs_definition: Option<String>,
+    pub number_matched: u64,
+    pub bbox: Option<Bbox>,
+    pub features: BoxStream<'static, Result<StreamedFeature, AppError>>,
+    /// Builds the links of the page from the cursor of the next page, which is only known once
+    /// all the features are read.
+    pub links: BuildLinks,
+}
+
+/// A single feature of a collection, in `crs`, along with its links.
+pub struct Item<'a> {
+    pub collection_id: &'a str,
+    pub crs: &'a Crs,
+    pub feature: &'a Feature,
+    /// The configured properties of the collection, in their order.
+    pub properties: &'a [String],
+    pub links: &'a [Link],
+}
+
+/// A resource to encode.
+pub enum Resource<'a> {
+    Landing(&'a LandingPage),
+    Conformance(&'a Conformance),
+    Collections(&'a Collections),
+    Collection(&'a Collection),
+    Items(Items<'a>),
+    Item(Item<'a>),
+}
+
+pub trait Encoder: Send + Sync {
+    /// The value of the `f` parameter selecting this encoder.
+    fn name(&self) -> &'static str;
+
+    /// A human readable name of the encoding, used in the titles of `alternate` links.
+    fn title(&self) -> &'static str;
+
+    /// The media types of a kind of resource in this encoding, the first one being the one
+    /// responses are sent as. Empty when the encoder does not support the kind.
+    fn media_types(&self, kind: ResourceKind) -> &'static [&'static str];
+
+    fn media_type(&self, kind: ResourceKind) -> &'static str {
+        self.media_types(kind).first().copied().unwrap_or_default()
+    }
+
+    fn supports(&self, kind: ResourceKind) -> bool {
+        !self.media_types(kind).is_empty()
+    }
+
+    /// The conformance classes implemented by this encoding.
+    fn conformance_classes(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// The schema of the responses documented in the OpenAPI document, `None` reusing the
+    /// schema of the JSON encoding.
+    fn schema(&self) -> Option<RefOr<Schema>> {
+        None
+    }
+
+    /// Whether encoding feature collections needs the types of the properties and the
+    /// definition of the CRS, for encodings declaring them upfront.
+    fn needs_schema(&self) -> bool {
+        false
+    }
+
+    /// The largest number of features of an encoding that holds all the features matching a
+    /// request rather than a page of them, `None` for paged encodings.
+    fn download_limit(&self) -> Option<u64> {
+        None
+    }
+
+    /// Whether encoding blocks, for example on file I/O, so that it runs on the blocking threads
+    /// rather than the async workers.
+    fn blocks(&self) -> bool {
+        false
+    }
+
+    /// Whether feature collections are written as their features are read, with
+    /// `encode_stream`, rather than encoded once the whole page is read.
+    fn streams_items(&self) -> bool {
+        false
+    }
+
+    fn encode_stream(&self, _items: ItemsStream) -> Result<Response, AppError> {
+        Err(AppError::Internal(format!(
+            "{} is not written incrementally",
+            self.title()
+        )))
+    }
+
+    fn encode(&self, resource: Resource<'_>, url_base: &str) -> Result<Response, AppError>;
+}
+
+/// The registered encoders. The first one supporting a kind of resource is its default.
+pub struct Encoders {
+    encoders: Vec<Box<dyn Encoder>>,
+}
+
+impl Encoders {
+    pub fn new(config: &AppConfig) -> Self {
+        Self {
+            encoders: vec![
+                Box::new(JsonEncoder),
+                Box::new(HtmlEncoder),
+                Box::new(FlatgeobufEncoder {
+                    index_max_features: config.flatgeobuf_index_max_features,
+                }),
+                Box::new(CsvEncoder::new(config)),
+                Box::new(GeopackageEncoder {
+                    max_features: config.geopackage_max_features,
+                }),
+                Box::new(GmlEncoder {
+                    profile: GmlProfile::Sf0,
+                }),
+                Box::new(GmlEncoder {
+                    profile: GmlProfile::Sf2,
+                }),
+            ],
+        }
+    }
+}
+
+/// Splits a media type or range into its lowercase essence and parameters, leaving out the
+/// quality and the extensions following it.
+fn parse_media_type(media_type: &str) -> (String, Vec<(String, String)>) {
+    let mut parts = media_type.split(';');
+    let essence = parts.next().unwrap_or_default().trim().to_ascii_lowercase();
+    let parameters = parts
+        .filter_map(|parameter| {
+            let (name, value) = parameter.split_once('=')?;
+            Some((
+                name.trim().to_ascii_lowercase(),
+                value.trim().trim_matches('"').to_string(),
+            ))
+        })
+        .take_while(|(name, _)| name != "q")
+        .collect();
+    (essence, parameters)
+}
+
+/// How specifically a media range of an `Accept` header matches a media type. A range with
+/// parameters only matches media types with the same values of these parameters.
+fn match_specificity(range: &str, media_type: &str) -> Option<u8> {
+    let (range, range_parameters) = parse_media_type(range);
+    let (media_type, parameters) = parse_media_type(media_type);
+    if !range_parameters.is_empty() {
+        let matches = range == media_type
+            && range_parameters
+                .iter()
+                .all(|parameter| parameters.contains(parameter));
+        return matches.then_some(4);
+    }
+    if range == media_type {
+        return Some(3);
+    }
+    let (range_type, range_subtype) = range.split_once('/')?;
+    let (main_type, _) = media_type.split_once('/')?;
+    match (range_type, range_subtype) {
+        ("*", "*") => Some(1),
+        (range_type, "*") if range_type == main_type => Some(2),
+        _ => None,
+    }
+}
+
+/// Parses an `Accept` header into its media ranges, with their parameters, and their quality.
+fn parse_accept(accept: &str) -> Vec<(String, f32)> {
+    accept
+        .split(',')
+        .filter_map(|range| {
+            let quality = range
+                .split(';')
+                .find_map(|parameter| parameter.trim().strip_prefix("q="))
+                .and_then(|q| q.trim().parse().ok())
+                .unwrap_or(1.0);
+            let media_range = range.trim();
+            (!media_range.is_empty()).then(|| (media_range.to_string(), quality))
+        })
+        .collect()
+}
+
+impl Encoders {
+    /// The encoders supporting a kind of resource, the default one first.
+    pub fn for_kind(&self, kind: ResourceKind) -> impl Iterator<Item = &dyn Encoder> {
+        self.encoders
+            .iter()
+            .map(|encoder| encoder.as_ref())
+            .filter(move |encoder| encoder.supports(kind))
+    }
+
+    pub fn is_default(&self, kind: ResourceKind, encoder: &dyn Encoder) -> bool {
+        self.for_kind(kind)
+            .next()
+            .is_some_and(|default| default.name() == encoder.name())
+    }
+
+    /// Picks the encoder of a response: the one named by the `f` parameter, otherwise the one
+    /// the `Accept` header prefers, ties going to the most specific media range and then to the
+    /// order of registration. Fails with 406 when no encoder is acceptable.
+    pub fn negotiate(
+        &self,
+        kind: ResourceKind,
+        f: Option<&str>,
+        headers: &HeaderMap,
+    ) -> Result<&dyn Encoder, AppError> {
+        if let Some(f) = f {
+            return self
+                .for_kind(kind)
+                .find(|encoder| encoder.name() == f)
+                .ok_or_else(|| {
+                    AppError::NotAcceptable(format!(
+                        "Format '{}' is not available for this resource, available formats are: {}",
+                        f,
+                        self.for_kind(kind)
+                            .map(|encoder| encoder.name())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ))
+                });
+        }
+
+        let Some(accept) = headers
+            .get(header::ACCEPT)
+            .and_then(|value| value.to_str().ok())
+        else {
+            return self.for_kind(kind).next().ok_or_else(|| {
+                AppError::NotAcceptable("No format is available for this resource".to_string())
+            });
+        };
+
+        let ranges = parse_accept(accept);
+        let mut best: Option<(&dyn Encoder, f32, u8)> = None;
+        for encoder in self.for_kind(kind) {
+            // The most specific range matching any of the media types decides the quality.
+            let score = encoder
+                .media_types(kind)
+                .iter()
+                .flat_map(|media_type| {
+                    ranges.iter().filter_map(|(range, quality)| {
+                        match_specificity(range, media_type)
+                            .map(|specificity| (specificity, *quality))
+                    })
+                })
+                .max_by(|a, b| a.0.cmp(&b.0).then(a.1.total_cmp(&b.1)));
+            if let Some((specificity, quality)) = score
+                && quality > 0.0
+                && best.is_none_or(|(_, best_quality, best_specificity)| {
+                    quality > best_quality
+                        || (quality == best_quality && specificity > best_specificity)
+                })
+            {
+                best = Some((encoder, quality, specificity));
+            }
+        }
+
+        best.map(|(encoder, _, _)| encoder).ok_or_else(|| {
+            AppError::NotAcceptable(format!(
+                "None of the accepted media types are available, available media types are: {}",
+                self.for_kind(kind)
+                    .map(|encoder| encoder.media_type(kind))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ))
+        })
+    }
+
+    /// Adds the `f` parameter selecting an encoder to a URL, unless it is the default one.
+    pub fn href(&self, kind: ResourceKind, href: &str, encoder: &dyn Encoder) -> String {
+        if self.is_default(kind, encoder) {
+            return href.to_string();
+        }
+        let separator = if href.contains('?') { '&' } else { '?' };
+        format!("{}{}f={}", href, separator, encoder.name())
+    }
+
+    /// Builds the `self` link of a resource in the selected encoding and the `alternate` links
+    /// to its other encodings.
+    pub fn links(&self, kind: ResourceKind, href: &str, selected: &dyn Encoder) -> Vec<Link> {
+        let mut links = vec![Link {
+            href: self.href(kind, href, selected),
+            rel: LinkRel::Self_,
+            type_: Some(selected.media_type(kind).to_string()),
+            title: Some("this document".to_string()),
+        }];
+        links.extend(
+            self.for_kind(kind)
+                .filter(|encoder| encoder.name() != selected.name())
+                .map(|encoder| Link {
+                    href: self.href(kind, href, encoder),
+                    rel: LinkRel::Alternate,
+                    type_: Some(encoder.media_type(kind).to_string()),
+                    title: Some(format!("this document as {}", encoder.title())),
+                }),
+        );
+        links
+    }
+
+    /// The conformance classes of all the encodings.
+    pub fn conformance_classes(&self) -> impl Iterator<Item = &'static str> {
+        self.encoders
+            .iter()
+            .flat_map(|encoder| encoder.conformance_classes().iter().copied())
+    }
+}
+
+/// Adds `Accept` to the `Vary` header of the responses, as their encoding is negotiated from it
+/// when the `f` parameter is missing, so that caches keep the encodings apart.
+pub async fn vary_accept(request: Request, next: Next) -> Response {
+    let mut response = next.run(request).await;
+    response
+        .headers_mut()
+        .append(header::VARY, HeaderValue::from_static("accept"));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, body::Body, routing::get};
+    use tower::ServiceExt;
+
+    fn encoders() -> Encoders {
+        let config: AppConfig = toml::from_str(
+            r#"
+            title = "test"
+            description = "test"
+            url_base = "http://localhost:3000"
+            collections = {}
+            "#,
+        )
+        .unwrap();
+        Encoders::new(&config)
+    }
+
+    #[tokio::test]
+    async fn test_vary_accept() {
+        let app = Router::new()
+            .route(
+                "/",
+                get(|| async { ([(header::VARY, "origin")], "landing page") }),
+            )
+            .layer(axum::middleware::from_fn(vary_accept));
+        let response = app
+            .oneshot(Request::builder().uri("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(
+            response
+                .headers()
+                .get_all(header::VARY)
+                .iter()
+                .collect::<Vec<_>>(),
+            ["origin", "accept"]
+        );
+    }
+
+    fn accept(value: &'static str) -> HeaderMap {
+        HeaderMap::from_iter([(header::ACCEPT, HeaderValue::from_static(value))])
+    }
+
+    fn negotiated(kind: ResourceKind, f: Option<&str>, headers: &HeaderMap) -> &'static str {
+        encoders()
+            .negotiate(kind, f, headers)
+            .map(|encoder| encoder.name())
+            .unwrap_or("none")
+    }
+
+    #[test]
+    fn test_negotiate() {
+        let browser = accept("text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8");
+        assert_eq!(negotiated(ResourceKind::Items, None, &browser), "html");
+        assert_eq!(
+            negotiated(ResourceKind::Items, Some("json"), &browser),
+            "json"
+        );
+        assert_eq!(
+            negotiated(ResourceKind::Items, None, &accept("*/*")),
+            "json"
+        );
+        assert_eq!(
+            negotiated(ResourceKind::Items, None, &HeaderMap::new()),
+            "json"
+        );
+        assert_eq!(
+            negotiated(
+                ResourceKind::Item,
+                None,
+                &accept("application/geo+json, text/html;q=0.5")
+            ),
+            "json"
+        );
+        assert_eq!(
+            negotiated(ResourceKind::Items, None, &accept("application/flatgeobuf")),
+            "fgb"
+        );
+        assert_eq!(
+            negotiated(
+                ResourceKind::Items,
+                None,
+                &accept(
+                    "application/gml+xml; version=3.2; profile=\"http://www.opengis.net/def/profile/ogc/2.0/gml-sf2\""
+                )
+            ),
+            "gmlsf2"
+        );
+        assert_eq!(
+            negotiated(ResourceKind::Item, None, &accept("application/gml+xml")),
+            "gmlsf0"
+        );
+        assert_eq!(
+            negotiated(
+                ResourceKind::Items,
+                None,
+                &accept("application/gml+xml;version=2.1, application/geo+json;q=0.5")
+            ),
+            "json"
+        );
+        assert_eq!(
+            negotiated(ResourceKind::Landing, None, &accept("text/*")),
+            "html"
+        );
+        assert_eq!(
+            negotiated(ResourceKind::Landing, None, &accept("text/html;q=0, */*")),
+            "json"
+        );
+    }
+
+    #[test]
+    fn test_negotiate_not_acceptable() {
+        let encoders = encoders();
+        let Err(AppError::NotAcceptable(message)) =
+            encoders.negotiate(ResourceKind::Items, Some("xml"), &HeaderMap::new())
+        else {
+            panic!("unknown formats must be rejected");
+        };
+        assert_eq!(
+            message,
+            "Format 'xml' is not available for this resource, available formats are: json, html, fgb, csv, gpkg, gmlsf0, gmlsf2"
+        );
+
+        let Err(AppError::NotAcceptable(_)) =
+            encoders.negotiate(ResourceKind::Items, None, &accept("application/xml"))
+        else {
+            panic!("unavailable media types must be rejected");
+        };
+    }
+
+    #[test]
+    fn test_links() {
+        let encoders = encoders();
+        let html = encoders
+            .for_kind(ResourceKind::Items)
+            .find(|encoder| encoder.name() == "html")
+            .unwrap();
+        let links = encoders.links(
+            ResourceKind::Items,
+            "http://localhost:3000/collections/countries/items",
+            html,
+        );
+        let links: Vec<_> = links
+            .iter()
+            .map(|link| {
+                (
+                    link.rel.as_str(),
+                    link.href.as_str(),
+                    link.type_.as_deref().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            links,
+            [
+                (
+                    "self",
+                    "http://localhost:3000/collections/countries/items?f=html",
+                    "text/html"
+                ),
+                (
+                    "alternate",
+                    "http://localhost:3000/collections/countries/items",
+                    "application/geo+json"
+                ),
+                (
+                    "alternate",
+                    "http://localhost:3000/collections/countries/items?f=fgb",
+                    "application/flatgeobuf"
+                ),
+                (
+                    "alternate",
+                    "http://localhost:3000/collections/countries/items?f=csv",
+                    "text/csv"
+                ),
+                (
+                    "alternate",
+                    "http://localhost:3000/collections/countries/items?f=gpkg",
+                    "application/geopackage+sqlite3"
+                ),
+                (
+                    "alternate",
+                    "http://localhost:3000/collections/countries/items?f=gmlsf0",
+                    "application/gml+xml; version=3.2; profile=http://www.opengis.net/def/profile/ogc/2.0/gml-sf0"
+                ),
+                (
+                    "alternate",
+                    "http://localhost:3000/collections/countries/items?f=gmlsf2",
+                    "application/gml+xml; version=3.2; profile=http://www.opengis.net/def/profile/ogc/2.0/gml-sf2"
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,270 @@
+use crate::models::Problem;
+use axum::{
+    body::to_bytes,
+    extract::Request,
+    http::{HeaderValue, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::fmt;
+
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+/// The largest body of a plain text error response that is turned into the detail of a problem.
+const MAX_DETAIL_BYTES: usize = 4096;
+
+/// An error of a request, from the storage or a handler, answered as an RFC 7807 problem.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AppError {
+    /// The request has invalid parameters or an invalid body.
+    BadRequest(String),
+    /// The collection, feature or other resource does not exist.
+    NotFound(String),
+    MethodNotAllowed(String),
+    /// No encoding of the resource matches the `f` parameter or the `Accept` header.
+    NotAcceptable(String),
+    /// A write conflicts with the stored features.
+    Conflict(String),
+    /// The feature changed since the ETag given in `If-Match`.
+    PreconditionFailed(String),
+    /// A write to a feature is missing `If-Match`.
+    PreconditionRequired(String),
+    /// An unexpected failure, such as a database error. Its message is only logged, along with
+    /// a correlation ID that is all the client gets.
+    Internal(String),
+}
+
+impl AppError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::BadRequest(_) => StatusCode::BAD_REQUEST,
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::MethodNotAllowed(_) => StatusCode::METHOD_NOT_ALLOWED,
+            Self::NotAcceptable(_) => StatusCode::NOT_ACCEPTABLE,
+            Self::Conflict(_) => StatusCode::CONFLICT,
+            Self::PreconditionFailed(_) => StatusCode::PRECONDITION_FAILED,
+            Self::PreconditionRequired(_) => StatusCode::PRECONDITION_REQUIRED,
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            Self::BadRequest(message)
+            | Self::NotFound(message)
+            | Self::MethodNotAllowed(message)
+            | Self::NotAcceptable(message)
+            | Self::Conflict(message)
+            | Self::PreconditionFailed(message)
+            | Self::PreconditionRequired(message)
+            | Self::Internal(message) => message,
+        }
+    }
+
+    /// The problem details sent to the client. Internal errors are logged under a new
+    /// correlation ID, which replaces their message.
+    pub fn to_problem(&self) -> Problem {
+        let status = self.status();
+        let (detail, correlation_id) = match self {
+            Self::Internal(message) => {
+                let correlation_id = format!("{:032x}", fastrand::u128(..));
+                tracing::error!(correlation_id, "{}", message);
+                (
+                    format!(
+                        "An unexpected error occurred, see correlation ID {}",
+                        correlation_id
+                    ),
+                    Some(correlation_id),
+                )
+            }
+            _ => (self.message().to_string(), None),
+        };
+        problem(status, Some(detail), correlation_id)
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl std::error::Error for AppError {}
+
+/// A query returning no row means the requested resource does not exist. Any other database
+/// error is internal.
+impl From<sqlx::Error> for AppError {
+    fn from(e: sqlx::Error) -> Self {
+        match e {
+            sqlx::Error::RowNotFound => Self::NotFound("Resource not found".to_string()),
+            e => Self::Internal(e.to_string()),
+        }
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let problem = self.to_problem();
+        let mut response = problem_response(&problem);
+        response.extensions_mut().insert(problem);
+        response
+    }
+}
+
+fn problem(status: StatusCode, detail: Option<String>, correlation_id: Option<String>) -> Problem {
+    Problem {
+        type_: "about:blank".to_string(),
+        title: status.canonical_reason().unwrap_or("Error").to_string(),
+        status: status.as_u16(),
+        detail,
+        instance: None,
+        correlation_id,
+    }
+}
+
+fn problem_response(problem: &Problem) -> Response {
+    let status = StatusCode::from_u16(problem.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    let body = serde_json::to_vec(problem).expect("problems always serialize");
+    (
+        status,
+        [(header::CONTENT_TYPE, HeaderValue::from_static(PROBLEM_JSON))],
+        body,
+    )
+        .into_response()
+}
+
+/// Sets the `instance` of the problems answered by the handlers to the request path, and turns
+/// the plain text errors of the extractors and the router, such as an invalid query string or an
+/// unsupported method, into problems.
+pub async fn problem_details(request: Request, next: Next) -> Response {
+    let instance = request.uri().path().to_string();
+    let response = next.run(request).await;
+    if !(response.status().is_client_error() || response.status().is_server_error()) {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let mut problem = match parts.extensions.remove::<Problem>() {
+        Some(problem) => problem,
+        None => {
+            let is_plain_text = parts.headers.get(header::CONTENT_TYPE).is_none_or(|value| {
+                value
+                    .to_str()
+                    .is_ok_and(|value| value.starts_with("text/plain"))
+            });
+            if !is_plain_text {
+                return Response::from_parts(parts, body);
+            }
+            let detail = to_bytes(body, MAX_DETAIL_BYTES)
+                .await
+                .ok()
+                .map(|bytes| String::from_utf8_lossy(&bytes).trim().to_string())
+                .filter(|detail| !detail.is_empty());
+            problem(parts.status, detail, None)
+        }
+    };
+    problem.instance = Some(instance);
+
+    let mut response = problem_response(&problem);
+    // Keep headers such as `Allow` of 405 responses.
+    for (name, value) in &parts.headers {
+        if name != header::CONTENT_TYPE && name != header::CONTENT_LENGTH {
+            response.headers_mut().append(name, value.clone());
+        }
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, body::Body, routing::get};
+    use tower::ServiceExt;
+
+    async fn problem_of(response: Response) -> serde_json::Value {
+        assert_eq!(response.headers()[header::CONTENT_TYPE], PROBLEM_JSON);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_into_response() {
+        let response =
+            AppError::NotFound("Collection rivers not found".to_string()).into_response();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            problem_of(response).await,
+            serde_json::json!({
+                "type": "about:blank",
+                "title": "Not Found",
+                "status": 404,
+                "detail": "Collection rivers not found"
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_internal_errors_are_hidden() {
+        let response =
+            AppError::Internal("password authentication failed".to_string()).into_response();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let problem = problem_of(response).await;
+        let correlation_id = problem["correlationId"].as_str().unwrap();
+        assert_eq!(correlation_id.len(), 32);
+        let detail = problem["detail"].as_str().unwrap();
+        assert!(detail.contains(correlation_id));
+        assert!(!detail.contains("password"));
+    }
+
+    #[test]
+    fn test_from_sqlx_error() {
+        assert_eq!(
+            AppError::from(sqlx::Error::RowNotFound).status(),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            AppError::from(sqlx::Error::PoolTimedOut).status(),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+
+    #[tokio::test]
+    async fn test_problem_details() {
+        let app = Router::new()
+            .route(
+                "/collections/{collection_id}",
+                get(|| async { AppError::BadRequest("Invalid limit".to_string()) }),
+            )
+            .route(
+                "/text",
+                get(|| async { (StatusCode::BAD_REQUEST, "Failed to deserialize") }),
+            )
+            .layer(axum::middleware::from_fn(problem_details));
+        let request = |uri: &str, method: &str| {
+            Request::builder()
+                .uri(uri)
+                .method(method)
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = app
+            .clone()
+            .oneshot(request("/collections/rivers", "GET"))
+            .await
+            .unwrap();
+        let problem = problem_of(response).await;
+        assert_eq!(problem["detail"], "Invalid limit");
+        assert_eq!(problem["instance"], "/collections/rivers");
+
+        let response = app.clone().oneshot(request("/text", "GET")).await.unwrap();
+        let problem = problem_of(response).await;
+        assert_eq!(problem["status"], 400);
+        assert_eq!(problem["detail"], "Failed to deserialize");
+
+        let response = app.oneshot(request("/text", "POST")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(response.headers()[header::ALLOW], "GET,HEAD");
+        assert_eq!(problem_of(response).await["title"], "Method Not Allowed");
+    }
+}
@@ -1,124 +1,301 @@
 use crate::{
-    models::{Collection, Collections, Conformance, LandingPage, Link, LinkRel},
+    config::CollectionConfig,
+    encoders::{Encoder, Encoders, Resource, ResourceKind},
+    error::AppError,
+    models::{
+        Collection, Collections, Conformance, Extent, FormatParams, LandingPage, Link, LinkRel,
+    },
     state::AppState,
 };
 use axum::{
-    Json,
-    extract::{Path, State},
-    http::StatusCode,
+    extract::{Path, Query, State},
+    http::HeaderMap,
+    response::Response,
 };
+use futures_util::{StreamExt, stream};
 
-fn build_collection(url_base: &str, id: &str) -> Collection {
+/// The number of collections whose extents are loaded at a time when listing the collections.
+const EXTENT_CONCURRENCY: usize = 8;
+
+fn build_collection(
+    url_base: &str,
+    id: &str,
+    config: &CollectionConfig,
+    extent: Option<Extent>,
+    encoders: &Encoders,
+    encoder: &dyn Encoder,
+) -> Collection {
     let collection_url = format!("{}/collections/{}", url_base, id);
+    let mut links = encoders.links(ResourceKind::Collection, &collection_url, encoder);
+    links.extend([
+        Link {
+            href: format!("{}/items", collection_url),
+            rel: LinkRel::Items,
+            type_: Some("application/geo+json".to_string()),
+            title: Some("Items".to_string()),
+        },
+        Link {
+            href: format!("{}/queryables", collection_url),
+            rel: LinkRel::Queryables,
+            type_: Some("application/schema+json".to_string()),
+            title: Some("Queryables".to_string()),
+        },
+        Link {
+            href: format!("{}/sortables", collection_url),
+            rel: LinkRel::Sortables,
+            type_: Some("application/schema+json".to_string()),
+            title: Some("Sortables".to_string()),
+        },
+        Link {
+            href: format!("{}/schema.xsd", collection_url),
+            rel: LinkRel::DescribedBy,
+            type_: Some("application/xml".to_string()),
+            title: Some("XML schema of the features".to_string()),
+        },
+        Link {
+            href: format!("{}/tiles", collection_url),
+            rel: LinkRel::TilesetsVector,
+            type_: Some("application/json".to_string()),
+            title: Some("Vector tiles".to_string()),
+        },
+    ]);
+    for (link, rel) in [
+        (&config.license, LinkRel::License),
+        (&config.describedby, LinkRel::DescribedBy),
+    ] {
+        if let Some(link) = link {
+            links.push(Link {
+                href: link.href.clone(),
+                rel,
+                type_: link.type_.clone(),
+                title: link.title.clone(),
+            });
+        }
+    }
+
     Collection {
         id: id.to_string(),
-        title: id.to_string(),
-        description: format!("Collection of {}", id),
-        links: vec![
-            Link {
-                href: collection_url.clone(),
-                rel: LinkRel::Self_,
-                type_: Some("application/json".to_string()),
-                title: Some("this document".to_string()),
-            },
-            Link {
-                href: format!("{}/items", collection_url),
-                rel: LinkRel::Items,
-                type_: Some("application/geo+json".to_string()),
-                title: Some("Items".to_string()),
-            },
-        ],
+        title: config.title(id).to_string(),
+        description: config.description(id),
+        keywords: config.keywords.clone(),
+        attribution: config.attribution.clone(),
+        contact: config.contact.clone(),
+        links,
+        crs: config
+            .supported_crs()
+            .iter()
+            .map(|crs| crs.uri().to_string())
+            .collect(),
+        storage_crs: config.storage_crs().uri().to_string(),
+        extent,
     }
 }
 
+/// Builds a collection document along with its extent.
+async fn load_collection(
+    state: &AppState,
+    id: &str,
+    config: &CollectionConfig,
+    encoder: &dyn Encoder,
+) -> Result<Collection, AppError> {
+    let extent = state.store.get_extent(id).await?;
+    Ok(build_collection(
+        &state.config.url_base,
+        id,
+        config,
+        extent,
+        &state.encoders,
+        encoder,
+    ))
+}
+
 #[utoipa::path(
     get,
     path = "/",
+    params(FormatParams),
     responses(
         (status = 200, description = "Landing page", body = LandingPage)
     )
 )]
-pub async fn get_landing_page(State(state): State<AppState>) -> Json<LandingPage> {
+pub async fn get_landing_page(
+    State(state): State<AppState>,
+    Query(params): Query<FormatParams>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let encoder = state
+        .encoders
+        .negotiate(ResourceKind::Landing, params.f.as_deref(), &headers)?;
     let url_base = &state.config.url_base;
-    Json(LandingPage {
+    let mut links = state
+        .encoders
+        .links(ResourceKind::Landing, &format!("{}/", url_base), encoder);
+    links.extend([
+        Link {
+            href: format!("{}/api/openapi.json", url_base),
+            rel: LinkRel::ServiceDesc,
+            type_: Some("application/vnd.oai.openapi+json;version=3.0".to_string()),
+            title: Some("the API definition".to_string()),
+        },
+        Link {
+            href: format!("{}/api.html", url_base),
+            rel: LinkRel::ServiceDoc,
+            type_: Some("text/html".to_string()),
+            title: Some("the API documentation".to_string()),
+        },
+        Link {
+            href: format!("{}/conformance", url_base),
+            rel: LinkRel::Conformance,
+            type_: Some("application/json".to_string()),
+            title: Some("OGC API conformance classes implemented by this server".to_string()),
+        },
+        Link {
+            href: format!("{}/collections", url_base),
+            rel: LinkRel::Data,
+            type_: Some("application/json".to_string()),
+            title: Some("Information about the feature collections".to_string()),
+        },
+        Link {
+            href: format!("{}/tileMatrixSets", url_base),
+            rel: LinkRel::TilingSchemes,
+            type_: Some("application/json".to_string()),
+            title: Some("The tile matrix sets tiles are available in".to_string()),
+        },
+    ]);
+    let page = LandingPage {
         title: state.config.title.clone(),
         description: state.config.description.clone(),
-        links: vec![
-            Link {
-                href: format!("{}/", url_base),
-                rel: LinkRel::Self_,
-                type_: Some("application/json".to_string()),
-                title: Some("this document".to_string()),
-            },
-            Link {
-                href: format!("{}/api/openapi.json", url_base),
-                rel: LinkRel::ServiceDesc,
-                type_: Some("application/vnd.oai.openapi+json;version=3.0".to_string()),
-                title: Some("the API definition".to_string()),
-            },
-            Link {
-                href: format!("{}/api.html", url_base),
-                rel: LinkRel::ServiceDoc,
-                type_: Some("text/html".to_string()),
-                title: Some("the API documentation".to_string()),
-            },
-            Link {
-                href: format!("{}/conformance", url_base),
-                rel: LinkRel::Conformance,
-                type_: Some("application/json".to_string()),
-                title: Some("OGC API conformance classes implemented by this server".to_string()),
-            },
-            Link {
-                href: format!("{}/collections", url_base),
-                rel: LinkRel::Data,
-                type_: Some("application/json".to_string()),
-                title: Some("Information about the feature collections".to_string()),
-            },
-        ],
-    })
+        links,
+    };
+
+    encoder.encode(Resource::Landing(&page), url_base)
 }
 
 #[utoipa::path(
     get,
     path = "/conformance",
+    params(FormatParams),
     responses(
         (status = 200, description = "Conformance page", body = Conformance)
     )
 )]
-pub async fn get_conformance() -> Json<Conformance> {
-    Json(Conformance {
-        conforms_to: vec![
-            "http://www.opengis.net/spec/ogcapi-features-1/1.0/conf/core".to_string(),
-            "http://www.opengis.net/spec/ogcapi-features-1/1.0/conf/oas30".to_string(),
-            "http://www.opengis.net/spec/ogcapi-features-1/1.0/conf/geojson".to_string(),
-        ],
-    })
+pub async fn get_conformance(
+    State(state): State<AppState>,
+    Query(params): Query<FormatParams>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let encoder =
+        state
+            .encoders
+            .negotiate(ResourceKind::Conformance, params.f.as_deref(), &headers)?;
+    let mut conforms_to = vec![
+        "http://www.opengis.net/spec/ogcapi-features-1/1.0/conf/core".to_string(),
+        "http://www.opengis.net/spec/ogcapi-features-1/1.0/conf/oas30".to_string(),
+        "http://www.opengis.net/spec/ogcapi-features-2/1.0/conf/crs".to_string(),
+        "http://www.opengis.net/spec/ogcapi-features-3/1.0/conf/filter".to_string(),
+        "http://www.opengis.net/spec/ogcapi-features-3/1.0/conf/features-filter".to_string(),
+        "http://www.opengis.net/spec/ogcapi-features-3/1.0/conf/queryables".to_string(),
+        "http://www.opengis.net/spec/cql2/1.0/conf/cql2-text".to_string(),
+        "http://www.opengis.net/spec/cql2/1.0/conf/cql2-json".to_string(),
+        "http://www.opengis.net/spec/cql2/1.0/conf/basic-cql2".to_string(),
+        "http://www.opengis.net/spec/cql2/1.0/conf/advanced-comparison-operators".to_string(),
+        "http://www.opengis.net/spec/cql2/1.0/conf/basic-spatial-functions".to_string(),
+        "http://www.opengis.net/spec/cql2/1.0/conf/basic-spatial-functions-plus".to_string(),
+        "http://www.opengis.net/spec/cql2/1.0/conf/spatial-functions".to_string(),
+        "http://www.opengis.net/spec/ogcapi-features-5/1.0/conf/sortables".to_string(),
+        "http://www.opengis.net/spec/ogcapi-records-1/1.0/conf/sorting".to_string(),
+        "http://www.opengis.net/spec/ogcapi-tiles-1/1.0/conf/core".to_string(),
+        "http://www.opengis.net/spec/ogcapi-tiles-1/1.0/conf/tileset".to_string(),
+        "http://www.opengis.net/spec/ogcapi-tiles-1/1.0/conf/tilesets-list".to_string(),
+        "http://www.opengis.net/spec/ogcapi-tiles-1/1.0/conf/geodata-tilesets".to_string(),
+        "http://www.opengis.net/spec/ogcapi-tiles-1/1.0/conf/mvt".to_string(),
+        "http://www.opengis.net/spec/tms/2.0/conf/tilematrixset".to_string(),
+        "http://www.opengis.net/spec/tms/2.0/conf/json-tilematrixset".to_string(),
+    ];
+    conforms_to.extend(
+        state
+            .encoders
+            .conformance_classes()
+            .map(|class| class.to_string()),
+    );
+    if state.config.collections.values().any(|c| c.writable) {
+        conforms_to.extend([
+            "http://www.opengis.net/spec/ogcapi-features-4/1.0/conf/create-replace-delete"
+                .to_string(),
+            "http://www.opengis.net/spec/ogcapi-features-4/1.0/conf/update".to_string(),
+            "http://www.opengis.net/spec/ogcapi-features-4/1.0/conf/features".to_string(),
+        ]);
+    }
+
+    let url_base = &state.config.url_base;
+    let conformance = Conformance {
+        conforms_to,
+        links: state.encoders.links(
+            ResourceKind::Conformance,
+            &format!("{}/conformance", url_base),
+            encoder,
+        ),
+    };
+
+    encoder.encode(Resource::Conformance(&conformance), url_base)
 }
 
 #[utoipa::path(
     get,
     path = "/collections",
+    params(FormatParams),
     responses(
         (status = 200, description = "List of collections", body = Collections)
     )
 )]
-pub async fn get_collections(State(state): State<AppState>) -> Json<Collections> {
+pub async fn get_collections(
+    State(state): State<AppState>,
+    Query(params): Query<FormatParams>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let encoder =
+        state
+            .encoders
+            .negotiate(ResourceKind::Collections, params.f.as_deref(), &headers)?;
     let url_base = &state.config.url_base;
-    let collections = state
+    // A collection whose extent cannot be computed is listed without one, rather than failing
+    // the listing of all the others.
+    let loads: Vec<_> = state
         .config
         .collections
-        .keys()
-        .map(|id| build_collection(url_base, id))
+        .iter()
+        .map(|(id, config)| {
+            let state = &state;
+            async move {
+                let extent = state.store.get_extent(id).await.unwrap_or_else(|e| {
+                    tracing::warn!("Could not compute the extent of collection {}: {}", id, e);
+                    None
+                });
+                build_collection(url_base, id, config, extent, &state.encoders, encoder)
+            }
+        })
         .collect();
+    let collections = stream::iter(loads)
+        .buffered(EXTENT_CONCURRENCY)
+        .collect()
+        .await;
+    let collections = Collections {
+        links: state.encoders.links(
+            ResourceKind::Collections,
+            &format!("{}/collections", url_base),
+            encoder,
+        ),
+        collections,
+    };
 
-    Json(Collections { collections })
+    encoder.encode(Resource::Collections(&collections), url_base)
 }
 
 #[utoipa::path(
     get,
     path = "/collections/{collection_id}",
     params(
-        ("collection_id" = String, Path, description = "ID of the collection")
+        ("collection_id" = String, Path, description = "ID of the collection"),
+        FormatParams
     ),
     responses(
         (status = 200, description = "Collection details", body = Collection),
@@ -128,15 +305,109 @@ pub async fn get_collections(State(state): State<AppState>) -> Json<Collections>
 pub async fn get_collection(
     State(state): State<AppState>,
     Path(collection_id): Path<String>,
-) -> Result<Json<Collection>, (StatusCode, String)> {
-    let url_base = &state.config.url_base;
-    if state.config.collections.contains_key(&collection_id) {
-        let collection = build_collection(url_base, &collection_id);
-        Ok(Json(collection))
+    Query(params): Query<FormatParams>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    if let Some(config) = state.config.collections.get(&collection_id) {
+        let encoder =
+            state
+                .encoders
+                .negotiate(ResourceKind::Collection, params.f.as_deref(), &headers)?;
+        let collection = load_collection(&state, &collection_id, config, encoder).await?;
+        encoder.encode(Resource::Collection(&collection), &state.config.url_base)
     } else {
-        Err((
-            StatusCode::NOT_FOUND,
-            format!("Collection {} not found", collection_id),
-        ))
+        Err(AppError::NotFound(format!(
+            "Collection {} not found",
+            collection_id
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AppConfig;
+
+    #[test]
+    fn test_build_collection_metadata() {
+        let config: AppConfig = toml::from_str(
+            r#"
+            title = "test"
+            description = "test"
+            url_base = "http://localhost:3000"
+
+            [collections.rivers]
+            table = "rivers"
+            id_column = "id"
+            geometry_column = "geom"
+            properties = []
+
+            [collections.countries]
+            title = "Countries"
+            description = "Countries of the world"
+            keywords = ["boundaries"]
+            attribution = "Made with Natural Earth"
+            contact = { name = "GIS team", email = "gis@example.com" }
+            license = { href = "https://creativecommons.org/licenses/by/4.0/", title = "CC BY 4.0" }
+            describedby = { href = "https://example.com/countries.html", type = "text/html" }
+            table = "countries"
+            id_column = "id"
+            geometry_column = "geom"
+            properties = []
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.collections.keys().collect::<Vec<_>>(),
+            ["rivers", "countries"]
+        );
+
+        let encoders = Encoders::new(&config);
+        let json = encoders.for_kind(ResourceKind::Collection).next().unwrap();
+        let rivers = build_collection(
+            &config.url_base,
+            "rivers",
+            &config.collections["rivers"],
+            None,
+            &encoders,
+            json,
+        );
+        assert_eq!(rivers.title, "rivers");
+        assert_eq!(rivers.description, "Collection of rivers");
+        assert_eq!(rivers.links.len(), 7);
+
+        let countries = build_collection(
+            &config.url_base,
+            "countries",
+            &config.collections["countries"],
+            None,
+            &encoders,
+            json,
+        );
+        let json = serde_json::to_value(&countries).unwrap();
+        assert_eq!(json["title"], "Countries");
+        assert_eq!(json["description"], "Countries of the world");
+        assert_eq!(json["keywords"], serde_json::json!(["boundaries"]));
+        assert_eq!(json["attribution"], "Made with Natural Earth");
+        assert_eq!(
+            json["contact"],
+            serde_json::json!({"name": "GIS team", "email": "gis@example.com"})
+        );
+        assert_eq!(
+            json["links"][7],
+            serde_json::json!({
+                "href": "https://creativecommons.org/licenses/by/4.0/",
+                "rel": "license",
+                "title": "CC BY 4.0"
+            })
+        );
+        assert_eq!(
+            json["links"][8],
+            serde_json::json!({
+                "href": "https://example.com/countries.html",
+                "rel": "describedby",
+                "type": "text/html"
+            })
+        );
     }
 }
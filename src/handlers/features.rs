@@ -1,64 +1,327 @@
+use super::transactions;
 use crate::{
+    encoders::{Encoder, Encoders, Item, Items, ItemsStream, Resource, ResourceKind},
+    error::AppError,
     models::{
-        DocFeatureCollectionSchema, DocFeatureSchema, GetItemsParams, Link, LinkRel,
-        OgcApiFeatureCollection,
+        Crs, DocFeatureCollectionSchema, DocFeatureSchema, FilterLang, GetItemParams,
+        GetItemsParams, Link, LinkRel, OgcApiFeatureCollection, QueryableProperty,
     },
     state::AppState,
-    storage::FeaturesWithCount,
+    storage::{FeatureWithEtag, FeaturesWithCount},
 };
 use axum::{
     Json,
     extract::{Path, Query, State},
-    http::{HeaderMap, StatusCode},
+    http::{HeaderMap, HeaderName, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
 };
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
 
-fn build_ogc_api_feature_collection(
-    features_with_count: FeaturesWithCount,
-    headers: &HeaderMap,
+const CONTENT_CRS: HeaderName = HeaderName::from_static("content-crs");
+
+type WithContentCrs<T> = ([(HeaderName, HeaderValue); 1], T);
+
+/// Resolves the requested CRS, falling back to CRS84, and checks that the collection supports it.
+pub(super) fn resolve_crs(
+    state: &AppState,
+    collection_id: &str,
+    requested: Option<&Crs>,
+) -> Result<Crs, AppError> {
+    let collection = state
+        .config
+        .collections
+        .get(collection_id)
+        .ok_or_else(|| AppError::NotFound(format!("Collection {} not found", collection_id)))?;
+    let crs = requested.cloned().unwrap_or_else(Crs::crs84);
+
+    if collection.supported_crs().contains(&crs) {
+        Ok(crs)
+    } else {
+        Err(AppError::BadRequest(format!(
+            "CRS {} is not supported by collection {}",
+            crs, collection_id
+        )))
+    }
+}
+
+/// Sets the page limit of the items of a collection, from its default when not requested, lowered
+/// to its maximum.
+fn resolve_limit(
+    state: &AppState,
     collection_id: &str,
     params: &GetItemsParams,
-) -> OgcApiFeatureCollection {
-    let limit = params.limit.unwrap_or(10);
-    let offset = params.offset.unwrap_or(0);
-
-    let host = headers
-        .get("host")
-        .map(|h| h.to_str().unwrap_or(""))
-        .unwrap_or("");
-    let scheme = headers
-        .get("x-forwarded-proto")
-        .map(|h| h.to_str().unwrap_or("http"))
-        .unwrap_or("http");
-    let base_url = format!("{}://{}/", scheme, host);
-
-    let mut links = vec![Link {
-        href: format!("{}collections/{}/items", base_url, collection_id),
-        rel: LinkRel::Self_,
-        type_: Some("application/geo+json".to_string()),
-        title: Some("this document".to_string()),
-    }];
-
-    if features_with_count.number_matched > (offset + limit) {
-        let next_offset = offset + limit;
-        links.push(Link {
-            href: format!(
-                "{}collections/{}/items?limit={}&offset={}{}",
+) -> Result<GetItemsParams, AppError> {
+    let collection = state
+        .config
+        .collections
+        .get(collection_id)
+        .ok_or_else(|| AppError::NotFound(format!("Collection {} not found", collection_id)))?;
+    let (default, max) = state.config.limits(Some(collection));
+    Ok(GetItemsParams {
+        limit: Some(params.page_limit(default, max)),
+        ..params.clone()
+    })
+}
+
+fn with_content_crs<T>(crs: &Crs, body: T) -> WithContentCrs<T> {
+    let value =
+        HeaderValue::from_str(&format!("<{}>", crs)).expect("CRS URIs are valid header values");
+    ([(CONTENT_CRS, value)], body)
+}
+
+/// Formats an entity tag as the quoted value of an `ETag` header.
+pub(super) fn etag_header(etag: &str) -> (HeaderName, HeaderValue) {
+    let value =
+        HeaderValue::from_str(&format!("\"{}\"", etag)).expect("ETags are valid header values");
+    (header::ETAG, value)
+}
+
+/// The ETag of a representation of a feature. Writes are checked against the ETag of the feature,
+/// which is the one of its default representation: in the default encoding and CRS, with all its
+/// properties and its geometry. The other representations append the encoding and the parameters
+/// they differ by.
+fn item_etag(
+    mut etag: String,
+    params: &GetItemParams,
+    crs: &Crs,
+    encoders: &Encoders,
+    encoder: &dyn Encoder,
+) -> String {
+    if !encoders.is_default(ResourceKind::Item, encoder) {
+        etag = format!("{}-{}", etag, encoder.name());
+    }
+
+    let mut variant = Vec::new();
+    if *crs != Crs::crs84() {
+        variant.push(format!("crs={}", crs));
+    }
+    if let Some(properties) = params.properties() {
+        variant.push(format!("properties={}", properties.join(",")));
+    }
+    if params.skip_geometry.unwrap_or(false) {
+        variant.push("skipGeometry".to_string());
+    }
+    if variant.is_empty() {
+        etag
+    } else {
+        // Encoded, as ETags are limited to visible ASCII characters other than quotes.
+        format!("{}-{}", etag, URL_SAFE_NO_PAD.encode(variant.join("&")))
+    }
+}
+
+/// Whether the `If-None-Match` header matches the ETag, with the weak comparison of RFC 9110.
+fn if_none_match(headers: &HeaderMap, etag: &str) -> bool {
+    let quoted = format!("\"{}\"", etag);
+    headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| {
+            value
+                .split(',')
+                .map(str::trim)
+                .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == quoted)
+        })
+}
+
+/// Where a page link points to: a plain offset, or a keyset cursor for `next` links.
+enum PagePosition<'a> {
+    Offset(u64),
+    Cursor(&'a str),
+}
+
+fn build_items_query(
+    params: &GetItemsParams,
+    limit: u64,
+    position: PagePosition<'_>,
+    f: Option<&str>,
+) -> String {
+    let mut query = vec![("limit", limit.to_string())];
+    match position {
+        PagePosition::Offset(offset) => query.push(("offset", offset.to_string())),
+        PagePosition::Cursor(cursor) => query.push(("cursor", cursor.to_string())),
+    }
+    if let Some(bbox) = &params.bbox {
+        let bbox = bbox
+            .iter()
+            .map(|c| c.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        query.push(("bbox", bbox));
+    }
+    if let Some(bbox_crs) = &params.bbox_crs {
+        query.push(("bbox-crs", bbox_crs.to_string()));
+    }
+    if let Some(crs) = &params.crs {
+        query.push(("crs", crs.to_string()));
+    }
+    if let Some(filter) = &params.filter {
+        query.push(("filter", filter.clone()));
+        query.push((
+            "filter-lang",
+            params.filter_lang.unwrap_or_default().as_str().to_string(),
+        ));
+    }
+    if let Some(sortby) = &params.sortby {
+        query.push(("sortby", sortby.clone()));
+    }
+    if let Some(properties) = &params.properties {
+        query.push(("properties", properties.clone()));
+    }
+    if let Some(skip_geometry) = params.skip_geometry {
+        query.push(("skipGeometry", skip_geometry.to_string()));
+    }
+    if let Some(datetime) = &params.datetime {
+        query.push(("datetime", datetime.clone()));
+    }
+    for (property, value) in &params.property_filters {
+        query.push((property, value.clone()));
+    }
+    if let Some(f) = f {
+        query.push(("f", f.to_string()));
+    }
+    serde_urlencoded::to_string(query).expect("query pairs always encode")
+}
+
+/// The query of the links to a feature, with the parameters of the request but the encoding.
+fn build_item_query(params: &GetItemParams) -> String {
+    let mut query = Vec::new();
+    if let Some(crs) = &params.crs {
+        query.push(("crs", crs.to_string()));
+    }
+    if let Some(properties) = &params.properties {
+        query.push(("properties", properties.clone()));
+    }
+    if let Some(skip_geometry) = params.skip_geometry {
+        query.push(("skipGeometry", skip_geometry.to_string()));
+    }
+    serde_urlencoded::to_string(query).expect("query pairs always encode")
+}
+
+/// The links of a page of items. The `next` link is only known once the page is read, the
+/// others are settled before.
+struct PageLinks {
+    base_url: String,
+    collection_id: String,
+    params: GetItemsParams,
+    limit: u64,
+    offset: u64,
+    number_matched: u64,
+    /// The `f` parameter of the page links.
+    f: Option<&'static str>,
+    media_type: &'static str,
+    /// The `self` and `alternate` links.
+    links: Vec<Link>,
+}
+
+impl PageLinks {
+    fn new(
+        url_base: &str,
+        collection_id: &str,
+        params: &GetItemsParams,
+        offset: u64,
+        number_matched: u64,
+        encoders: &Encoders,
+        encoder: &dyn Encoder,
+    ) -> Self {
+        // Built from the configured base URL, like the item links and the `Location` of created
+        // features, so that they agree behind a proxy.
+        let base_url = format!("{}/", url_base);
+        // Page links keep the encoding, unless it is the default one and was negotiated.
+        let f = (params.f.is_some() || !encoders.is_default(ResourceKind::Items, encoder))
+            .then(|| encoder.name());
+        let limit = params
+            .limit
+            .expect("the limit is resolved by collection_items");
+        // The `self` and `alternate` links keep the parameters of the request but the encoding.
+        let position = match &params.cursor {
+            Some(cursor) => PagePosition::Cursor(cursor),
+            None => PagePosition::Offset(offset),
+        };
+        let links = encoders.links(
+            ResourceKind::Items,
+            &format!(
+                "{}collections/{}/items?{}",
                 base_url,
                 collection_id,
-                limit,
-                next_offset,
-                params
-                    .bbox
-                    .as_ref()
-                    .map(|bbox| format!("&bbox={},{},{},{}", bbox[0], bbox[1], bbox[2], bbox[3]))
-                    .unwrap_or_default(),
+                build_items_query(params, limit, position, None)
             ),
-            rel: LinkRel::Next,
-            type_: Some("application/geo+json".to_string()),
-            title: Some("next page".to_string()),
-        });
+            encoder,
+        );
+
+        Self {
+            base_url,
+            collection_id: collection_id.to_string(),
+            params: params.clone(),
+            limit,
+            offset,
+            number_matched,
+            f,
+            media_type: encoder.media_type(ResourceKind::Items),
+            links,
+        }
     }
 
+    fn page_link(&self, position: PagePosition<'_>, rel: LinkRel, title: &str) -> Link {
+        Link {
+            href: format!(
+                "{}collections/{}/items?{}",
+                self.base_url,
+                self.collection_id,
+                build_items_query(&self.params, self.limit, position, self.f),
+            ),
+            rel,
+            type_: Some(self.media_type.to_string()),
+            title: Some(title.to_string()),
+        }
+    }
+
+    fn build(mut self, next_cursor: Option<&str>) -> Vec<Link> {
+        let mut links = std::mem::take(&mut self.links);
+        if let Some(cursor) = next_cursor {
+            links.push(self.page_link(PagePosition::Cursor(cursor), LinkRel::Next, "next page"));
+        }
+        if self.offset > 0 {
+            links.push(self.page_link(
+                PagePosition::Offset(self.offset.saturating_sub(self.limit)),
+                LinkRel::Prev,
+                "previous page",
+            ));
+        }
+        links.push(self.page_link(PagePosition::Offset(0), LinkRel::First, "first page"));
+        if let Some(last_page) = self
+            .number_matched
+            .saturating_sub(1)
+            .checked_div(self.limit)
+        {
+            links.push(self.page_link(
+                PagePosition::Offset(last_page * self.limit),
+                LinkRel::Last,
+                "last page",
+            ));
+        }
+        links
+    }
+}
+
+fn build_ogc_api_feature_collection(
+    features_with_count: FeaturesWithCount,
+    url_base: &str,
+    collection_id: &str,
+    params: &GetItemsParams,
+    encoders: &Encoders,
+    encoder: &dyn Encoder,
+) -> OgcApiFeatureCollection {
+    let links = PageLinks::new(
+        url_base,
+        collection_id,
+        params,
+        features_with_count.offset,
+        features_with_count.number_matched,
+        encoders,
+        encoder,
+    )
+    .build(features_with_count.next_cursor.as_deref());
+
     OgcApiFeatureCollection::new(
         features_with_count.features,
         features_with_count.number_matched,
@@ -71,8 +334,14 @@ fn build_ogc_api_feature_collection(
 #[utoipa::path(
     get,
     path = "/collections/{collection_id}/items",
+    params(
+        ("collection_id" = String, Path, description = "ID of the collection"),
+        GetItemsParams
+    ),
     responses(
-        (status = 200, description = "Collection items", body = DocFeatureCollectionSchema)
+        (status = 200, description = "Collection items", body = DocFeatureCollectionSchema, content_type = "application/geo+json"),
+        (status = 400, description = "Unsupported CRS, invalid filter or invalid parameters"),
+        (status = 406, description = "The requested format is not available")
     )
 )]
 pub async fn get_collection_items(
@@ -80,29 +349,381 @@ pub async fn get_collection_items(
     Path(collection_id): Path<String>,
     Query(params): Query<GetItemsParams>,
     headers: HeaderMap,
-) -> Result<Json<OgcApiFeatureCollection>, (StatusCode, String)> {
-    let page = state.store.get_features(&collection_id, &params).await?;
+) -> Result<Response, AppError> {
+    collection_items(&state, &collection_id, &params, &headers).await
+}
 
-    Ok(Json(build_ogc_api_feature_collection(
+#[utoipa::path(
+    post,
+    path = "/collections/{collection_id}/items",
+    params(
+        ("collection_id" = String, Path, description = "ID of the collection"),
+        GetItemsParams
+    ),
+    request_body(
+        description = "A GeoJSON feature to create in a writable collection, or a CQL2 JSON filter expression to search the items with",
+        content(
+            (Object = "application/geo+json"),
+            (Object = "application/json")
+        )
+    ),
+    responses(
+        (status = 200, description = "Collection items matching the filter", body = DocFeatureCollectionSchema),
+        (status = 201, description = "Feature created, its URL is in the Location header"),
+        (status = 400, description = "Unsupported CRS, invalid filter, invalid feature or invalid parameters"),
+        (status = 405, description = "The collection is read-only")
+    )
+)]
+pub async fn post_collection_items(
+    State(state): State<AppState>,
+    Path(collection_id): Path<String>,
+    Query(mut params): Query<GetItemsParams>,
+    headers: HeaderMap,
+    Json(body): Json<serde_json::Value>,
+) -> Result<Response, AppError> {
+    let is_geojson = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/geo+json"));
+    if is_geojson || body.get("type").and_then(|t| t.as_str()) == Some("Feature") {
+        return transactions::create_feature(&state, &collection_id, &headers, body).await;
+    }
+
+    let filter = body;
+    if params.filter.is_some() {
+        return Err(AppError::BadRequest(
+            "The filter must be sent either in the query or in the request body, not both"
+                .to_string(),
+        ));
+    }
+    params.filter = Some(filter.to_string());
+    params.filter_lang = Some(FilterLang::Cql2Json);
+
+    collection_items(&state, &collection_id, &params, &headers).await
+}
+
+async fn collection_items(
+    state: &AppState,
+    collection_id: &str,
+    params: &GetItemsParams,
+    headers: &HeaderMap,
+) -> Result<Response, AppError> {
+    let crs = resolve_crs(state, collection_id, params.crs.as_ref())?;
+    resolve_crs(state, collection_id, params.bbox_crs.as_ref())?;
+    let params = &resolve_limit(state, collection_id, params)?;
+    let encoder = state
+        .encoders
+        .negotiate(ResourceKind::Items, params.f.as_deref(), headers)?;
+
+    if encoder.streams_items() {
+        let (properties, crs_definition) =
+            items_schema(state, collection_id, params, &crs, encoder).await?;
+        let page = state.store.stream_features(collection_id, params).await?;
+        let links = PageLinks::new(
+            &state.config.url_base,
+            collection_id,
+            params,
+            page.offset,
+            page.number_matched,
+            &state.encoders,
+            encoder,
+        );
+        let response = encoder.encode_stream(ItemsStream {
+            collection_id: collection_id.to_string(),
+            crs: crs.clone(),
+            properties,
+            crs_definition,
+            number_matched: page.number_matched,
+            bbox: params.bbox.clone(),
+            features: page.features,
+            links: Box::new(move |next_cursor| links.build(next_cursor.as_deref())),
+        })?;
+        return Ok(with_content_crs(&crs, response).into_response());
+    }
+
+    // Downloads hold all the matching features, up to the limit of the encoding.
+    let download_params;
+    let params = match encoder.download_limit() {
+        Some(limit) => {
+            download_params = GetItemsParams {
+                limit: Some(limit),
+                offset: None,
+                cursor: None,
+                ..params.clone()
+            };
+            &download_params
+        }
+        None => params,
+    };
+    let page = state.store.get_features(collection_id, params).await?;
+    if let Some(limit) = encoder.download_limit()
+        && page.number_matched > limit
+    {
+        return Err(AppError::BadRequest(format!(
+            "{} features match the request, but a {} download holds at most {}, narrow down the filters",
+            page.number_matched,
+            encoder.title(),
+            limit
+        )));
+    }
+
+    let (properties, crs_definition) =
+        items_schema(state, collection_id, params, &crs, encoder).await?;
+    let collection = build_ogc_api_feature_collection(
         page,
-        &headers,
-        &collection_id,
-        &params,
-    )))
+        &state.config.url_base,
+        collection_id,
+        params,
+        &state.encoders,
+        encoder,
+    );
+
+    let encode = {
+        let (collection_id, crs) = (collection_id.to_string(), crs.clone());
+        let url_base = state.config.url_base.clone();
+        move |encoder: &dyn Encoder| {
+            encoder.encode(
+                Resource::Items(Items {
+                    collection_id: &collection_id,
+                    crs: &crs,
+                    collection: &collection,
+                    properties: &properties,
+                    crs_definition: crs_definition.as_deref(),
+                }),
+                &url_base,
+            )
+        }
+    };
+    let response = if encoder.blocks() {
+        let encoders = state.encoders.clone();
+        let name = encoder.name();
+        tokio::task::spawn_blocking(move || {
+            let encoder = encoders
+                .for_kind(ResourceKind::Items)
+                .find(|encoder| encoder.name() == name)
+                .expect("the negotiated encoder is registered");
+            encode(encoder)
+        })
+        .await
+        .map_err(|e| AppError::Internal(format!("Failed to encode the features: {}", e)))??
+    } else {
+        encode(encoder)?
+    };
+    Ok(with_content_crs(&crs, response).into_response())
+}
+
+/// The types of the returned properties and the definition of the CRS, for the encoders that
+/// need them.
+async fn items_schema(
+    state: &AppState,
+    collection_id: &str,
+    params: &GetItemsParams,
+    crs: &Crs,
+    encoder: &dyn Encoder,
+) -> Result<(Vec<QueryableProperty>, Option<String>), AppError> {
+    if !encoder.needs_schema() {
+        return Ok((Vec::new(), None));
+    }
+    Ok((
+        property_schema(state, collection_id, params).await?,
+        state.store.get_crs_definition(crs).await?,
+    ))
+}
+
+/// The types of the properties returned by an items request, in the order of the configuration.
+async fn property_schema(
+    state: &AppState,
+    collection_id: &str,
+    params: &GetItemsParams,
+) -> Result<Vec<QueryableProperty>, AppError> {
+    let selected = params.properties();
+    let queryables = state.store.get_queryables(collection_id).await?;
+    Ok(queryables
+        .into_iter()
+        .filter(|property| property.role.as_deref() != Some("primary-geometry"))
+        .filter(|property| {
+            selected
+                .as_ref()
+                .is_none_or(|selected| selected.contains(&property.name))
+        })
+        .collect())
 }
 
 #[utoipa::path(
     get,
     path = "/collections/{collection_id}/items/{id}",
+    params(
+        ("collection_id" = String, Path, description = "ID of the collection"),
+        ("id" = String, Path, description = "ID of the feature"),
+        GetItemParams
+    ),
     responses(
-        (status = 200, description = "Collection item", body = DocFeatureSchema)
+        (status = 200, description = "Collection item, with its version in the ETag header", body = DocFeatureSchema, content_type = "application/geo+json"),
+        (status = 304, description = "The feature matches the If-None-Match header"),
+        (status = 400, description = "Unsupported CRS or invalid feature ID"),
+        (status = 404, description = "Feature not found"),
+        (status = 406, description = "The requested format is not available")
     )
 )]
 pub async fn get_collection_item(
     State(state): State<AppState>,
     Path((collection_id, id)): Path<(String, String)>,
-) -> Result<Json<geojson::Feature>, (StatusCode, String)> {
-    let feature = state.store.get_feature(&collection_id, &id).await?;
+    Query(params): Query<GetItemParams>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let crs = resolve_crs(&state, &collection_id, params.crs.as_ref())?;
+    let encoder = state
+        .encoders
+        .negotiate(ResourceKind::Item, params.f.as_deref(), &headers)?;
 
-    Ok(Json(feature))
+    let FeatureWithEtag { feature, etag } = state
+        .store
+        .get_feature(&collection_id, &id, &params)
+        .await?;
+
+    let url_base = &state.config.url_base;
+    let collection_url = format!("{}/collections/{}", url_base, collection_id);
+    let mut href = format!("{}/items/{}", collection_url, id);
+    let query = build_item_query(&params);
+    if !query.is_empty() {
+        href = format!("{}?{}", href, query);
+    }
+    let mut links = state.encoders.links(ResourceKind::Item, &href, encoder);
+    links.push(Link {
+        href: collection_url,
+        rel: LinkRel::Collection,
+        type_: Some("application/json".to_string()),
+        title: Some("the collection document".to_string()),
+    });
+
+    let etag = item_etag(etag, &params, &crs, &state.encoders, encoder);
+    if if_none_match(&headers, &etag) {
+        return Ok((StatusCode::NOT_MODIFIED, [etag_header(&etag)]).into_response());
+    }
+
+    let properties = state
+        .config
+        .collections
+        .get(&collection_id)
+        .map_or(&[][..], |collection| &collection.properties);
+    let response = encoder.encode(
+        Resource::Item(Item {
+            collection_id: &collection_id,
+            crs: &crs,
+            feature: &feature,
+            properties,
+            links: &links,
+        }),
+        url_base,
+    )?;
+    Ok(([etag_header(&etag)], with_content_crs(&crs, response)).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AppConfig;
+
+    #[test]
+    fn test_page_links_keep_the_query() {
+        let config: AppConfig = toml::from_str(
+            r#"
+            title = "test"
+            description = "test"
+            url_base = "http://localhost:3000"
+            collections = {}
+            "#,
+        )
+        .unwrap();
+        let encoders = Encoders::new(&config);
+        let html = encoders
+            .negotiate(ResourceKind::Items, Some("html"), &HeaderMap::new())
+            .unwrap();
+        let params: GetItemsParams = serde_urlencoded::from_str(
+            "limit=5&offset=10&bbox=0,0,1,1&datetime=2024-01-01&sortby=-name&f=html",
+        )
+        .unwrap();
+        let links = PageLinks::new(
+            &config.url_base,
+            "countries",
+            &params,
+            10,
+            100,
+            &encoders,
+            html,
+        )
+        .build(None);
+
+        let query = "limit=5&offset=10&bbox=0%2C0%2C1%2C1&sortby=-name&datetime=2024-01-01";
+        assert!(matches!(links[0].rel, LinkRel::Self_));
+        assert_eq!(
+            links[0].href,
+            format!(
+                "http://localhost:3000/collections/countries/items?{}&f=html",
+                query
+            )
+        );
+        let json = links
+            .iter()
+            .find(|link| link.type_.as_deref() == Some("application/geo+json"))
+            .unwrap();
+        assert_eq!(
+            json.href,
+            format!(
+                "http://localhost:3000/collections/countries/items?{}",
+                query
+            )
+        );
+
+        let params: GetItemParams = serde_urlencoded::from_str(
+            "crs=http://www.opengis.net/def/crs/EPSG/0/2056&skipGeometry=true&f=html",
+        )
+        .unwrap();
+        assert_eq!(
+            build_item_query(&params),
+            "crs=http%3A%2F%2Fwww.opengis.net%2Fdef%2Fcrs%2FEPSG%2F0%2F2056&skipGeometry=true"
+        );
+    }
+
+    #[test]
+    fn test_item_etag() {
+        let config: AppConfig = toml::from_str(
+            r#"
+            title = "test"
+            description = "test"
+            url_base = "http://localhost:3000"
+            collections = {}
+            "#,
+        )
+        .unwrap();
+        let encoders = Encoders::new(&config);
+        let json = encoders.for_kind(ResourceKind::Item).next().unwrap();
+        let html = encoders
+            .negotiate(ResourceKind::Item, Some("html"), &HeaderMap::new())
+            .unwrap();
+        let etag = |query: &str, encoder: &dyn Encoder| {
+            let params: GetItemParams = serde_urlencoded::from_str(query).unwrap();
+            let crs = params.crs.clone().unwrap_or_else(Crs::crs84);
+            item_etag("abc".to_string(), &params, &crs, &encoders, encoder)
+        };
+
+        assert_eq!(etag("", json), "abc");
+        assert_eq!(etag("", html), "abc-html");
+        let variants = [
+            etag("crs=http://www.opengis.net/def/crs/EPSG/0/2056", json),
+            etag("properties=name", json),
+            etag("properties=pop_est", json),
+            etag("skipGeometry=true", json),
+            etag("skipGeometry=true", html),
+        ];
+        for (i, variant) in variants.iter().enumerate() {
+            assert!(variant.starts_with("abc-"));
+            assert!(
+                variant
+                    .bytes()
+                    .all(|byte| byte.is_ascii_graphic() && byte != b'"')
+            );
+            assert!(!variants[i + 1..].contains(variant));
+        }
+    }
 }
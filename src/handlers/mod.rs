@@ -1,11 +1,39 @@
 pub mod core;
 pub mod features;
+pub mod queryables;
+pub mod tiles;
+pub mod transactions;
 
 pub use crate::models::{
-    Collection, Collections, Conformance, DocFeatureCollectionSchema, DocFeatureSchema, GetItemsParams,
-    LandingPage, Link,
+    Collection, Collections, Conformance, DocFeatureCollectionSchema, DocFeatureSchema,
+    GetItemsParams, LandingPage, Link, Problem, Queryables, TileMatrixSet, TileMatrixSets, TileSet,
+    TileSets,
+};
+use crate::{
+    config::AppConfig,
+    encoders::{Encoders, ResourceKind},
+    error::PROBLEM_JSON,
+};
+use utoipa::{
+    OpenApi,
+    openapi::{
+        ContentBuilder, HttpMethod, ObjectBuilder, Ref, RefOr, ResponseBuilder, Type,
+        path::{Operation, ParameterBuilder, ParameterIn},
+    },
 };
-use utoipa::OpenApi;
+
+/// The paths whose successful responses are encoded by the encoders.
+const ENCODED_PATHS: [(&str, ResourceKind); 6] = [
+    ("/", ResourceKind::Landing),
+    ("/conformance", ResourceKind::Conformance),
+    ("/collections", ResourceKind::Collections),
+    ("/collections/{collection_id}", ResourceKind::Collection),
+    ("/collections/{collection_id}/items", ResourceKind::Items),
+    (
+        "/collections/{collection_id}/items/{id}",
+        ResourceKind::Item,
+    ),
+];
 
 #[derive(OpenApi)]
 #[openapi(
@@ -14,7 +42,20 @@ use utoipa::OpenApi;
         core::get_conformance,
         core::get_collections,
         core::get_collection,
-        features::get_collection_items
+        features::get_collection_items,
+        features::post_collection_items,
+        features::get_collection_item,
+        queryables::get_queryables,
+        queryables::get_sortables,
+        queryables::get_xml_schema,
+        tiles::get_tile_matrix_sets,
+        tiles::get_tile_matrix_set,
+        tiles::get_collection_tilesets,
+        tiles::get_collection_tileset,
+        tiles::get_collection_tile,
+        transactions::put_collection_item,
+        transactions::patch_collection_item,
+        transactions::delete_collection_item
     ),
     components(schemas(
         LandingPage,
@@ -24,7 +65,330 @@ use utoipa::OpenApi;
         Link,
         GetItemsParams,
         DocFeatureCollectionSchema,
-        DocFeatureSchema
+        DocFeatureSchema,
+        Queryables,
+        TileMatrixSets,
+        TileMatrixSet,
+        TileSets,
+        TileSet,
+        Problem
     ))
 )]
 pub struct ApiDoc;
+
+/// Documents the media types of the responses of the encoded paths and the values of their `f`
+/// parameter from the registered encoders.
+fn document_encodings(doc: &mut utoipa::openapi::OpenApi, encoders: &Encoders) {
+    for (path, kind) in ENCODED_PATHS {
+        let Some(operation) = doc
+            .paths
+            .paths
+            .get_mut(path)
+            .and_then(|item| item.get.as_mut())
+        else {
+            continue;
+        };
+        if let Some(RefOr::T(response)) = operation.responses.responses.get_mut("200") {
+            let json_schema = response
+                .content
+                .values()
+                .next()
+                .and_then(|content| content.schema.clone());
+            response.content = encoders
+                .for_kind(kind)
+                .map(|encoder| {
+                    let schema = encoder.schema().or_else(|| json_schema.clone());
+                    (
+                        encoder.media_type(kind).to_string(),
+                        ContentBuilder::new().schema(schema).build(),
+                    )
+                })
+                .collect();
+        }
+        for parameter in operation.parameters.iter_mut().flatten() {
+            if parameter.name == "f" {
+                let formats = encoders.for_kind(kind).map(|encoder| encoder.name());
+                parameter.schema = Some(
+                    ObjectBuilder::new()
+                        .schema_type(Type::String)
+                        .enum_values(Some(formats))
+                        .into(),
+                );
+            }
+        }
+    }
+}
+
+/// Documents the bodies of the error responses as RFC 7807 problems, and the internal error any
+/// operation may answer with.
+fn document_problems(doc: &mut utoipa::openapi::OpenApi) {
+    let problem = || {
+        ContentBuilder::new()
+            .schema(Some(Ref::from_schema_name("Problem")))
+            .build()
+    };
+    for item in doc.paths.paths.values_mut() {
+        let operations = [
+            &mut item.get,
+            &mut item.put,
+            &mut item.post,
+            &mut item.delete,
+            &mut item.patch,
+        ];
+        for operation in operations.into_iter().flatten() {
+            let responses = &mut operation.responses.responses;
+            for (status, response) in responses.iter_mut() {
+                if let RefOr::T(response) = response
+                    && status.parse::<u16>().is_ok_and(|status| status >= 400)
+                {
+                    response.content = [(PROBLEM_JSON.to_string(), problem())].into();
+                }
+            }
+            responses.entry("500".to_string()).or_insert_with(|| {
+                ResponseBuilder::new()
+                    .description("Internal error, logged under the returned correlation ID")
+                    .content(PROBLEM_JSON, problem())
+                    .into()
+            });
+        }
+    }
+}
+
+/// Documents the default and the maximum of the `limit` parameter of an items operation.
+fn document_limit(operation: &mut Operation, default: u64, max: u64) {
+    for parameter in operation.parameters.iter_mut().flatten() {
+        if parameter.name == "limit" {
+            parameter.schema = Some(
+                ObjectBuilder::new()
+                    .schema_type(Type::Integer)
+                    .minimum(Some(1))
+                    .maximum(Some(max))
+                    .default(Some(default.into()))
+                    .into(),
+            );
+        }
+    }
+}
+
+/// Builds the OpenAPI document, adding an items path per collection that documents the
+/// properties its features can be filtered on with query parameters.
+pub fn api_doc(config: &AppConfig, encoders: &Encoders) -> utoipa::openapi::OpenApi {
+    let mut doc = ApiDoc::openapi();
+    document_encodings(&mut doc, encoders);
+    let Some(mut items) = doc
+        .paths
+        .get_path_operation("/collections/{collection_id}/items", HttpMethod::Get)
+        .cloned()
+    else {
+        document_problems(&mut doc);
+        return doc;
+    };
+    let (default_limit, max_limit) = config.limits(None);
+    document_limit(&mut items, default_limit, max_limit);
+    doc.paths.add_path_operation(
+        "/collections/{collection_id}/items",
+        vec![HttpMethod::Get],
+        items.clone(),
+    );
+
+    for (collection_id, collection) in &config.collections {
+        let mut operation = items.clone();
+        operation.operation_id = Some(format!("get_{}_items", collection_id));
+        let mut parameters: Vec<_> = operation
+            .parameters
+            .take()
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|parameter| parameter.name != "collection_id")
+            .collect();
+        parameters.extend(collection.properties.iter().map(|property| {
+            ParameterBuilder::new()
+                .name(property)
+                .parameter_in(ParameterIn::Query)
+                .description(Some(format!(
+                    "Only return features whose `{}` equals the given value",
+                    property
+                )))
+                .schema(Some(ObjectBuilder::new().schema_type(Type::String)))
+                .build()
+        }));
+        operation.parameters = Some(parameters);
+        let (default_limit, max_limit) = config.limits(Some(collection));
+        document_limit(&mut operation, default_limit, max_limit);
+        doc.paths.add_path_operation(
+            format!("/collections/{}/items", collection_id),
+            vec![HttpMethod::Get],
+            operation,
+        );
+    }
+    document_problems(&mut doc);
+    doc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use utoipa::openapi::Schema;
+
+    #[test]
+    fn test_api_doc_documents_property_filters_per_collection() {
+        let config: AppConfig = toml::from_str(
+            r#"
+            title = "test"
+            description = "test"
+            url_base = "http://localhost:3000"
+
+            [collections.countries]
+            table = "naturalearth_lowres"
+            id_column = "ogc_fid"
+            geometry_column = "wkb_geometry"
+            properties = ["name", "pop_est"]
+            "#,
+        )
+        .unwrap();
+        let doc = api_doc(&config, &Encoders::new(&config));
+
+        let operation = doc
+            .paths
+            .get_path_operation("/collections/countries/items", HttpMethod::Get)
+            .unwrap();
+        let names: Vec<_> = operation
+            .parameters
+            .iter()
+            .flatten()
+            .map(|parameter| parameter.name.as_str())
+            .collect();
+        assert!(!names.contains(&"collection_id"));
+        assert!(names.contains(&"limit"));
+        assert!(names.ends_with(&["name", "pop_est"]));
+    }
+
+    #[test]
+    fn test_api_doc_documents_limits() {
+        let config: AppConfig = toml::from_str(
+            r#"
+            title = "test"
+            description = "test"
+            url_base = "http://localhost:3000"
+            default_limit = 20
+            max_limit = 1000
+
+            [collections.countries]
+            table = "naturalearth_lowres"
+            id_column = "ogc_fid"
+            geometry_column = "wkb_geometry"
+            properties = ["name"]
+            max_limit = 100
+            "#,
+        )
+        .unwrap();
+        let doc = api_doc(&config, &Encoders::new(&config));
+
+        let limit = |path: &str| {
+            let operation = doc.paths.get_path_operation(path, HttpMethod::Get).unwrap();
+            let limit = operation
+                .parameters
+                .iter()
+                .flatten()
+                .find(|parameter| parameter.name == "limit")
+                .unwrap();
+            let Some(RefOr::T(Schema::Object(schema))) = &limit.schema else {
+                panic!("the limit parameter must have a schema");
+            };
+            (
+                schema.default.clone(),
+                serde_json::to_value(&schema.maximum).unwrap(),
+            )
+        };
+        assert_eq!(
+            limit("/collections/{collection_id}/items"),
+            (Some(20.into()), serde_json::json!(1000))
+        );
+        assert_eq!(
+            limit("/collections/countries/items"),
+            (Some(20.into()), serde_json::json!(100))
+        );
+    }
+
+    #[test]
+    fn test_api_doc_documents_problems() {
+        let config: AppConfig = toml::from_str(
+            r#"
+            title = "test"
+            description = "test"
+            url_base = "http://localhost:3000"
+            collections = {}
+            "#,
+        )
+        .unwrap();
+        let doc = api_doc(&config, &Encoders::new(&config));
+
+        let operation = doc
+            .paths
+            .get_path_operation("/collections/{collection_id}/items/{id}", HttpMethod::Get)
+            .unwrap();
+        for status in ["400", "404", "500"] {
+            let Some(RefOr::T(response)) = operation.responses.responses.get(status) else {
+                panic!("the {} response must be documented", status);
+            };
+            assert_eq!(
+                response.content.keys().collect::<Vec<_>>(),
+                ["application/problem+json"]
+            );
+        }
+        assert!(
+            doc.components
+                .as_ref()
+                .is_some_and(|components| components.schemas.contains_key("Problem"))
+        );
+    }
+
+    #[test]
+    fn test_api_doc_documents_encodings() {
+        let config: AppConfig = toml::from_str(
+            r#"
+            title = "test"
+            description = "test"
+            url_base = "http://localhost:3000"
+            collections = {}
+            "#,
+        )
+        .unwrap();
+        let doc = api_doc(&config, &Encoders::new(&config));
+
+        let operation = doc
+            .paths
+            .get_path_operation("/collections/{collection_id}/items/{id}", HttpMethod::Get)
+            .unwrap();
+        let Some(RefOr::T(response)) = operation.responses.responses.get("200") else {
+            panic!("the item response must be documented");
+        };
+        assert_eq!(
+            response.content.keys().collect::<Vec<_>>(),
+            [
+                "application/geo+json",
+                "text/html",
+                "application/gml+xml; version=3.2; profile=http://www.opengis.net/def/profile/ogc/2.0/gml-sf0",
+                "application/gml+xml; version=3.2; profile=http://www.opengis.net/def/profile/ogc/2.0/gml-sf2"
+            ]
+        );
+        let f = operation
+            .parameters
+            .iter()
+            .flatten()
+            .find(|parameter| parameter.name == "f")
+            .unwrap();
+        let Some(RefOr::T(Schema::Object(schema))) = &f.schema else {
+            panic!("the f parameter must have a schema");
+        };
+        assert_eq!(
+            schema.enum_values,
+            Some(vec![
+                "json".into(),
+                "html".into(),
+                "gmlsf0".into(),
+                "gmlsf2".into()
+            ])
+        );
+    }
+}
@@ -0,0 +1,108 @@
+use crate::{encoders::application_schema, error::AppError, models::Queryables, state::AppState};
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::header,
+};
+
+const SCHEMA_JSON: &str = "application/schema+json";
+const XML: &str = "application/xml";
+
+#[utoipa::path(
+    get,
+    path = "/collections/{collection_id}/queryables",
+    params(
+        ("collection_id" = String, Path, description = "ID of the collection")
+    ),
+    responses(
+        (status = 200, description = "JSON Schema of the properties that can be used in filters", body = Queryables, content_type = "application/schema+json"),
+        (status = 404, description = "Collection not found")
+    )
+)]
+pub async fn get_queryables(
+    State(state): State<AppState>,
+    Path(collection_id): Path<String>,
+) -> Result<([(header::HeaderName, &'static str); 1], Json<Queryables>), AppError> {
+    let properties = state.store.get_queryables(&collection_id).await?;
+
+    Ok((
+        [(header::CONTENT_TYPE, SCHEMA_JSON)],
+        Json(Queryables::new(
+            format!(
+                "{}/collections/{}/queryables",
+                state.config.url_base, collection_id
+            ),
+            format!("Queryables of {}", collection_id),
+            properties,
+        )),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/collections/{collection_id}/sortables",
+    params(
+        ("collection_id" = String, Path, description = "ID of the collection")
+    ),
+    responses(
+        (status = 200, description = "JSON Schema of the properties that can be used in sortby", body = Queryables, content_type = "application/schema+json"),
+        (status = 404, description = "Collection not found")
+    )
+)]
+pub async fn get_sortables(
+    State(state): State<AppState>,
+    Path(collection_id): Path<String>,
+) -> Result<([(header::HeaderName, &'static str); 1], Json<Queryables>), AppError> {
+    let properties = state.store.get_queryables(&collection_id).await?;
+    let sortables = state
+        .config
+        .collections
+        .get(&collection_id)
+        .map(|collection| collection.sortables())
+        .unwrap_or_default();
+
+    Ok((
+        [(header::CONTENT_TYPE, SCHEMA_JSON)],
+        Json(Queryables::new(
+            format!(
+                "{}/collections/{}/sortables",
+                state.config.url_base, collection_id
+            ),
+            format!("Sortables of {}", collection_id),
+            properties
+                .into_iter()
+                .filter(|property| sortables.contains(&property.name))
+                .collect(),
+        )),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/collections/{collection_id}/schema.xsd",
+    params(
+        ("collection_id" = String, Path, description = "ID of the collection")
+    ),
+    responses(
+        (status = 200, description = "XML schema of the features in GML", body = String, content_type = "application/xml"),
+        (status = 404, description = "Collection not found")
+    )
+)]
+pub async fn get_xml_schema(
+    State(state): State<AppState>,
+    Path(collection_id): Path<String>,
+) -> Result<([(header::HeaderName, &'static str); 1], String), AppError> {
+    let properties = state.store.get_queryables(&collection_id).await?;
+
+    Ok((
+        [(header::CONTENT_TYPE, XML)],
+        application_schema(
+            &state.config.url_base,
+            &collection_id,
+            &properties
+                .into_iter()
+                .filter(|property| property.role.as_deref() != Some("primary-geometry"))
+                .collect::<Vec<_>>(),
+        ),
+    ))
+}
@@ -0,0 +1,328 @@
+use crate::{
+    config::CollectionConfig,
+    error::AppError,
+    models::{
+        Link, LinkRel, MAX_TILE_MATRIX, TileLayer, TileMatrixLimits, TileMatrixSet,
+        TileMatrixSetItem, TileMatrixSets, TileSet, TileSetItem, TileSetLink, TileSets,
+        TilingScheme,
+    },
+    state::AppState,
+};
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use indexmap::IndexMap;
+use serde_json::json;
+
+const MVT: &str = "application/vnd.mapbox-vector-tile";
+
+fn tiling_scheme(tile_matrix_set_id: &str) -> Result<TilingScheme, AppError> {
+    TilingScheme::from_id(tile_matrix_set_id).ok_or_else(|| {
+        AppError::NotFound(format!("Tile matrix set {} not found", tile_matrix_set_id))
+    })
+}
+
+fn collection_config<'a>(
+    state: &'a AppState,
+    collection_id: &str,
+) -> Result<&'a CollectionConfig, AppError> {
+    state
+        .config
+        .collections
+        .get(collection_id)
+        .ok_or_else(|| AppError::NotFound(format!("Collection {} not found", collection_id)))
+}
+
+/// The zoom levels tiles of a collection are served at, capped by the deepest tile matrix.
+fn zoom_levels(config: &CollectionConfig) -> std::ops::RangeInclusive<u8> {
+    config.tiles.min_zoom..=config.tiles.max_zoom.min(MAX_TILE_MATRIX)
+}
+
+/// The links of a tileset: the tileset itself, its tile matrix set and the URI template of its
+/// tiles.
+fn tileset_links(url_base: &str, collection_id: &str, scheme: TilingScheme) -> Vec<TileSetLink> {
+    let tileset_url = format!(
+        "{}/collections/{}/tiles/{}",
+        url_base,
+        collection_id,
+        scheme.id()
+    );
+    vec![
+        Link {
+            href: tileset_url.clone(),
+            rel: LinkRel::Self_,
+            type_: Some("application/json".to_string()),
+            title: Some(format!("Tiles of {} in {}", collection_id, scheme.id())),
+        }
+        .into(),
+        Link {
+            href: format!("{}/tileMatrixSets/{}", url_base, scheme.id()),
+            rel: LinkRel::TilingScheme,
+            type_: Some("application/json".to_string()),
+            title: Some(scheme.title().to_string()),
+        }
+        .into(),
+        TileSetLink {
+            link: Link {
+                href: format!("{}/{{tileMatrix}}/{{tileCol}}/{{tileRow}}", tileset_url),
+                rel: LinkRel::Item,
+                type_: Some(MVT.to_string()),
+                title: Some("Mapbox vector tiles".to_string()),
+            },
+            templated: true,
+        },
+    ]
+}
+
+fn tile_matrix_limits(config: &CollectionConfig, scheme: TilingScheme) -> Vec<TileMatrixLimits> {
+    zoom_levels(config)
+        .map(|z| {
+            let (columns, rows) = scheme.matrix_size(z);
+            TileMatrixLimits {
+                tile_matrix: z.to_string(),
+                min_tile_row: 0,
+                max_tile_row: rows - 1,
+                min_tile_col: 0,
+                max_tile_col: columns - 1,
+            }
+        })
+        .collect()
+}
+
+#[utoipa::path(
+    get,
+    path = "/tileMatrixSets",
+    responses(
+        (status = 200, description = "The tile matrix sets tiles are available in", body = TileMatrixSets)
+    )
+)]
+pub async fn get_tile_matrix_sets(State(state): State<AppState>) -> Json<TileMatrixSets> {
+    let url_base = &state.config.url_base;
+    Json(TileMatrixSets {
+        tile_matrix_sets: TilingScheme::ALL
+            .into_iter()
+            .map(|scheme| TileMatrixSetItem {
+                id: scheme.id(),
+                title: scheme.title(),
+                uri: scheme.uri(),
+                links: vec![Link {
+                    href: format!("{}/tileMatrixSets/{}", url_base, scheme.id()),
+                    rel: LinkRel::Self_,
+                    type_: Some("application/json".to_string()),
+                    title: Some(scheme.title().to_string()),
+                }],
+            })
+            .collect(),
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/tileMatrixSets/{tile_matrix_set_id}",
+    params(
+        ("tile_matrix_set_id" = String, Path, description = "ID of the tile matrix set")
+    ),
+    responses(
+        (status = 200, description = "Definition of the tile matrix set", body = TileMatrixSet),
+        (status = 404, description = "Tile matrix set not found")
+    )
+)]
+pub async fn get_tile_matrix_set(
+    Path(tile_matrix_set_id): Path<String>,
+) -> Result<Json<TileMatrixSet>, AppError> {
+    Ok(Json(tiling_scheme(&tile_matrix_set_id)?.definition()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/collections/{collection_id}/tiles",
+    params(
+        ("collection_id" = String, Path, description = "ID of the collection")
+    ),
+    responses(
+        (status = 200, description = "The vector tilesets of the collection", body = TileSets),
+        (status = 404, description = "Collection not found")
+    )
+)]
+pub async fn get_collection_tilesets(
+    State(state): State<AppState>,
+    Path(collection_id): Path<String>,
+) -> Result<Json<TileSets>, AppError> {
+    let config = collection_config(&state, &collection_id)?;
+    Ok(Json(TileSets {
+        tilesets: TilingScheme::ALL
+            .into_iter()
+            .map(|scheme| TileSetItem {
+                title: format!("{} in {}", config.title(&collection_id), scheme.id()),
+                data_type: "vector",
+                crs: scheme.crs().uri().to_string(),
+                tile_matrix_set_uri: scheme.uri(),
+                links: tileset_links(&state.config.url_base, &collection_id, scheme),
+            })
+            .collect(),
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/collections/{collection_id}/tiles/{tile_matrix_set_id}",
+    params(
+        ("collection_id" = String, Path, description = "ID of the collection"),
+        ("tile_matrix_set_id" = String, Path, description = "ID of the tile matrix set")
+    ),
+    responses(
+        (status = 200, description = "Metadata of the vector tiles of the collection", body = TileSet),
+        (status = 404, description = "Collection or tile matrix set not found")
+    )
+)]
+pub async fn get_collection_tileset(
+    State(state): State<AppState>,
+    Path((collection_id, tile_matrix_set_id)): Path<(String, String)>,
+) -> Result<Json<TileSet>, AppError> {
+    let config = collection_config(&state, &collection_id)?;
+    let scheme = tiling_scheme(&tile_matrix_set_id)?;
+    let queryables = state.store.get_queryables(&collection_id).await?;
+    let tile_properties = config.tile_properties();
+    let properties: IndexMap<_, _> = queryables
+        .into_iter()
+        .filter(|property| tile_properties.contains(&property.name))
+        .map(|property| (property.name.clone(), property))
+        .collect();
+    let zoom_levels = zoom_levels(config);
+
+    Ok(Json(TileSet {
+        title: format!("{} in {}", config.title(&collection_id), scheme.id()),
+        data_type: "vector",
+        crs: scheme.crs().uri().to_string(),
+        tile_matrix_set_uri: scheme.uri(),
+        links: tileset_links(&state.config.url_base, &collection_id, scheme),
+        tile_matrix_set_limits: tile_matrix_limits(config, scheme),
+        layers: vec![TileLayer {
+            id: collection_id.clone(),
+            data_type: "vector",
+            min_tile_matrix: zoom_levels.start().to_string(),
+            max_tile_matrix: zoom_levels.end().to_string(),
+            properties_schema: json!({"type": "object", "properties": properties}),
+        }],
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/collections/{collection_id}/tiles/{tile_matrix_set_id}/{z}/{x}/{y}",
+    params(
+        ("collection_id" = String, Path, description = "ID of the collection"),
+        ("tile_matrix_set_id" = String, Path, description = "ID of the tile matrix set"),
+        ("z" = u8, Path, description = "Zoom level, the ID of the tile matrix"),
+        ("x" = u32, Path, description = "Column of the tile"),
+        ("y" = u32, Path, description = "Row of the tile")
+    ),
+    responses(
+        (status = 200, description = "Mapbox vector tile with a layer named after the collection", body = Vec<u8>, content_type = "application/vnd.mapbox-vector-tile"),
+        (status = 204, description = "No feature is in the tile"),
+        (status = 404, description = "Collection, tile matrix set or tile not found")
+    )
+)]
+pub async fn get_collection_tile(
+    State(state): State<AppState>,
+    Path((collection_id, tile_matrix_set_id, z, x, y)): Path<(String, String, u8, u32, u32)>,
+) -> Result<Response, AppError> {
+    let config = collection_config(&state, &collection_id)?;
+    let scheme = tiling_scheme(&tile_matrix_set_id)?;
+    if !zoom_levels(config).contains(&z) || !scheme.contains(z, x, y) {
+        return Err(AppError::NotFound(format!(
+            "Tile {}/{}/{} not found, tiles of {} are available at zoom levels {} to {}",
+            z,
+            x,
+            y,
+            collection_id,
+            zoom_levels(config).start(),
+            zoom_levels(config).end()
+        )));
+    }
+
+    let tile = state
+        .store
+        .get_tile(&collection_id, scheme, z, x, y)
+        .await?;
+    if tile.is_empty() {
+        return Ok(StatusCode::NO_CONTENT.into_response());
+    }
+    Ok(([(header::CONTENT_TYPE, MVT)], tile).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collection(tiles: &str) -> CollectionConfig {
+        toml::from_str(&format!(
+            r#"
+            table = "countries"
+            id_column = "id"
+            geometry_column = "geom"
+            properties = ["name"]
+            {}
+            "#,
+            tiles
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_zoom_levels() {
+        assert_eq!(zoom_levels(&collection("")), 0..=16);
+        assert_eq!(
+            zoom_levels(&collection("tiles = { min_zoom = 2, max_zoom = 30 }")),
+            2..=24
+        );
+    }
+
+    #[test]
+    fn test_tile_matrix_limits() {
+        let config = collection("tiles = { min_zoom = 1, max_zoom = 2 }");
+        assert_eq!(
+            tile_matrix_limits(&config, TilingScheme::WorldCrs84Quad),
+            [
+                TileMatrixLimits {
+                    tile_matrix: "1".to_string(),
+                    min_tile_row: 0,
+                    max_tile_row: 1,
+                    min_tile_col: 0,
+                    max_tile_col: 3,
+                },
+                TileMatrixLimits {
+                    tile_matrix: "2".to_string(),
+                    min_tile_row: 0,
+                    max_tile_row: 3,
+                    min_tile_col: 0,
+                    max_tile_col: 7,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tileset_links() {
+        let links = serde_json::to_value(tileset_links(
+            "http://localhost:3000",
+            "countries",
+            TilingScheme::WebMercatorQuad,
+        ))
+        .unwrap();
+        assert_eq!(
+            links[2],
+            json!({
+                "href": "http://localhost:3000/collections/countries/tiles/WebMercatorQuad/{tileMatrix}/{tileCol}/{tileRow}",
+                "rel": "item",
+                "type": "application/vnd.mapbox-vector-tile",
+                "title": "Mapbox vector tiles",
+                "templated": true
+            })
+        );
+        assert!(links[0].get("templated").is_none());
+    }
+}
@@ -0,0 +1,253 @@
+use super::features::{etag_header, resolve_crs};
+use crate::{
+    config::CollectionConfig,
+    error::AppError,
+    models::{Crs, FeaturePatch},
+    state::AppState,
+};
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+
+/// Returns the collection if features can be written to it.
+fn writable_collection<'a>(
+    state: &'a AppState,
+    collection_id: &str,
+) -> Result<&'a CollectionConfig, AppError> {
+    let collection = state
+        .config
+        .collections
+        .get(collection_id)
+        .ok_or_else(|| AppError::NotFound(format!("Collection {} not found", collection_id)))?;
+    if collection.writable {
+        Ok(collection)
+    } else {
+        Err(AppError::MethodNotAllowed(format!(
+            "Collection {} is read-only",
+            collection_id
+        )))
+    }
+}
+
+/// Resolves the CRS of the geometry in the request body from the `Content-Crs` header,
+/// defaulting to CRS84.
+fn resolve_content_crs(
+    state: &AppState,
+    collection_id: &str,
+    headers: &HeaderMap,
+) -> Result<Crs, AppError> {
+    let requested = match headers.get("content-crs") {
+        Some(value) => {
+            let value = value.to_str().unwrap_or_default();
+            let uri = value.trim_start_matches('<').trim_end_matches('>');
+            Some(Crs::from_uri(uri).ok_or_else(|| {
+                AppError::BadRequest(format!("Unsupported Content-Crs {}", value))
+            })?)
+        }
+        None => None,
+    };
+    resolve_crs(state, collection_id, requested.as_ref())
+}
+
+/// Reads the `If-Match` header that writes require, returning `None` for `*`, which matches any
+/// version of the feature.
+fn required_if_match(headers: &HeaderMap) -> Result<Option<String>, AppError> {
+    let value = headers
+        .get(header::IF_MATCH)
+        .ok_or_else(|| {
+            AppError::PreconditionRequired(
+                "Writes require an If-Match header with the ETag of the feature".to_string(),
+            )
+        })?
+        .to_str()
+        .unwrap_or_default()
+        .trim();
+
+    if value == "*" {
+        return Ok(None);
+    }
+    // If-Match uses the strong comparison, which weak entity tags never pass.
+    if value.starts_with("W/") {
+        return Err(AppError::PreconditionFailed(
+            "Weak entity tags never match If-Match".to_string(),
+        ));
+    }
+    match value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+        Some(etag) if !etag.contains('"') => Ok(Some(etag.to_string())),
+        _ => Err(AppError::BadRequest(
+            "If-Match must hold a single quoted entity tag or *".to_string(),
+        )),
+    }
+}
+
+/// Creates the feature posted to the items of a collection. Called by the items POST handler,
+/// which also serves CQL2 JSON searches.
+pub(super) async fn create_feature(
+    state: &AppState,
+    collection_id: &str,
+    headers: &HeaderMap,
+    body: serde_json::Value,
+) -> Result<Response, AppError> {
+    writable_collection(state, collection_id)?;
+    let crs = resolve_content_crs(state, collection_id, headers)?;
+    let feature = geojson::Feature::from_json_value(body)
+        .map_err(|e| AppError::BadRequest(format!("Invalid feature: {}", e)))?;
+
+    let (id, etag) = state
+        .store
+        .create_feature(collection_id, &feature, &crs)
+        .await?;
+    let location = format!(
+        "{}/collections/{}/items/{}",
+        state.config.url_base, collection_id, id
+    );
+
+    Ok((
+        StatusCode::CREATED,
+        [(header::LOCATION, location)],
+        [etag_header(&etag)],
+    )
+        .into_response())
+}
+
+#[utoipa::path(
+    put,
+    path = "/collections/{collection_id}/items/{id}",
+    params(
+        ("collection_id" = String, Path, description = "ID of the collection"),
+        ("id" = String, Path, description = "ID of the feature"),
+        ("If-Match" = String, Header, description = "The ETag of the feature as last read, or `*`")
+    ),
+    request_body(
+        content = Object,
+        description = "The GeoJSON feature replacing the existing one",
+        content_type = "application/geo+json"
+    ),
+    responses(
+        (status = 204, description = "Feature replaced, with its new version in the ETag header"),
+        (status = 400, description = "Invalid feature"),
+        (status = 404, description = "Feature not found"),
+        (status = 405, description = "The collection is read-only"),
+        (status = 412, description = "The feature was modified since the If-Match ETag was read"),
+        (status = 428, description = "The If-Match header is missing")
+    )
+)]
+pub async fn put_collection_item(
+    State(state): State<AppState>,
+    Path((collection_id, id)): Path<(String, String)>,
+    headers: HeaderMap,
+    Json(feature): Json<geojson::Feature>,
+) -> Result<Response, AppError> {
+    writable_collection(&state, &collection_id)?;
+    let if_match = required_if_match(&headers)?;
+    let crs = resolve_content_crs(&state, &collection_id, &headers)?;
+
+    let etag = state
+        .store
+        .replace_feature(&collection_id, &id, &feature, &crs, if_match.as_deref())
+        .await?;
+    Ok((StatusCode::NO_CONTENT, [etag_header(&etag)]).into_response())
+}
+
+#[utoipa::path(
+    patch,
+    path = "/collections/{collection_id}/items/{id}",
+    params(
+        ("collection_id" = String, Path, description = "ID of the collection"),
+        ("id" = String, Path, description = "ID of the feature"),
+        ("If-Match" = String, Header, description = "The ETag of the feature as last read, or `*`")
+    ),
+    request_body(
+        content = Object,
+        description = "A JSON merge patch of the feature, with the changed geometry and properties",
+        content_type = "application/merge-patch+json"
+    ),
+    responses(
+        (status = 204, description = "Feature updated, with its new version in the ETag header"),
+        (status = 400, description = "Invalid patch"),
+        (status = 404, description = "Feature not found"),
+        (status = 405, description = "The collection is read-only"),
+        (status = 412, description = "The feature was modified since the If-Match ETag was read"),
+        (status = 428, description = "The If-Match header is missing")
+    )
+)]
+pub async fn patch_collection_item(
+    State(state): State<AppState>,
+    Path((collection_id, id)): Path<(String, String)>,
+    headers: HeaderMap,
+    Json(patch): Json<FeaturePatch>,
+) -> Result<Response, AppError> {
+    writable_collection(&state, &collection_id)?;
+    let if_match = required_if_match(&headers)?;
+    let crs = resolve_content_crs(&state, &collection_id, &headers)?;
+
+    let etag = state
+        .store
+        .update_feature(&collection_id, &id, &patch, &crs, if_match.as_deref())
+        .await?;
+    Ok((StatusCode::NO_CONTENT, [etag_header(&etag)]).into_response())
+}
+
+#[utoipa::path(
+    delete,
+    path = "/collections/{collection_id}/items/{id}",
+    params(
+        ("collection_id" = String, Path, description = "ID of the collection"),
+        ("id" = String, Path, description = "ID of the feature"),
+        ("If-Match" = String, Header, description = "The ETag of the feature as last read, or `*`")
+    ),
+    responses(
+        (status = 204, description = "Feature deleted"),
+        (status = 404, description = "Feature not found"),
+        (status = 405, description = "The collection is read-only"),
+        (status = 412, description = "The feature was modified since the If-Match ETag was read"),
+        (status = 428, description = "The If-Match header is missing")
+    )
+)]
+pub async fn delete_collection_item(
+    State(state): State<AppState>,
+    Path((collection_id, id)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> Result<StatusCode, AppError> {
+    writable_collection(&state, &collection_id)?;
+    let if_match = required_if_match(&headers)?;
+
+    state
+        .store
+        .delete_feature(&collection_id, &id, if_match.as_deref())
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn if_match(value: &str) -> Result<Option<String>, AppError> {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_MATCH, HeaderValue::from_str(value).unwrap());
+        required_if_match(&headers)
+    }
+
+    #[test]
+    fn test_required_if_match() {
+        assert_eq!(if_match("\"abc\""), Ok(Some("abc".to_string())));
+        assert_eq!(if_match("*"), Ok(None));
+        assert_eq!(
+            if_match("W/\"abc\"").unwrap_err().status(),
+            StatusCode::PRECONDITION_FAILED
+        );
+        assert_eq!(
+            if_match("\"abc\", \"def\"").unwrap_err().status(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            required_if_match(&HeaderMap::new()).unwrap_err().status(),
+            StatusCode::PRECONDITION_REQUIRED
+        );
+    }
+}
@@ -1,11 +1,18 @@
 mod config;
+mod cql2;
+mod encoders;
+mod error;
 mod handlers;
 mod models;
 mod routes;
 mod state;
 mod storage;
 
-use crate::{state::AppState, storage::drivers::Postgis};
+use crate::{
+    encoders::Encoders,
+    state::AppState,
+    storage::drivers::{Postgis, detect_id_types, discover_collections},
+};
 
 use clap::Parser;
 use sqlx::postgres::PgPoolOptions;
@@ -23,21 +30,36 @@ async fn main() {
 
     let config_str =
         std::fs::read_to_string(&args.config).expect("Failed to read configuration file");
-    let config: config::AppConfig =
+    let mut config: config::AppConfig =
         toml::from_str(&config_str).expect("Failed to parse configuration");
-    let config = Arc::new(config);
 
     let pool = PgPoolOptions::new()
-        .max_connections(5)
+        .max_connections(config.max_connections)
         .connect(
             &std::env::var("DATABASE_URL").expect("`DATABASE_URL` must be set in the environment"),
         )
         .await
         .expect("Failed to connect to the database");
 
+    discover_collections(&pool, &mut config)
+        .await
+        .expect("Failed to discover the spatial tables");
+    if let Err(e) = config.check_collections() {
+        panic!("Invalid configuration: {}", e);
+    }
+    detect_id_types(&pool, &mut config)
+        .await
+        .expect("Failed to detect the feature ID types");
+    let config = Arc::new(config);
+
     let store = Arc::new(Postgis::new(pool, Arc::clone(&config)));
+    let encoders = Arc::new(Encoders::new(&config));
 
-    let app_state = AppState { store, config };
+    let app_state = AppState {
+        store,
+        config,
+        encoders,
+    };
 
     let app = routes::create_router(app_state);
 
@@ -0,0 +1,74 @@
+use serde::{Deserialize, Deserializer, de};
+use std::fmt;
+
+pub const CRS84_URI: &str = "http://www.opengis.net/def/crs/OGC/1.3/CRS84";
+const EPSG_URI_PREFIX: &str = "http://www.opengis.net/def/crs/EPSG/0/";
+
+/*
+ * A coordinate reference system as used by OGC API - Features - Part 2.
+ *
+ * CRSs are identified by their URI, e.g. `http://www.opengis.net/def/crs/EPSG/0/2056`.
+ * The short `EPSG:2056` form is also accepted, which is convenient in the configuration file.
+ * CRS84 and EPSG:4326 share the same PostGIS SRID but differ in axis order:
+ * EPSG:4326 is latitude/longitude, while PostGIS always stores longitude/latitude.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Crs {
+    uri: String,
+    srid: i32,
+}
+
+impl Crs {
+    pub fn crs84() -> Self {
+        Self {
+            uri: CRS84_URI.to_string(),
+            srid: 4326,
+        }
+    }
+
+    pub fn from_uri(uri: &str) -> Option<Self> {
+        let uri = uri.trim();
+        if uri == CRS84_URI || uri == "OGC:CRS84" {
+            return Some(Self::crs84());
+        }
+
+        let code = uri
+            .strip_prefix(EPSG_URI_PREFIX)
+            .or_else(|| uri.strip_prefix("EPSG:"))?;
+        let srid: i32 = code.parse().ok().filter(|srid| *srid > 0)?;
+
+        Some(Self {
+            uri: format!("{}{}", EPSG_URI_PREFIX, srid),
+            srid,
+        })
+    }
+
+    pub fn uri(&self) -> &str {
+        &self.uri
+    }
+
+    pub fn srid(&self) -> i32 {
+        self.srid
+    }
+
+    /// EPSG:4326 mandates latitude/longitude axis order, unlike the PostGIS storage order.
+    pub fn is_lat_lon(&self) -> bool {
+        self.srid == 4326 && self.uri != CRS84_URI
+    }
+}
+
+impl fmt::Display for Crs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.uri)
+    }
+}
+
+impl<'de> Deserialize<'de> for Crs {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let uri = String::deserialize(deserializer)?;
+        Crs::from_uri(&uri).ok_or_else(|| de::Error::custom(format!("unsupported crs: {}", uri)))
+    }
+}
@@ -0,0 +1,10 @@
+use serde::Deserialize;
+use utoipa::IntoParams;
+
+#[derive(Deserialize, IntoParams, Default)]
+#[into_params(parameter_in = Query)]
+pub struct FormatParams {
+    /// The encoding of the response, e.g. `json` or `html`. Defaults to the one preferred by the
+    /// `Accept` header.
+    pub f: Option<String>,
+}
@@ -7,13 +7,58 @@ pub enum LinkRel {
     #[serde(rename = "self")]
     Self_,
     Next,
+    Prev,
+    First,
+    Last,
     Alternate,
     Collection,
     Items,
+    Item,
     ServiceDesc,
     ServiceDoc,
     Conformance,
     Data,
+    License,
+    #[serde(rename = "describedby")]
+    DescribedBy,
+    #[serde(rename = "http://www.opengis.net/def/rel/ogc/1.0/queryables")]
+    Queryables,
+    #[serde(rename = "http://www.opengis.net/def/rel/ogc/1.0/sortables")]
+    Sortables,
+    #[serde(rename = "http://www.opengis.net/def/rel/ogc/1.0/tiling-schemes")]
+    TilingSchemes,
+    #[serde(rename = "http://www.opengis.net/def/rel/ogc/1.0/tiling-scheme")]
+    TilingScheme,
+    #[serde(rename = "http://www.opengis.net/def/rel/ogc/1.0/tilesets-vector")]
+    TilesetsVector,
+}
+
+impl LinkRel {
+    /// The relation type, as serialized.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Self_ => "self",
+            Self::Next => "next",
+            Self::Prev => "prev",
+            Self::First => "first",
+            Self::Last => "last",
+            Self::Alternate => "alternate",
+            Self::Collection => "collection",
+            Self::Items => "items",
+            Self::Item => "item",
+            Self::ServiceDesc => "service-desc",
+            Self::ServiceDoc => "service-doc",
+            Self::Conformance => "conformance",
+            Self::Data => "data",
+            Self::License => "license",
+            Self::DescribedBy => "describedby",
+            Self::Queryables => "http://www.opengis.net/def/rel/ogc/1.0/queryables",
+            Self::Sortables => "http://www.opengis.net/def/rel/ogc/1.0/sortables",
+            Self::TilingSchemes => "http://www.opengis.net/def/rel/ogc/1.0/tiling-schemes",
+            Self::TilingScheme => "http://www.opengis.net/def/rel/ogc/1.0/tiling-scheme",
+            Self::TilesetsVector => "http://www.opengis.net/def/rel/ogc/1.0/tilesets-vector",
+        }
+    }
 }
 
 #[derive(Serialize, ToSchema, Debug)]
@@ -25,3 +70,21 @@ pub struct Link {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub title: Option<String>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_link_rel_as_str_matches_serialization() {
+        for rel in [
+            LinkRel::Self_,
+            LinkRel::ServiceDesc,
+            LinkRel::DescribedBy,
+            LinkRel::Queryables,
+            LinkRel::TilesetsVector,
+        ] {
+            assert_eq!(serde_json::to_value(&rel).unwrap(), rel.as_str());
+        }
+    }
+}
@@ -1 +1,4 @@
+pub mod crs;
+pub mod format;
 pub mod link;
+pub mod problem;
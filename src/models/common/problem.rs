@@ -0,0 +1,24 @@
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// The details of an error, as defined by RFC 7807 and served as `application/problem+json`.
+#[derive(Serialize, ToSchema, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Problem {
+    /// A URI identifying the type of problem, `about:blank` when the status says it all.
+    #[serde(rename = "type")]
+    pub type_: String,
+    /// A short summary of the type of problem.
+    pub title: String,
+    /// The HTTP status code.
+    pub status: u16,
+    /// An explanation specific to this occurrence of the problem.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    /// The path of the request the problem occurred in.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instance: Option<String>,
+    /// The ID under which the details of an internal error are logged, to quote when reporting it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<String>,
+}
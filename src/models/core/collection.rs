@@ -1,10 +1,11 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
-use crate::models::Link;
+use crate::models::{Crs, Link};
 
 #[derive(Serialize, ToSchema)]
 pub struct Collections {
+    pub links: Vec<Link>,
     pub collections: Vec<Collection>,
 }
 
@@ -13,5 +14,67 @@ pub struct Collection {
     pub id: String,
     pub title: String,
     pub description: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub keywords: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attribution: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub contact: Option<Contact>,
     pub links: Vec<Link>,
+    pub crs: Vec<String>,
+    #[serde(rename = "storageCrs")]
+    pub storage_crs: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extent: Option<Extent>,
+}
+
+/// Who to contact about the data of a collection.
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone, PartialEq)]
+pub struct Contact {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+#[derive(Serialize, ToSchema, Debug, Clone, PartialEq)]
+pub struct Extent {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spatial: Option<SpatialExtent>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temporal: Option<TemporalExtent>,
+}
+
+/// The bounding box of a collection in CRS84, as `[min lon, min lat, max lon, max lat]`.
+#[derive(Serialize, ToSchema, Debug, Clone, PartialEq)]
+pub struct SpatialExtent {
+    pub bbox: Vec<[f64; 4]>,
+    pub crs: String,
+}
+
+impl SpatialExtent {
+    pub fn new(bbox: [f64; 4]) -> Self {
+        Self {
+            bbox: vec![bbox],
+            crs: Crs::crs84().uri().to_string(),
+        }
+    }
+}
+
+/// The temporal extent of a collection, `null` marking an open end.
+#[derive(Serialize, ToSchema, Debug, Clone, PartialEq)]
+pub struct TemporalExtent {
+    pub interval: Vec<[Option<String>; 2]>,
+    pub trs: String,
+}
+
+impl TemporalExtent {
+    pub fn new(start: Option<String>, end: Option<String>) -> Self {
+        Self {
+            interval: vec![[start, end]],
+            trs: "http://www.opengis.net/def/uom/ISO-8601/0/Gregorian".to_string(),
+        }
+    }
 }
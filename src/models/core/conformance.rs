@@ -1,7 +1,10 @@
 use serde::Serialize;
 use utoipa::ToSchema;
 
+use crate::models::Link;
+
 #[derive(Serialize, ToSchema)]
 pub struct Conformance {
     pub conforms_to: Vec<String>,
+    pub links: Vec<Link>,
 }
@@ -0,0 +1,35 @@
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/*
+ * An opaque keyset pagination cursor, passed in the `cursor` parameter of `next` links.
+ *
+ * It records the sort key values of the last feature of the previous page, so the next page
+ * can be fetched with a `WHERE (sort keys) > (values)` condition instead of an `OFFSET`,
+ * which stays fast however deep the client pages.
+ */
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Cursor {
+    /// The position of the first feature of the page, used to build `prev`/`first`/`last` links.
+    pub offset: u64,
+    /// The `sortby` the cursor was created for.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sortby: Option<String>,
+    /// The sort key values of the last feature of the previous page, keyed by column.
+    pub after: Map<String, Value>,
+}
+
+impl Cursor {
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).expect("cursors always serialize"))
+    }
+
+    pub fn decode(token: &str) -> Result<Self, String> {
+        URL_SAFE_NO_PAD
+            .decode(token)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .ok_or_else(|| "invalid cursor".to_string())
+    }
+}
@@ -8,13 +8,13 @@ const OGC_API_FEATURE_COLLECTION_TYPE: &str = "FeatureCollection";
 pub struct OgcApiFeatureCollection {
     #[serde(rename = "type")]
     type_: &'static str,
-    bbox: Option<Bbox>,
-    features: Vec<Feature>,
+    pub bbox: Option<Bbox>,
+    pub features: Vec<Feature>,
     #[serde(rename = "numberMatched")]
-    number_matched: u64,
+    pub number_matched: u64,
     #[serde(rename = "numberReturned")]
-    number_returned: u64,
-    links: Vec<Link>,
+    pub number_returned: u64,
+    pub links: Vec<Link>,
 }
 
 impl OgcApiFeatureCollection {
@@ -1,3 +1,6 @@
+pub mod cursor;
 pub mod feature_collection;
 pub mod parameters;
+pub mod patch;
+pub mod queryables;
 pub mod schema;
@@ -1,5 +1,8 @@
+use crate::cql2::{self, Expr};
+use crate::models::{Crs, Cursor};
 use geojson::Bbox;
 use serde::{Deserialize, Deserializer, de};
+use std::{collections::BTreeMap, fmt::Display, str::FromStr};
 use utoipa::IntoParams;
 use utoipa::ToSchema;
 
@@ -42,11 +45,386 @@ where
     }
 }
 
-#[derive(Deserialize, ToSchema, IntoParams)]
+/*
+ * Numbers and booleans arrive as strings once the parameters are buffered for
+ * `#[serde(flatten)]`, so the typed parameters of `GetItemsParams` accept both.
+ */
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StringOrValue<T> {
+    String(String),
+    Value(T),
+}
+
+fn deserialize_parsed_option<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr + Deserialize<'de>,
+    T::Err: Display,
+{
+    match Option::<StringOrValue<T>>::deserialize(deserializer)? {
+        Some(StringOrValue::String(s)) => s
+            .parse()
+            .map(Some)
+            .map_err(|e| de::Error::custom(format!("invalid value '{}': {}", s, e))),
+        Some(StringOrValue::Value(value)) => Ok(Some(value)),
+        None => Ok(None),
+    }
+}
+
+/// Deserializes `limit`, which must be a positive integer.
+fn deserialize_limit<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let limit = match Option::<StringOrValue<u64>>::deserialize(deserializer)? {
+        Some(StringOrValue::String(s)) => Some(s.parse().map_err(|_| {
+            de::Error::custom(format!("limit must be a positive integer, not '{}'", s))
+        })?),
+        Some(StringOrValue::Value(value)) => Some(value),
+        None => None,
+    };
+    if limit == Some(0) {
+        return Err(de::Error::custom("limit must be a positive integer, not 0"));
+    }
+    Ok(limit)
+}
+
+/// Splits the comma separated `properties` parameter. An empty value selects no properties.
+fn split_properties(properties: Option<&str>) -> Option<Vec<String>> {
+    properties.map(|properties| {
+        properties
+            .split(',')
+            .map(str::trim)
+            .filter(|property| !property.is_empty())
+            .map(str::to_string)
+            .collect()
+    })
+}
+
+#[derive(Deserialize, ToSchema, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum FilterLang {
+    #[default]
+    Cql2Text,
+    Cql2Json,
+}
+
+impl FilterLang {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FilterLang::Cql2Text => "cql2-text",
+            FilterLang::Cql2Json => "cql2-json",
+        }
+    }
+}
+
+/// A parsed `datetime` parameter. Open interval ends are `None`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Datetime {
+    Instant(String),
+    Interval {
+        start: Option<String>,
+        end: Option<String>,
+    },
+}
+
+/// Checks that a value starts with an RFC 3339 full date, optionally followed by a time.
+/// The database validates the rest when casting it.
+fn is_rfc3339(value: &str) -> bool {
+    let bytes = value.as_bytes();
+    bytes.len() >= 10
+        && bytes[..10].iter().enumerate().all(|(i, b)| match i {
+            4 | 7 => *b == b'-',
+            _ => b.is_ascii_digit(),
+        })
+        && (bytes.len() == 10 || matches!(bytes[10], b'T' | b't' | b' '))
+}
+
+/// A single key of the `sortby` parameter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SortKey {
+    pub property: String,
+    pub descending: bool,
+}
+
+#[derive(Deserialize, ToSchema, IntoParams, Default, Clone)]
+#[into_params(parameter_in = Query)]
 pub struct GetItemsParams {
+    /// The number of features of a page. Defaults to the collection's default limit, and is
+    /// lowered to its maximum limit.
+    #[serde(default, deserialize_with = "deserialize_limit")]
+    #[param(minimum = 1)]
     pub limit: Option<u64>,
+    #[serde(default, deserialize_with = "deserialize_parsed_option")]
     pub offset: Option<u64>,
     #[serde(default, deserialize_with = "deserialize_bbox_option")]
     #[param(value_type = Option<String>, example = "5.3,43.2,13.8,51.6")]
     pub bbox: Option<Bbox>,
+    /// The CRS of the `bbox` coordinates. Defaults to CRS84.
+    #[serde(rename = "bbox-crs")]
+    #[param(value_type = Option<String>, example = "http://www.opengis.net/def/crs/EPSG/0/2056")]
+    #[schema(value_type = Option<String>)]
+    pub bbox_crs: Option<Crs>,
+    /// The CRS of the returned geometries. Defaults to CRS84.
+    #[param(value_type = Option<String>, example = "http://www.opengis.net/def/crs/EPSG/0/2056")]
+    #[schema(value_type = Option<String>)]
+    pub crs: Option<Crs>,
+    /// A CQL2 filter expression, e.g. `pop_est > 1000000 AND name LIKE 'A%'` in the text encoding.
+    #[param(example = "pop_est > 1000000")]
+    pub filter: Option<String>,
+    /// The encoding of `filter`. Defaults to `cql2-text`.
+    #[serde(rename = "filter-lang")]
+    pub filter_lang: Option<FilterLang>,
+    /// Comma separated sortables, prefixed with `-` for descending order, e.g. `-pop_est,name`.
+    #[param(example = "-pop_est,name")]
+    pub sortby: Option<String>,
+    /// An opaque keyset cursor taken from a `next` link. Cannot be combined with `offset`.
+    pub cursor: Option<String>,
+    /// An RFC 3339 instant, or an interval with `..` for open ends, e.g. `../2024-01-01`.
+    #[param(example = "2024-01-01T00:00:00Z/..")]
+    pub datetime: Option<String>,
+    /// Comma separated properties to return, a subset of the collection's properties.
+    #[param(example = "name,pop_est")]
+    pub properties: Option<String>,
+    /// Leaves the geometry out of the returned features.
+    #[serde(
+        rename = "skipGeometry",
+        default,
+        deserialize_with = "deserialize_parsed_option"
+    )]
+    pub skip_geometry: Option<bool>,
+    /// The encoding of the response, e.g. `json` or `html`. Defaults to the one preferred by the
+    /// `Accept` header.
+    pub f: Option<String>,
+    /// Any other parameter, which must name a property the features are filtered on by equality.
+    #[serde(flatten)]
+    #[param(ignore)]
+    #[schema(ignore)]
+    pub property_filters: BTreeMap<String, String>,
+}
+
+impl GetItemsParams {
+    /// Parses `filter` according to `filter-lang`.
+    pub fn filter_expr(&self) -> Result<Option<Expr>, String> {
+        let Some(filter) = &self.filter else {
+            return Ok(None);
+        };
+        match self.filter_lang.unwrap_or_default() {
+            FilterLang::Cql2Text => cql2::parse_text(filter).map(Some),
+            FilterLang::Cql2Json => {
+                let value = serde_json::from_str(filter)
+                    .map_err(|e| format!("filter is not valid JSON: {}", e))?;
+                cql2::parse_json(&value).map(Some)
+            }
+        }
+    }
+
+    /// Parses `datetime` into an instant or an interval with at least one bounded end.
+    pub fn datetime(&self) -> Result<Option<Datetime>, String> {
+        let Some(datetime) = &self.datetime else {
+            return Ok(None);
+        };
+        let bound = |value: &str| -> Result<Option<String>, String> {
+            match value {
+                "" | ".." => Ok(None),
+                value if is_rfc3339(value) => Ok(Some(value.to_string())),
+                value => Err(format!("'{}' is not an RFC 3339 date or date-time", value)),
+            }
+        };
+        match datetime.split_once('/') {
+            None => match bound(datetime)? {
+                Some(instant) => Ok(Some(Datetime::Instant(instant))),
+                None => Err("datetime must not be empty".to_string()),
+            },
+            Some((start, end)) => {
+                let (start, end) = (bound(start)?, bound(end)?);
+                if start.is_none() && end.is_none() {
+                    return Err(
+                        "at least one end of a datetime interval must be bounded".to_string()
+                    );
+                }
+                Ok(Some(Datetime::Interval { start, end }))
+            }
+        }
+    }
+
+    /// The requested subset of properties, or `None` for all properties.
+    pub fn properties(&self) -> Option<Vec<String>> {
+        split_properties(self.properties.as_deref())
+    }
+
+    /// Decodes `cursor` and checks that it was created for the same `sortby`.
+    pub fn decode_cursor(&self) -> Result<Option<Cursor>, String> {
+        let Some(token) = &self.cursor else {
+            return Ok(None);
+        };
+        if self.offset.is_some() {
+            return Err("cursor and offset cannot be combined".to_string());
+        }
+        let cursor = Cursor::decode(token)?;
+        if cursor.sortby != self.sortby {
+            return Err("cursor was created for a different sortby".to_string());
+        }
+        Ok(Some(cursor))
+    }
+
+    /// Parses `sortby` into its keys. A leading `+` (which arrives as a space when not
+    /// percent-encoded) or no prefix means ascending, `-` means descending.
+    pub fn sort_keys(&self) -> Result<Vec<SortKey>, String> {
+        let Some(sortby) = &self.sortby else {
+            return Ok(Vec::new());
+        };
+        sortby
+            .split(',')
+            .map(|key| {
+                let key = key.trim();
+                let (property, descending) = match key.strip_prefix('-') {
+                    Some(property) => (property, true),
+                    None => (key.strip_prefix('+').unwrap_or(key), false),
+                };
+                if property.is_empty() {
+                    Err(format!("invalid sortby key '{}'", key))
+                } else {
+                    Ok(SortKey {
+                        property: property.to_string(),
+                        descending,
+                    })
+                }
+            })
+            .collect()
+    }
+
+    /// The number of features of the page: `limit`, or `default` without it, lowered to `max`.
+    pub fn page_limit(&self, default: u64, max: u64) -> u64 {
+        self.limit.unwrap_or(default).min(max)
+    }
+}
+
+#[derive(Deserialize, IntoParams, Default)]
+#[into_params(parameter_in = Query)]
+pub struct GetItemParams {
+    /// The CRS of the returned geometry. Defaults to CRS84.
+    #[param(value_type = Option<String>, example = "http://www.opengis.net/def/crs/EPSG/0/2056")]
+    pub crs: Option<Crs>,
+    /// Comma separated properties to return, a subset of the collection's properties.
+    #[param(example = "name,pop_est")]
+    pub properties: Option<String>,
+    /// Leaves the geometry out of the returned feature.
+    #[serde(rename = "skipGeometry", default)]
+    pub skip_geometry: Option<bool>,
+    /// The encoding of the response, e.g. `json` or `html`. Defaults to the one preferred by the
+    /// `Accept` header.
+    pub f: Option<String>,
+}
+
+impl GetItemParams {
+    /// The requested subset of properties, or `None` for all properties.
+    pub fn properties(&self) -> Option<Vec<String>> {
+        split_properties(self.properties.as_deref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_items_params_collects_property_filters() {
+        let params: GetItemsParams = serde_urlencoded::from_str(
+            "limit=5&offset=10&bbox=0,0,1,1&country=CH&status=active&f=html",
+        )
+        .unwrap();
+        assert_eq!(params.limit, Some(5));
+        assert_eq!(params.f.as_deref(), Some("html"));
+        assert_eq!(params.offset, Some(10));
+        assert_eq!(params.bbox, Some(vec![0.0, 0.0, 1.0, 1.0]));
+        assert_eq!(
+            params.property_filters,
+            BTreeMap::from([
+                ("country".to_string(), "CH".to_string()),
+                ("status".to_string(), "active".to_string()),
+            ])
+        );
+
+        for limit in ["ten", "0", "-1"] {
+            let error = serde_urlencoded::from_str::<GetItemsParams>(&format!("limit={}", limit))
+                .err()
+                .unwrap();
+            assert!(
+                error
+                    .to_string()
+                    .contains("limit must be a positive integer"),
+                "{}",
+                error
+            );
+        }
+
+        let params: GetItemsParams =
+            serde_urlencoded::from_str("properties=name,%20pop_est&skipGeometry=true").unwrap();
+        assert_eq!(
+            params.properties(),
+            Some(vec!["name".to_string(), "pop_est".to_string()])
+        );
+        assert_eq!(params.skip_geometry, Some(true));
+        assert!(params.property_filters.is_empty());
+
+        let params: GetItemsParams = serde_urlencoded::from_str("properties=").unwrap();
+        assert_eq!(params.properties(), Some(vec![]));
+    }
+
+    #[test]
+    fn test_page_limit() {
+        let params = |limit| GetItemsParams {
+            limit,
+            ..Default::default()
+        };
+        assert_eq!(params(None).page_limit(10, 100), 10);
+        assert_eq!(params(Some(50)).page_limit(10, 100), 50);
+        assert_eq!(params(Some(5000)).page_limit(10, 100), 100);
+    }
+
+    #[test]
+    fn test_datetime() {
+        let datetime = |value: &str| {
+            GetItemsParams {
+                datetime: Some(value.to_string()),
+                ..Default::default()
+            }
+            .datetime()
+        };
+        assert_eq!(
+            datetime("2024-01-01T12:00:00Z"),
+            Ok(Some(Datetime::Instant("2024-01-01T12:00:00Z".to_string())))
+        );
+        assert_eq!(
+            datetime("2023-01-01/2024-01-01"),
+            Ok(Some(Datetime::Interval {
+                start: Some("2023-01-01".to_string()),
+                end: Some("2024-01-01".to_string())
+            }))
+        );
+        assert_eq!(
+            datetime("../2024-01-01"),
+            Ok(Some(Datetime::Interval {
+                start: None,
+                end: Some("2024-01-01".to_string())
+            }))
+        );
+        assert_eq!(
+            datetime("2024-01-01/"),
+            Ok(Some(Datetime::Interval {
+                start: Some("2024-01-01".to_string()),
+                end: None
+            }))
+        );
+        assert_eq!(
+            datetime("../.."),
+            Err("at least one end of a datetime interval must be bounded".to_string())
+        );
+        assert_eq!(
+            datetime("yesterday"),
+            Err("'yesterday' is not an RFC 3339 date or date-time".to_string())
+        );
+    }
 }
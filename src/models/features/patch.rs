@@ -0,0 +1,22 @@
+use geojson::Geometry;
+use serde::{Deserialize, Deserializer};
+use serde_json::{Map, Value};
+
+/// Distinguishes a member set to `null` (`Some(None)`) from an absent one (`None`).
+fn deserialize_nullable<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+/// A JSON merge patch (RFC 7396) of a feature. Only the given members are changed, and
+/// properties set to `null` are cleared.
+#[derive(Deserialize, Debug, Default)]
+pub struct FeaturePatch {
+    #[serde(default, deserialize_with = "deserialize_nullable")]
+    pub geometry: Option<Option<Geometry>>,
+    #[serde(default)]
+    pub properties: Map<String, Value>,
+}
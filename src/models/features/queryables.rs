@@ -0,0 +1,66 @@
+/*
+ * The queryables of a collection, served as a JSON Schema document.
+ *
+ * https://docs.ogc.org/is/19-079r2/19-079r2.html#queryables
+ */
+use indexmap::IndexMap;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+const JSON_SCHEMA_DIALECT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+#[derive(Serialize, ToSchema, Debug, Clone, PartialEq)]
+pub struct QueryableProperty {
+    #[serde(skip)]
+    pub name: String,
+    pub title: String,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub type_: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<String>,
+    #[serde(rename = "x-ogc-role", skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+}
+
+impl QueryableProperty {
+    pub fn new(name: &str, type_: Option<&str>, format: Option<&str>) -> Self {
+        Self {
+            name: name.to_string(),
+            title: name.to_string(),
+            type_: type_.map(str::to_string),
+            format: format.map(str::to_string),
+            role: None,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct Queryables {
+    #[serde(rename = "$schema")]
+    pub schema: &'static str,
+    #[serde(rename = "$id")]
+    pub id: String,
+    #[serde(rename = "type")]
+    pub type_: &'static str,
+    pub title: String,
+    #[schema(value_type = Object)]
+    pub properties: IndexMap<String, QueryableProperty>,
+    #[serde(rename = "additionalProperties")]
+    pub additional_properties: bool,
+}
+
+impl Queryables {
+    pub fn new(id: String, title: String, properties: Vec<QueryableProperty>) -> Self {
+        Self {
+            schema: JSON_SCHEMA_DIALECT,
+            id,
+            type_: "object",
+            title,
+            properties: properties
+                .into_iter()
+                .map(|property| (property.name.clone(), property))
+                .collect(),
+            additional_properties: false,
+        }
+    }
+}
@@ -1,15 +1,29 @@
 mod common;
 mod core;
 mod features;
+mod tiles;
 
-pub use common::link::{Link, LinkRel};
+pub use common::{
+    crs::Crs,
+    format::FormatParams,
+    link::{Link, LinkRel},
+    problem::Problem,
+};
 pub use core::{
-    collection::{Collection, Collections},
+    collection::{Collection, Collections, Contact, Extent, SpatialExtent, TemporalExtent},
     conformance::Conformance,
     landing::LandingPage,
 };
-pub use features::feature_collection::OgcApiFeatureCollection;
+pub use features::{cursor::Cursor, feature_collection::OgcApiFeatureCollection};
 pub use features::{
-    parameters::GetItemsParams,
+    parameters::{Datetime, FilterLang, GetItemParams, GetItemsParams, SortKey},
+    patch::FeaturePatch,
+    queryables::{QueryableProperty, Queryables},
     schema::{DocFeatureCollectionSchema, DocFeatureSchema},
 };
+pub use tiles::{
+    tile_matrix_set::{
+        MAX_TILE_MATRIX, TileMatrixSet, TileMatrixSetItem, TileMatrixSets, TilingScheme,
+    },
+    tileset::{TileLayer, TileMatrixLimits, TileSet, TileSetItem, TileSetLink, TileSets},
+};
@@ -0,0 +1,2 @@
+pub mod tile_matrix_set;
+pub mod tileset;
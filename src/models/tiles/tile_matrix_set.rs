@@ -0,0 +1,228 @@
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::models::{Crs, Link};
+
+/// Half the width of the Web Mercator projection, in meters.
+const WEB_MERCATOR_HALF_WIDTH: f64 = 20037508.3427892;
+
+/// The size of a pixel used by the OGC to turn cell sizes into scale denominators, in meters.
+const STANDARDIZED_PIXEL_SIZE: f64 = 0.00028;
+
+/// The meters per degree at the equator of the WGS 84 ellipsoid.
+const METERS_PER_DEGREE: f64 = 6378137.0 * 2.0 * std::f64::consts::PI / 360.0;
+
+/// The width and height of the tiles, in pixels.
+pub const TILE_SIZE: u32 = 256;
+
+/// The deepest tile matrix of the tile matrix sets.
+pub const MAX_TILE_MATRIX: u8 = 24;
+
+/// The tile matrix sets tiles are served in, as defined in the OGC registry
+/// (http://www.opengis.net/def/tilematrixset/OGC/1.0/).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TilingScheme {
+    /// Web Mercator, with a single tile at zoom level 0.
+    WebMercatorQuad,
+    /// CRS84, with two tiles at zoom level 0 covering the western and eastern hemispheres.
+    WorldCrs84Quad,
+}
+
+impl TilingScheme {
+    pub const ALL: [Self; 2] = [Self::WebMercatorQuad, Self::WorldCrs84Quad];
+
+    pub fn from_id(id: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|scheme| scheme.id() == id)
+    }
+
+    pub fn id(&self) -> &'static str {
+        match self {
+            Self::WebMercatorQuad => "WebMercatorQuad",
+            Self::WorldCrs84Quad => "WorldCRS84Quad",
+        }
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            Self::WebMercatorQuad => "Google Maps Compatible for the World",
+            Self::WorldCrs84Quad => "CRS84 for the World",
+        }
+    }
+
+    pub fn uri(&self) -> String {
+        format!(
+            "http://www.opengis.net/def/tilematrixset/OGC/1.0/{}",
+            self.id()
+        )
+    }
+
+    pub fn crs(&self) -> Crs {
+        match self {
+            Self::WebMercatorQuad => Crs::from_uri("EPSG:3857").expect("EPSG:3857 is valid"),
+            Self::WorldCrs84Quad => Crs::crs84(),
+        }
+    }
+
+    /// The top left corner of the tile matrices.
+    fn origin(&self) -> [f64; 2] {
+        match self {
+            Self::WebMercatorQuad => [-WEB_MERCATOR_HALF_WIDTH, WEB_MERCATOR_HALF_WIDTH],
+            Self::WorldCrs84Quad => [-180.0, 90.0],
+        }
+    }
+
+    /// The width of a tile at zoom level 0, in units of the CRS.
+    fn base_tile_width(&self) -> f64 {
+        match self {
+            Self::WebMercatorQuad => 2.0 * WEB_MERCATOR_HALF_WIDTH,
+            Self::WorldCrs84Quad => 180.0,
+        }
+    }
+
+    /// The number of columns and rows of tiles at a zoom level.
+    pub fn matrix_size(&self, z: u8) -> (u32, u32) {
+        let rows = 1u32 << z;
+        match self {
+            Self::WebMercatorQuad => (rows, rows),
+            Self::WorldCrs84Quad => (rows * 2, rows),
+        }
+    }
+
+    /// Whether a tile is within the matrix of its zoom level.
+    pub fn contains(&self, z: u8, x: u32, y: u32) -> bool {
+        let (columns, rows) = self.matrix_size(z);
+        z <= MAX_TILE_MATRIX && x < columns && y < rows
+    }
+
+    /// The bounds of a tile as `[min x, min y, max x, max y]` in the CRS, longitude first for
+    /// CRS84.
+    pub fn tile_bounds(&self, z: u8, x: u32, y: u32) -> [f64; 4] {
+        let [origin_x, origin_y] = self.origin();
+        let width = self.base_tile_width() / f64::from(1u32 << z);
+        [
+            origin_x + f64::from(x) * width,
+            origin_y - f64::from(y + 1) * width,
+            origin_x + f64::from(x + 1) * width,
+            origin_y - f64::from(y) * width,
+        ]
+    }
+
+    pub fn tile_matrix(&self, z: u8) -> TileMatrix {
+        let cell_size = self.base_tile_width() / f64::from(TILE_SIZE) / f64::from(1u32 << z);
+        let meters_per_unit = match self {
+            Self::WebMercatorQuad => 1.0,
+            Self::WorldCrs84Quad => METERS_PER_DEGREE,
+        };
+        let (matrix_width, matrix_height) = self.matrix_size(z);
+        TileMatrix {
+            id: z.to_string(),
+            scale_denominator: cell_size * meters_per_unit / STANDARDIZED_PIXEL_SIZE,
+            cell_size,
+            corner_of_origin: "topLeft",
+            point_of_origin: self.origin(),
+            tile_width: TILE_SIZE,
+            tile_height: TILE_SIZE,
+            matrix_width,
+            matrix_height,
+        }
+    }
+
+    /// The definition of the tile matrix set, following the JSON encoding of OGC Two Dimensional
+    /// Tile Matrix Set 2.0.
+    pub fn definition(&self) -> TileMatrixSet {
+        TileMatrixSet {
+            id: self.id(),
+            title: self.title(),
+            uri: self.uri(),
+            crs: self.crs().uri().to_string(),
+            ordered_axes: match self {
+                Self::WebMercatorQuad => ["X", "Y"],
+                Self::WorldCrs84Quad => ["Lon", "Lat"],
+            },
+            tile_matrices: (0..=MAX_TILE_MATRIX).map(|z| self.tile_matrix(z)).collect(),
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TileMatrix {
+    pub id: String,
+    pub scale_denominator: f64,
+    pub cell_size: f64,
+    pub corner_of_origin: &'static str,
+    pub point_of_origin: [f64; 2],
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub matrix_width: u32,
+    pub matrix_height: u32,
+}
+
+#[derive(Serialize, ToSchema, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TileMatrixSet {
+    pub id: &'static str,
+    pub title: &'static str,
+    pub uri: String,
+    pub crs: String,
+    pub ordered_axes: [&'static str; 2],
+    pub tile_matrices: Vec<TileMatrix>,
+}
+
+/// A tile matrix set in the list of the available ones.
+#[derive(Serialize, ToSchema)]
+pub struct TileMatrixSetItem {
+    pub id: &'static str,
+    pub title: &'static str,
+    pub uri: String,
+    pub links: Vec<Link>,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TileMatrixSets {
+    pub tile_matrix_sets: Vec<TileMatrixSetItem>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tile_bounds() {
+        let scheme = TilingScheme::WebMercatorQuad;
+        assert_eq!(
+            scheme.tile_bounds(0, 0, 0),
+            [
+                -WEB_MERCATOR_HALF_WIDTH,
+                -WEB_MERCATOR_HALF_WIDTH,
+                WEB_MERCATOR_HALF_WIDTH,
+                WEB_MERCATOR_HALF_WIDTH
+            ]
+        );
+        assert_eq!(
+            scheme.tile_bounds(1, 1, 0),
+            [0.0, 0.0, WEB_MERCATOR_HALF_WIDTH, WEB_MERCATOR_HALF_WIDTH]
+        );
+
+        let scheme = TilingScheme::WorldCrs84Quad;
+        assert_eq!(scheme.matrix_size(0), (2, 1));
+        assert_eq!(scheme.tile_bounds(0, 1, 0), [0.0, -90.0, 180.0, 90.0]);
+        assert_eq!(scheme.tile_bounds(2, 0, 3), [-180.0, -90.0, -135.0, -45.0]);
+        assert!(scheme.contains(2, 7, 3));
+        assert!(!scheme.contains(2, 8, 0));
+        assert!(!scheme.contains(2, 0, 4));
+    }
+
+    #[test]
+    fn test_tile_matrix() {
+        let matrix = TilingScheme::WebMercatorQuad.tile_matrix(0);
+        assert!((matrix.scale_denominator - 559082264.0287178).abs() < 1e-4);
+        assert!((matrix.cell_size - 156543.03392804097).abs() < 1e-9);
+
+        let matrix = TilingScheme::WorldCrs84Quad.tile_matrix(1);
+        assert!((matrix.scale_denominator - 139770566.00717944).abs() < 1e-4);
+        assert_eq!(matrix.cell_size, 0.3515625);
+        assert_eq!((matrix.matrix_width, matrix.matrix_height), (4, 2));
+    }
+}
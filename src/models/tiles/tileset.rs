@@ -0,0 +1,76 @@
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::models::Link;
+
+/// A link of a tileset, which may be a URI template of its tiles.
+#[derive(Serialize, ToSchema)]
+pub struct TileSetLink {
+    #[serde(flatten)]
+    pub link: Link,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub templated: bool,
+}
+
+impl From<Link> for TileSetLink {
+    fn from(link: Link) -> Self {
+        Self {
+            link,
+            templated: false,
+        }
+    }
+}
+
+/// The range of tiles available in a tile matrix.
+#[derive(Serialize, ToSchema, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TileMatrixLimits {
+    pub tile_matrix: String,
+    pub min_tile_row: u32,
+    pub max_tile_row: u32,
+    pub min_tile_col: u32,
+    pub max_tile_col: u32,
+}
+
+/// A layer of the vector tiles, holding the features of a collection.
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TileLayer {
+    pub id: String,
+    pub data_type: &'static str,
+    pub min_tile_matrix: String,
+    pub max_tile_matrix: String,
+    #[schema(value_type = Object)]
+    pub properties_schema: serde_json::Value,
+}
+
+/// The metadata of the tiles of a collection in a tile matrix set.
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TileSet {
+    pub title: String,
+    pub data_type: &'static str,
+    pub crs: String,
+    #[serde(rename = "tileMatrixSetURI")]
+    pub tile_matrix_set_uri: String,
+    pub links: Vec<TileSetLink>,
+    pub tile_matrix_set_limits: Vec<TileMatrixLimits>,
+    pub layers: Vec<TileLayer>,
+}
+
+/// A tileset in the list of the tilesets of a collection.
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TileSetItem {
+    pub title: String,
+    pub data_type: &'static str,
+    pub crs: String,
+    #[serde(rename = "tileMatrixSetURI")]
+    pub tile_matrix_set_uri: String,
+    pub links: Vec<TileSetLink>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TileSets {
+    pub tilesets: Vec<TileSetItem>,
+}
@@ -1,25 +1,66 @@
 use crate::{
-    handlers::{self, core, features},
+    encoders, error,
+    handlers::{self, core, features, queryables, tiles, transactions},
     state::AppState,
 };
-use axum::{Router, routing::get};
-use utoipa::OpenApi;
+use axum::{Router, middleware, response::Redirect, routing::get};
 use utoipa_swagger_ui::SwaggerUi;
 
 pub fn create_router(app_state: AppState) -> Router {
     Router::new()
-        .merge(SwaggerUi::new("/swagger-ui").url("/api/openapi.json", handlers::ApiDoc::openapi()))
+        .merge(SwaggerUi::new("/swagger-ui").url(
+            "/api/openapi.json",
+            handlers::api_doc(&app_state.config, &app_state.encoders),
+        ))
+        .route(
+            "/api.html",
+            get(|| async { Redirect::temporary("/swagger-ui/") }),
+        )
         .route("/", get(core::get_landing_page))
         .route("/conformance", get(core::get_conformance))
+        .route("/tileMatrixSets", get(tiles::get_tile_matrix_sets))
+        .route(
+            "/tileMatrixSets/{tile_matrix_set_id}",
+            get(tiles::get_tile_matrix_set),
+        )
         .route("/collections", get(core::get_collections))
         .route("/collections/{collection_id}", get(core::get_collection))
         .route(
             "/collections/{collection_id}/items",
-            get(features::get_collection_items),
+            get(features::get_collection_items).post(features::post_collection_items),
+        )
+        .route(
+            "/collections/{collection_id}/queryables",
+            get(queryables::get_queryables),
+        )
+        .route(
+            "/collections/{collection_id}/sortables",
+            get(queryables::get_sortables),
+        )
+        .route(
+            "/collections/{collection_id}/schema.xsd",
+            get(queryables::get_xml_schema),
+        )
+        .route(
+            "/collections/{collection_id}/tiles",
+            get(tiles::get_collection_tilesets),
+        )
+        .route(
+            "/collections/{collection_id}/tiles/{tile_matrix_set_id}",
+            get(tiles::get_collection_tileset),
+        )
+        .route(
+            "/collections/{collection_id}/tiles/{tile_matrix_set_id}/{z}/{x}/{y}",
+            get(tiles::get_collection_tile),
         )
         .route(
             "/collections/{collection_id}/items/{id}",
-            get(features::get_collection_item),
+            get(features::get_collection_item)
+                .put(transactions::put_collection_item)
+                .patch(transactions::patch_collection_item)
+                .delete(transactions::delete_collection_item),
         )
+        .layer(middleware::from_fn(error::problem_details))
+        .layer(middleware::from_fn(encoders::vary_accept))
         .with_state(app_state)
 }
@@ -1,8 +1,9 @@
-use crate::{config::AppConfig, storage::Storage};
+use crate::{config::AppConfig, encoders::Encoders, storage::Storage};
 use std::sync::Arc;
 
 #[derive(Clone)]
 pub struct AppState {
     pub store: Arc<dyn Storage>,
     pub config: Arc<AppConfig>,
+    pub encoders: Arc<Encoders>,
 }
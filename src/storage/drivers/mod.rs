@@ -1,3 +1,3 @@
 mod postgis;
 
-pub use postgis::Postgis;
+pub use postgis::{Postgis, detect_id_types, discover_collections};
@@ -0,0 +1,474 @@
+/*
+ * Discovery of the tables published as collections without being configured.
+ *
+ * Spatial tables are listed from the `geometry_columns` view, and from `geography_columns` when
+ * enabled. Each table needs a single column primary key, which becomes the feature ID, and its
+ * other non-spatial columns become the properties. Names that would need quoting are left out,
+ * as the generated SQL uses them verbatim. A configured collection naming the same table, or with
+ * the same ID and no table, is merged over the discovered one, its fields taking precedence.
+ */
+use crate::config::{AppConfig, CollectionConfig, DiscoveryConfig};
+use crate::models::Crs;
+use sqlx::PgPool;
+
+const GEOMETRY_COLUMNS_SQL: &str = "SELECT f_table_schema::text, f_table_name::text, f_geometry_column::text, srid, false FROM geometry_columns";
+
+const GEOGRAPHY_COLUMNS_SQL: &str = "SELECT f_table_schema::text, f_table_name::text, f_geography_column::text, srid, true FROM geography_columns";
+
+const PRIMARY_KEY_SQL: &str = "SELECT a.attname::text FROM pg_index i JOIN pg_attribute a ON a.attrelid = i.indrelid AND a.attnum = ANY(i.indkey) WHERE i.indrelid = format('%I.%I', $1::text, $2::text)::regclass AND i.indisprimary";
+
+const COLUMNS_SQL: &str = "SELECT column_name::text FROM information_schema.columns WHERE table_schema = $1 AND table_name = $2 ORDER BY ordinal_position";
+
+/// A spatial column, as listed by `geometry_columns` or `geography_columns`.
+#[derive(Debug, Clone, PartialEq)]
+struct SpatialColumn {
+    schema: String,
+    table: String,
+    column: String,
+    srid: i32,
+    geography: bool,
+}
+
+/// Matches a name against a pattern where `*` stands for any characters and `?` for one.
+fn matches_pattern(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    // The position in the pattern after the last `*`, and how far the name is matched by it.
+    let mut backtrack = None;
+    let (mut p, mut n) = (0, 0);
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p + 1, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((after_star, matched)) => {
+                    p = after_star;
+                    n = matched + 1;
+                    backtrack = Some((after_star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Whether the allow and deny lists let a table be published.
+fn is_published(discovery: &DiscoveryConfig, schema: &str, table: &str) -> bool {
+    let qualified = format!("{}.{}", schema, table);
+    let matches_table =
+        |pattern: &String| matches_pattern(pattern, table) || matches_pattern(pattern, &qualified);
+    let matches_schema = |pattern: &String| matches_pattern(pattern, schema);
+
+    (discovery.schemas.is_empty() || discovery.schemas.iter().any(matches_schema))
+        && !discovery.exclude_schemas.iter().any(matches_schema)
+        && (discovery.tables.is_empty() || discovery.tables.iter().any(matches_table))
+        && !discovery.exclude_tables.iter().any(matches_table)
+}
+
+/// Whether a name can be used in SQL without quoting.
+fn is_plain_identifier(name: &str) -> bool {
+    name.chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_lowercase() || c == '_')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+/// The ID of the collection of a table: its name in the `public` schema, its schema-qualified
+/// name otherwise.
+fn collection_id(schema: &str, table: &str) -> String {
+    if schema == "public" {
+        table.to_string()
+    } else {
+        format!("{}.{}", schema, table)
+    }
+}
+
+/// The schema and name of a configured table, unqualified names being taken from `public`.
+fn table_key(table: &str) -> (&str, &str) {
+    table.split_once('.').unwrap_or(("public", table))
+}
+
+/// Builds the fields of the collection of a table from its spatial columns, the first of which
+/// holds the features' geometry, its primary key and its other columns.
+fn build_collection(
+    spatial_columns: &[&SpatialColumn],
+    id_column: String,
+    columns: Vec<String>,
+) -> toml::Table {
+    let spatial = spatial_columns[0];
+    // Geographies are cast, so that the queries written for geometries apply to them.
+    let geometry_column = if spatial.geography {
+        format!("{}::geometry", spatial.column)
+    } else {
+        spatial.column.clone()
+    };
+    let storage_crs = match spatial.srid {
+        4326 | 0 => None,
+        srid => Crs::from_uri(&format!("EPSG:{}", srid)),
+    };
+    let properties: Vec<String> = columns
+        .into_iter()
+        .filter(|column| {
+            *column != id_column
+                && is_plain_identifier(column)
+                && !spatial_columns
+                    .iter()
+                    .any(|spatial| spatial.column == *column)
+        })
+        .collect();
+
+    let mut fields = toml::Table::new();
+    fields.insert(
+        "table".to_string(),
+        format!("{}.{}", spatial.schema, spatial.table).into(),
+    );
+    fields.insert("id_column".to_string(), id_column.into());
+    fields.insert("geometry_column".to_string(), geometry_column.into());
+    fields.insert("properties".to_string(), properties.into());
+    if let Some(crs) = storage_crs {
+        fields.insert("storage_crs".to_string(), crs.uri().into());
+    }
+    fields
+}
+
+/// Merges the fields set in the configuration over the fields of a discovered collection.
+fn merge_collection(
+    configured: &toml::Table,
+    mut discovered: toml::Table,
+) -> Result<CollectionConfig, toml::de::Error> {
+    discovered.extend(configured.clone());
+    discovered.try_into()
+}
+
+/// The ID of the configured collection a table is merged into: the one naming the table, or
+/// else the one with the table's collection ID and no table. Collections already merged are
+/// left out.
+fn configured_collection(config: &AppConfig, schema: &str, table: &str) -> Option<String> {
+    let unmerged = || {
+        config
+            .collections
+            .iter()
+            .filter_map(|(id, collection)| Some((id, collection.configured.as_ref()?)))
+    };
+    unmerged()
+        .find(|(_, fields)| {
+            fields
+                .get("table")
+                .and_then(|configured| configured.as_str())
+                .is_some_and(|configured| table_key(configured) == (schema, table))
+        })
+        .or_else(|| {
+            unmerged().find(|(id, fields)| {
+                **id == collection_id(schema, table) && !fields.contains_key("table")
+            })
+        })
+        .map(|(id, _)| id.clone())
+}
+
+/// Merges the configured collections over the spatial tables they match, and adds a collection
+/// for each other published spatial table, after the configured collections. Configured
+/// collections keep their ID and position, and are merged even when the allow and deny lists
+/// leave their table out. A table whose collection ID is already configured is not published.
+pub async fn discover_collections(
+    pool: &PgPool,
+    config: &mut AppConfig,
+) -> Result<(), sqlx::Error> {
+    let Some(discovery) = config.discovery.clone() else {
+        return Ok(());
+    };
+
+    let mut sql = GEOMETRY_COLUMNS_SQL.to_string();
+    if discovery.geography {
+        sql = format!("{} UNION ALL {}", sql, GEOGRAPHY_COLUMNS_SQL);
+    }
+    let rows: Vec<(String, String, String, i32, bool)> =
+        sqlx::query_as(&format!("{} ORDER BY 1, 2, 3", sql))
+            .fetch_all(pool)
+            .await?;
+    let spatial_columns: Vec<SpatialColumn> = rows
+        .into_iter()
+        .map(|(schema, table, column, srid, geography)| SpatialColumn {
+            schema,
+            table,
+            column,
+            srid,
+            geography,
+        })
+        .collect();
+
+    let mut tables: Vec<(&str, &str)> = spatial_columns
+        .iter()
+        .map(|spatial| (spatial.schema.as_str(), spatial.table.as_str()))
+        .collect();
+    tables.dedup();
+
+    for (schema, table) in tables {
+        let configured = configured_collection(config, schema, table);
+        let collection_id = configured
+            .clone()
+            .unwrap_or_else(|| collection_id(schema, table));
+        if configured.is_none()
+            && (!is_published(&discovery, schema, table)
+                || config.collections.contains_key(&collection_id))
+        {
+            continue;
+        }
+        let table_columns: Vec<&SpatialColumn> = spatial_columns
+            .iter()
+            .filter(|spatial| spatial.schema == schema && spatial.table == table)
+            .collect();
+        if !is_plain_identifier(schema)
+            || !is_plain_identifier(table)
+            || !is_plain_identifier(&table_columns[0].column)
+        {
+            tracing::warn!(
+                "Table {}.{} is not discovered, its names need quoting",
+                schema,
+                table
+            );
+            continue;
+        }
+
+        let primary_key: Vec<String> = sqlx::query_scalar(PRIMARY_KEY_SQL)
+            .bind(schema)
+            .bind(table)
+            .fetch_all(pool)
+            .await?;
+        let id_column = match <[String; 1]>::try_from(primary_key) {
+            Ok([id_column]) if is_plain_identifier(&id_column) => id_column,
+            _ => {
+                tracing::warn!(
+                    "Table {}.{} is not discovered, it has no single column primary key",
+                    schema,
+                    table
+                );
+                continue;
+            }
+        };
+        let columns: Vec<String> = sqlx::query_scalar(COLUMNS_SQL)
+            .bind(schema)
+            .bind(table)
+            .fetch_all(pool)
+            .await?;
+
+        let discovered = build_collection(&table_columns, id_column, columns);
+        tracing::info!(
+            "Publishing table {}.{} as collection {}",
+            schema,
+            table,
+            collection_id
+        );
+        let collection = match config.collections.get(&collection_id) {
+            Some(CollectionConfig {
+                configured: Some(fields),
+                ..
+            }) => merge_collection(fields, discovered).map_err(|e| {
+                sqlx::Error::Configuration(format!("collection {}: {}", collection_id, e).into())
+            })?,
+            _ => discovered
+                .try_into()
+                .expect("the discovered fields are those of a collection"),
+        };
+        config.collections.insert(collection_id, collection);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spatial_column(column: &str, srid: i32, geography: bool) -> SpatialColumn {
+        SpatialColumn {
+            schema: "gis".to_string(),
+            table: "roads".to_string(),
+            column: column.to_string(),
+            srid,
+            geography,
+        }
+    }
+
+    #[test]
+    fn test_matches_pattern() {
+        assert!(matches_pattern("roads", "roads"));
+        assert!(matches_pattern("road*", "roads"));
+        assert!(matches_pattern("*_tmp", "roads_tmp"));
+        assert!(matches_pattern("r?ads", "roads"));
+        assert!(matches_pattern("*a*s", "roads"));
+        assert!(matches_pattern("*", ""));
+        assert!(!matches_pattern("road", "roads"));
+        assert!(!matches_pattern("*_tmp", "roads"));
+        assert!(!matches_pattern("r?ds", "roads"));
+    }
+
+    #[test]
+    fn test_is_published() {
+        let discovery: DiscoveryConfig = toml::from_str(
+            r#"
+            schemas = ["public", "gis*"]
+            exclude_schemas = ["gis_staging"]
+            exclude_tables = ["*_tmp", "gis.secret"]
+            "#,
+        )
+        .unwrap();
+        assert!(is_published(&discovery, "public", "roads"));
+        assert!(is_published(&discovery, "gis", "rivers"));
+        assert!(!is_published(&discovery, "topology", "rivers"));
+        assert!(!is_published(&discovery, "gis_staging", "rivers"));
+        assert!(!is_published(&discovery, "public", "roads_tmp"));
+        assert!(!is_published(&discovery, "gis", "secret"));
+        assert!(is_published(&discovery, "public", "secret"));
+
+        let discovery = DiscoveryConfig {
+            tables: vec!["public.roads".to_string()],
+            ..Default::default()
+        };
+        assert!(is_published(&discovery, "public", "roads"));
+        assert!(!is_published(&discovery, "gis", "roads"));
+    }
+
+    #[test]
+    fn test_build_collection() {
+        let geom = spatial_column("geom", 2056, false);
+        let centroid = spatial_column("centroid", 2056, false);
+        let collection: CollectionConfig = build_collection(
+            &[&geom, &centroid],
+            "gid".to_string(),
+            ["gid", "name", "geom", "Lanes", "centroid", "surface"]
+                .map(String::from)
+                .to_vec(),
+        )
+        .try_into()
+        .unwrap();
+        assert_eq!(collection.table, "gis.roads");
+        assert_eq!(collection.id_column, "gid");
+        assert_eq!(collection.geometry_column, "geom");
+        assert_eq!(collection.properties, ["name", "surface"]);
+        assert_eq!(
+            collection.storage_crs,
+            Some(Crs::from_uri("EPSG:2056").unwrap())
+        );
+        assert!(!collection.writable);
+
+        let geog = spatial_column("geog", 4326, true);
+        let collection: CollectionConfig =
+            build_collection(&[&geog], "id".to_string(), vec!["id".to_string()])
+                .try_into()
+                .unwrap();
+        assert_eq!(collection.geometry_column, "geog::geometry");
+        assert_eq!(collection.storage_crs, None);
+    }
+
+    #[test]
+    fn test_merge_collection() {
+        let config: AppConfig = toml::from_str(
+            r#"
+            title = "Test"
+            description = "Test"
+            url_base = "http://localhost"
+
+            [collections.roads]
+            title = "Roads"
+            writable = true
+
+            [collections.highways]
+            table = "gis.roads"
+            properties = ["name"]
+
+            [collections.rivers]
+            table = "rivers"
+            id_column = "id"
+            geometry_column = "geom"
+            properties = []
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            configured_collection(&config, "gis", "roads").as_deref(),
+            Some("highways")
+        );
+        assert_eq!(
+            configured_collection(&config, "public", "roads").as_deref(),
+            Some("roads")
+        );
+        assert_eq!(
+            configured_collection(&config, "public", "rivers").as_deref(),
+            Some("rivers")
+        );
+        assert_eq!(configured_collection(&config, "gis", "rivers"), None);
+
+        let geom = spatial_column("geom", 2056, false);
+        let discovered = build_collection(
+            &[&geom],
+            "gid".to_string(),
+            ["gid", "name", "lanes"].map(String::from).to_vec(),
+        );
+        let roads = &config.collections["roads"];
+        let collection =
+            merge_collection(roads.configured.as_ref().unwrap(), discovered.clone()).unwrap();
+        assert_eq!(collection.title.as_deref(), Some("Roads"));
+        assert!(collection.writable);
+        assert_eq!(collection.table, "gis.roads");
+        assert_eq!(collection.id_column, "gid");
+        assert_eq!(collection.properties, ["name", "lanes"]);
+        assert_eq!(
+            collection.storage_crs,
+            Some(Crs::from_uri("EPSG:2056").unwrap())
+        );
+        assert!(collection.configured.is_none());
+
+        let highways = &config.collections["highways"];
+        let collection =
+            merge_collection(highways.configured.as_ref().unwrap(), discovered).unwrap();
+        assert_eq!(collection.properties, ["name"]);
+        assert_eq!(collection.geometry_column, "geom");
+    }
+
+    #[test]
+    fn test_check_collections() {
+        let mut config: AppConfig = toml::from_str(
+            r#"
+            title = "Test"
+            description = "Test"
+            url_base = "http://localhost"
+
+            [collections.roads]
+            title = "Roads"
+            table = "roads"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.check_collections().unwrap_err(),
+            "Collection roads matches no discovered table, so it needs id_column, geometry_column, properties"
+        );
+        config.collections["roads"].configured = None;
+        assert!(config.check_collections().is_ok());
+
+        let geog = spatial_column("geog", 4326, true);
+        let discovered = build_collection(&[&geog], "id".to_string(), vec!["id".to_string()]);
+        let configured = toml::from_str("writable = true").unwrap();
+        config.collections["roads"] = merge_collection(&configured, discovered).unwrap();
+        assert_eq!(
+            config.check_collections().unwrap_err(),
+            "Collection roads cannot be writable, its geometry is read from geog::geometry"
+        );
+    }
+
+    #[test]
+    fn test_collection_id() {
+        assert_eq!(collection_id("public", "roads"), "roads");
+        assert_eq!(collection_id("gis", "roads"), "gis.roads");
+        assert_eq!(table_key("roads"), ("public", "roads"));
+        assert_eq!(table_key("gis.roads"), ("gis", "roads"));
+    }
+}
@@ -0,0 +1,422 @@
+/*
+ * Translation of CQL2 expressions into parameterized PostGIS SQL.
+ *
+ * Literals are never inlined: each one is pushed onto the bind list and referenced by its
+ * placeholder. Property names are validated against the collection configuration before
+ * they are written into the SQL, so only configured columns can be referenced. Literals are
+ * checked against the types of the columns they are compared with, so that mismatches are
+ * reported as invalid filters rather than failing in the database.
+ */
+use super::SqlValue;
+use crate::config::CollectionConfig;
+use crate::cql2::{Expr, Scalar, SpatialOperand};
+use std::collections::HashMap;
+
+/// The name under which the geometry column can always be referenced in filters.
+const GEOMETRY_PROPERTY: &str = "geometry";
+
+/// The kinds of values that PostgreSQL compares with each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Number,
+    Text,
+    Bool,
+    Temporal,
+    Uuid,
+    /// Values that no literal can be compared with, such as JSON or arrays.
+    Other,
+}
+
+impl Kind {
+    /// The kind of a column, from its type as reported by `information_schema.columns`.
+    fn of_column(data_type: &str) -> Self {
+        match data_type {
+            "smallint" | "integer" | "bigint" | "numeric" | "real" | "double precision" => {
+                Self::Number
+            }
+            "text" | "character varying" | "character" => Self::Text,
+            "boolean" => Self::Bool,
+            "date" | "timestamp without time zone" | "timestamp with time zone" => Self::Temporal,
+            "uuid" => Self::Uuid,
+            _ => Self::Other,
+        }
+    }
+
+    /// The kind of a literal, `None` for properties.
+    fn of_literal(scalar: &Scalar) -> Option<Self> {
+        match scalar {
+            Scalar::Property(_) => None,
+            Scalar::String(_) => Some(Self::Text),
+            Scalar::Number(_) => Some(Self::Number),
+            Scalar::Bool(_) => Some(Self::Bool),
+            Scalar::Date(_) | Scalar::Timestamp(_) => Some(Self::Temporal),
+        }
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            Self::Number => "a number",
+            Self::Text => "a string",
+            Self::Bool => "a boolean",
+            Self::Temporal => "a date or timestamp",
+            Self::Uuid => "a UUID",
+            Self::Other => "a value of another type",
+        }
+    }
+}
+
+/// Whether `value` is a UUID in its hyphenated form.
+pub(super) fn is_uuid(value: &str) -> bool {
+    value.len() == 36
+        && value.char_indices().all(|(i, c)| match i {
+            8 | 13 | 18 | 23 => c == '-',
+            _ => c.is_ascii_hexdigit(),
+        })
+}
+
+struct FilterSqlBuilder<'a> {
+    collection: &'a CollectionConfig,
+    /// The data types of the columns, properties missing from it being left unchecked.
+    column_types: &'a HashMap<String, String>,
+    binds: &'a mut Vec<SqlValue>,
+}
+
+impl FilterSqlBuilder<'_> {
+    fn bind(&mut self, value: SqlValue) -> String {
+        self.binds.push(value);
+        format!("${}", self.binds.len())
+    }
+
+    fn property(&self, name: &str) -> Result<String, String> {
+        if self.collection.properties.iter().any(|p| p == name) {
+            Ok(name.to_string())
+        } else {
+            Err(format!(
+                "unknown property '{}', filterable properties are: {}",
+                name,
+                self.collection.properties.join(", ")
+            ))
+        }
+    }
+
+    fn geometry_property(&self, name: &str) -> Result<String, String> {
+        if name == GEOMETRY_PROPERTY || name == self.collection.geometry_column {
+            Ok(self.collection.geometry_column.clone())
+        } else {
+            Err(format!(
+                "'{}' is not a geometry property, use '{}'",
+                name, GEOMETRY_PROPERTY
+            ))
+        }
+    }
+
+    fn scalar(&mut self, scalar: &Scalar) -> Result<String, String> {
+        Ok(match scalar {
+            Scalar::Property(name) => self.property(name)?,
+            Scalar::String(s) => self.bind(SqlValue::Text(s.clone())),
+            Scalar::Number(n) => self.bind(SqlValue::Float(*n)),
+            Scalar::Bool(b) => self.bind(SqlValue::Bool(*b)),
+            Scalar::Date(d) => format!("CAST({} AS date)", self.bind(SqlValue::Text(d.clone()))),
+            Scalar::Timestamp(t) => format!(
+                "CAST({} AS timestamptz)",
+                self.bind(SqlValue::Text(t.clone()))
+            ),
+        })
+    }
+
+    /// The kind of a property or literal, `None` for properties of unknown type.
+    fn kind(&self, scalar: &Scalar) -> Option<Kind> {
+        match scalar {
+            Scalar::Property(name) => self
+                .column_types
+                .get(name)
+                .map(|data_type| Kind::of_column(data_type)),
+            literal => Kind::of_literal(literal),
+        }
+    }
+
+    /// Translates scalars compared with each other, checking that they have the kind of the
+    /// first property of known type. Strings compared with UUID columns are validated and cast.
+    fn compared_scalars(&mut self, scalars: &[&Scalar]) -> Result<Vec<String>, String> {
+        let expected = scalars.iter().find_map(|scalar| match scalar {
+            Scalar::Property(name) => self
+                .column_types
+                .get(name)
+                .map(|data_type| (name.as_str(), Kind::of_column(data_type))),
+            _ => None,
+        });
+
+        let mut sql = Vec::with_capacity(scalars.len());
+        for scalar in scalars {
+            sql.push(match (expected, self.kind(scalar), scalar) {
+                (Some((_, Kind::Uuid)), _, Scalar::String(value)) => {
+                    if !is_uuid(value) {
+                        return Err(format!("'{}' is not a valid UUID", value));
+                    }
+                    format!("CAST({} AS uuid)", self.bind(SqlValue::Text(value.clone())))
+                }
+                (Some((name, expected)), Some(kind), _) if kind != expected => {
+                    return Err(format!(
+                        "'{}' holds {} and cannot be compared with {}",
+                        name,
+                        expected.describe(),
+                        kind.describe()
+                    ));
+                }
+                _ => self.scalar(scalar)?,
+            });
+        }
+        Ok(sql)
+    }
+
+    fn spatial_operand(&mut self, operand: &SpatialOperand) -> Result<String, String> {
+        match operand {
+            SpatialOperand::Property(name) => self.geometry_property(name),
+            SpatialOperand::Geometry(geometry) => {
+                let placeholder = self.bind(SqlValue::Text(geometry.to_string()));
+                let literal = format!("ST_SetSRID(ST_GeomFromGeoJSON({}), 4326)", placeholder);
+                let storage_srid = self.collection.storage_crs().srid();
+                Ok(if storage_srid == 4326 {
+                    literal
+                } else {
+                    format!("ST_Transform({}, {})", literal, storage_srid)
+                })
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Result<String, String> {
+        Ok(match expr {
+            Expr::And(exprs) | Expr::Or(exprs) => {
+                let separator = if matches!(expr, Expr::And(_)) {
+                    " AND "
+                } else {
+                    " OR "
+                };
+                let parts = exprs
+                    .iter()
+                    .map(|e| self.expr(e))
+                    .collect::<Result<Vec<_>, _>>()?;
+                format!("({})", parts.join(separator))
+            }
+            Expr::Not(inner) => format!("NOT ({})", self.expr(inner)?),
+            Expr::Bool(b) => if *b { "TRUE" } else { "FALSE" }.to_string(),
+            Expr::Comparison { op, left, right } => {
+                let sql = self.compared_scalars(&[left, right])?;
+                format!("{} {} {}", sql[0], op.as_sql(), sql[1])
+            }
+            Expr::Like { value, pattern } => {
+                if let Some(kind) = self.kind(pattern).filter(|kind| *kind != Kind::Text) {
+                    return Err(format!(
+                        "LIKE patterns must be strings, not {}",
+                        kind.describe()
+                    ));
+                }
+                format!(
+                    "CAST({} AS text) LIKE {}",
+                    self.scalar(value)?,
+                    self.scalar(pattern)?
+                )
+            }
+            Expr::Between { value, low, high } => {
+                let sql = self.compared_scalars(&[value, low, high])?;
+                format!("{} BETWEEN {} AND {}", sql[0], sql[1], sql[2])
+            }
+            Expr::In { value, list } => {
+                let scalars: Vec<&Scalar> = std::iter::once(value).chain(list).collect();
+                let sql = self.compared_scalars(&scalars)?;
+                format!("{} IN ({})", sql[0], sql[1..].join(", "))
+            }
+            Expr::IsNull(value) => format!("{} IS NULL", self.scalar(value)?),
+            Expr::Spatial { op, left, right } => format!(
+                "{}({}, {})",
+                op.as_postgis_function(),
+                self.spatial_operand(left)?,
+                self.spatial_operand(right)?
+            ),
+        })
+    }
+}
+
+/// Builds the SQL condition for a filter, appending its literals to `binds`. `column_types`
+/// holds the data types of the collection's columns, against which literals are checked.
+pub(super) fn build_filter_sql(
+    expr: &Expr,
+    collection: &CollectionConfig,
+    column_types: &HashMap<String, String>,
+    binds: &mut Vec<SqlValue>,
+) -> Result<String, String> {
+    FilterSqlBuilder {
+        collection,
+        column_types,
+        binds,
+    }
+    .expr(expr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cql2::parse_text;
+    use crate::models::Crs;
+
+    fn get_test_collection() -> CollectionConfig {
+        toml::from_str(
+            r#"
+            table = "naturalearth_lowres"
+            id_column = "ogc_fid"
+            geometry_column = "wkb_geometry"
+            properties = ["name", "pop_est"]
+            "#,
+        )
+        .unwrap()
+    }
+
+    fn get_test_column_types() -> HashMap<String, String> {
+        HashMap::from([
+            ("name".to_string(), "text".to_string()),
+            ("pop_est".to_string(), "bigint".to_string()),
+            ("founded".to_string(), "date".to_string()),
+            ("uid".to_string(), "uuid".to_string()),
+        ])
+    }
+
+    fn try_translate(filter: &str, collection: &CollectionConfig) -> Result<String, String> {
+        let mut binds = vec![SqlValue::Float(0.0)];
+        build_filter_sql(
+            &parse_text(filter).unwrap(),
+            collection,
+            &get_test_column_types(),
+            &mut binds,
+        )
+    }
+
+    fn translate(filter: &str, collection: &CollectionConfig) -> (String, Vec<SqlValue>) {
+        let mut binds = vec![SqlValue::Float(0.0)];
+        let sql = build_filter_sql(
+            &parse_text(filter).unwrap(),
+            collection,
+            &get_test_column_types(),
+            &mut binds,
+        )
+        .unwrap();
+        (sql, binds)
+    }
+
+    #[test]
+    fn test_build_filter_sql_binds_literals() {
+        let (sql, binds) = translate(
+            "pop_est > 1000000 AND (name LIKE 'A%' OR name IN ('Chad', 'Peru'))",
+            &get_test_collection(),
+        );
+        assert_eq!(
+            sql,
+            "(pop_est > $2 AND (CAST(name AS text) LIKE $3 OR name IN ($4, $5)))"
+        );
+        assert_eq!(
+            binds[1..],
+            [
+                SqlValue::Float(1000000.0),
+                SqlValue::Text("A%".to_string()),
+                SqlValue::Text("Chad".to_string()),
+                SqlValue::Text("Peru".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_build_filter_sql_negations_and_nulls() {
+        let (sql, _) = translate(
+            "pop_est NOT BETWEEN 1 AND 2 AND name IS NOT NULL",
+            &get_test_collection(),
+        );
+        assert_eq!(
+            sql,
+            "(NOT (pop_est BETWEEN $2 AND $3) AND NOT (name IS NULL))"
+        );
+    }
+
+    #[test]
+    fn test_build_filter_sql_spatial() {
+        let mut collection = get_test_collection();
+        let (sql, binds) = translate("S_INTERSECTS(geometry, POINT(7 46))", &collection);
+        assert_eq!(
+            sql,
+            "ST_Intersects(wkb_geometry, ST_SetSRID(ST_GeomFromGeoJSON($2), 4326))"
+        );
+        assert_eq!(
+            binds[1],
+            SqlValue::Text(r#"{"type":"Point","coordinates":[7.0,46.0]}"#.to_string())
+        );
+
+        collection.storage_crs = Crs::from_uri("EPSG:2056");
+        let (sql, _) = translate("S_WITHIN(wkb_geometry, BBOX(6, 45, 8, 47))", &collection);
+        assert_eq!(
+            sql,
+            "ST_Within(wkb_geometry, ST_Transform(ST_SetSRID(ST_GeomFromGeoJSON($2), 4326), 2056))"
+        );
+    }
+
+    #[test]
+    fn test_build_filter_sql_temporal_literals() {
+        let mut collection = get_test_collection();
+        collection.properties.push("founded".to_string());
+        let (sql, _) = translate("founded = DATE('2024-01-01')", &collection);
+        assert_eq!(sql, "founded = CAST($2 AS date)");
+    }
+
+    #[test]
+    fn test_build_filter_sql_checks_literal_types() {
+        let mut collection = get_test_collection();
+        collection.properties.push("uid".to_string());
+        assert_eq!(
+            try_translate("name = 5", &collection).unwrap_err(),
+            "'name' holds a string and cannot be compared with a number"
+        );
+        assert_eq!(
+            try_translate("pop_est = 'abc'", &collection).unwrap_err(),
+            "'pop_est' holds a number and cannot be compared with a string"
+        );
+        assert_eq!(
+            try_translate("pop_est IN (1, 'two')", &collection).unwrap_err(),
+            "'pop_est' holds a number and cannot be compared with a string"
+        );
+        assert_eq!(
+            try_translate("name LIKE 5", &collection).unwrap_err(),
+            "LIKE patterns must be strings, not a number"
+        );
+        assert_eq!(
+            try_translate("uid = 'abc'", &collection).unwrap_err(),
+            "'abc' is not a valid UUID"
+        );
+
+        let (sql, _) = translate("uid = '67e55044-10b1-426f-9247-bb680e5fe0c8'", &collection);
+        assert_eq!(sql, "uid = CAST($2 AS uuid)");
+    }
+
+    #[test]
+    fn test_build_filter_sql_rejects_unknown_properties() {
+        let collection = get_test_collection();
+        let mut binds = Vec::new();
+        let err = build_filter_sql(
+            &parse_text("secret = 1").unwrap(),
+            &collection,
+            &HashMap::new(),
+            &mut binds,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            "unknown property 'secret', filterable properties are: name, pop_est"
+        );
+
+        let err = build_filter_sql(
+            &parse_text("S_INTERSECTS(name, POINT(0 0))").unwrap(),
+            &collection,
+            &HashMap::new(),
+            &mut binds,
+        )
+        .unwrap_err();
+        assert_eq!(err, "'name' is not a geometry property, use 'geometry'");
+    }
+}
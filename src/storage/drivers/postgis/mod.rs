@@ -0,0 +1,2259 @@
+use crate::config::{AppConfig, CollectionConfig, IdType, TemporalConfig};
+use crate::error::AppError;
+use crate::models::{
+    Crs, Cursor, Datetime, Extent, FeaturePatch, GetItemParams, GetItemsParams, QueryableProperty,
+    SortKey, SpatialExtent, TemporalExtent, TilingScheme,
+};
+use crate::storage::{FeatureStream, FeatureWithEtag, Storage, StreamedFeature};
+use async_trait::async_trait;
+pub use discovery::discover_collections;
+use futures_util::{
+    StreamExt,
+    stream::{self, BoxStream},
+};
+use geojson::{Feature, feature::Id};
+use serde_json::Value;
+use sqlx::{
+    Arguments, PgPool, Postgres, Row, Transaction,
+    postgres::{PgArguments, PgRow},
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+use tokio::sync::mpsc;
+
+mod discovery;
+mod filter;
+mod tiles;
+mod write;
+
+/// A value bound to a numbered placeholder of a generated query.
+#[derive(Debug, Clone, PartialEq)]
+enum SqlValue {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Text(String),
+}
+
+impl SqlValue {
+    fn add_to(&self, arguments: &mut PgArguments) {
+        let added = match self {
+            SqlValue::Int(v) => arguments.add(*v),
+            SqlValue::Float(v) => arguments.add(*v),
+            SqlValue::Bool(v) => arguments.add(*v),
+            SqlValue::Text(v) => arguments.add(v.clone()),
+        };
+        added.expect("primitive values always encode");
+    }
+}
+
+struct FeatureQueryParts<'a> {
+    where_sql: String,
+    order_sql: String,
+    placeholder_count: usize,
+    binds: Vec<SqlValue>,
+    sort_keys: Vec<SortKey>,
+    cursor: Option<Cursor>,
+    /// The properties to return.
+    properties: Vec<&'a str>,
+    /// The CRS to return the geometries in, `None` when they are skipped.
+    geometry_crs: Option<Crs>,
+    params: &'a GetItemsParams,
+    collection: &'a CollectionConfig,
+}
+
+impl<'a> FeatureQueryParts<'a> {
+    /// `column_types` holds the data types of the filtered properties, used to bind their values
+    /// and to check the literals of the filter.
+    fn new(
+        collection: &'a CollectionConfig,
+        params: &'a GetItemsParams,
+        column_types: &HashMap<String, String>,
+    ) -> Result<Self, AppError> {
+        let mut where_clauses = Vec::new();
+        let mut binds = Vec::new();
+
+        if let Some(bbox) = &params.bbox
+            && bbox.len() == 4
+        {
+            let bbox_srid = params.bbox_crs.as_ref().map_or(4326, Crs::srid);
+            let envelope = get_bbox_envelope(bbox, params.bbox_crs.as_ref());
+            binds.extend(envelope.map(SqlValue::Float));
+            let mut envelope_sql = format!(
+                "ST_MakeEnvelope(${}, ${}, ${}, ${}, {})",
+                binds.len() - 3,
+                binds.len() - 2,
+                binds.len() - 1,
+                binds.len(),
+                bbox_srid
+            );
+            let storage_srid = collection.storage_crs().srid();
+            if bbox_srid != storage_srid {
+                envelope_sql = format!("ST_Transform({}, {})", envelope_sql, storage_srid);
+            }
+            where_clauses.push(format!(
+                "ST_Intersects({}, {})",
+                collection.geometry_column, envelope_sql
+            ));
+        }
+
+        if let Some(expr) = params
+            .filter_expr()
+            .map_err(|e| AppError::BadRequest(format!("Invalid filter: {}", e)))?
+        {
+            let filter_sql = filter::build_filter_sql(&expr, collection, column_types, &mut binds)
+                .map_err(|e| AppError::BadRequest(format!("Invalid filter: {}", e)))?;
+            where_clauses.push(filter_sql);
+        }
+
+        for (property, value) in &params.property_filters {
+            if !collection.properties.contains(property) {
+                return Err(AppError::BadRequest(format!(
+                    "Unknown query parameter '{}', features can be filtered by: {}",
+                    property,
+                    collection.properties.join(", ")
+                )));
+            }
+            let data_type = column_types.get(property).map(String::as_str);
+            where_clauses.push(
+                get_property_filter_sql(property, data_type, value, &mut binds)
+                    .map_err(AppError::BadRequest)?,
+            );
+        }
+
+        if let Some(datetime) = params
+            .datetime()
+            .map_err(|e| AppError::BadRequest(format!("Invalid datetime: {}", e)))?
+        {
+            let temporal = collection.temporal.as_ref().ok_or_else(|| {
+                AppError::BadRequest(
+                    "Invalid datetime: the collection has no temporal properties".to_string(),
+                )
+            })?;
+            where_clauses.push(get_datetime_sql(temporal, &datetime, &mut binds));
+        }
+
+        let sort_keys = get_sort_keys(collection, params)?;
+        let cursor = params
+            .decode_cursor()
+            .map_err(|e| AppError::BadRequest(format!("Invalid cursor: {}", e)))?;
+        if let Some(cursor) = &cursor {
+            where_clauses.push(get_keyset_sql(
+                collection,
+                &sort_keys,
+                cursor,
+                column_types,
+                &mut binds,
+            )?);
+        }
+
+        let where_sql = if where_clauses.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", where_clauses.join(" AND "))
+        };
+        let order_sql = get_order_sql(&sort_keys);
+        let properties = get_selected_properties(collection, params.properties())?;
+        let geometry_crs = if params.skip_geometry.unwrap_or(false) {
+            None
+        } else {
+            Some(params.crs.clone().unwrap_or_else(Crs::crs84))
+        };
+
+        Ok(Self {
+            where_sql,
+            order_sql,
+            placeholder_count: binds.len(),
+            binds,
+            sort_keys,
+            cursor,
+            properties,
+            geometry_crs,
+            params,
+            collection,
+        })
+    }
+
+    /// The position of the first feature of the page within all matched features.
+    fn offset(&self) -> u64 {
+        self.cursor
+            .as_ref()
+            .map_or(self.params.offset.unwrap_or(0), |cursor| cursor.offset)
+    }
+
+    fn arguments(&self) -> PgArguments {
+        let mut arguments = PgArguments::default();
+        for value in &self.binds {
+            value.add_to(&mut arguments);
+        }
+        arguments
+    }
+}
+
+/// Validates `sortby` against the sortables and appends the id as a tie-breaker, so that the
+/// order, and therefore paging, is stable.
+fn get_sort_keys(
+    collection: &CollectionConfig,
+    params: &GetItemsParams,
+) -> Result<Vec<SortKey>, AppError> {
+    let mut sort_keys = params.sort_keys().map_err(AppError::BadRequest)?;
+
+    if let Some(key) = sort_keys
+        .iter()
+        .find(|key| !collection.sortables().contains(&key.property))
+    {
+        return Err(AppError::BadRequest(format!(
+            "Cannot sort by '{}', sortable properties are: {}",
+            key.property,
+            collection.sortables().join(", ")
+        )));
+    }
+
+    sort_keys.push(SortKey {
+        property: collection.id_column.clone(),
+        descending: false,
+    });
+    Ok(sort_keys)
+}
+
+fn get_order_sql(sort_keys: &[SortKey]) -> String {
+    sort_keys
+        .iter()
+        .map(|key| {
+            if key.descending {
+                format!("{} DESC", key.property)
+            } else {
+                key.property.clone()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Builds the condition selecting the rows sorted after the cursor position.
+///
+/// The cursor values are bound as a single JSON object and cast back to the column types with
+/// `json_populate_record`, so the comparisons stay index friendly. PostgreSQL sorts NULLs last
+/// in ascending and first in descending order, which the generated conditions mirror. The values
+/// are checked against the column types first, as the cursor comes from the client.
+fn get_keyset_sql(
+    collection: &CollectionConfig,
+    sort_keys: &[SortKey],
+    cursor: &Cursor,
+    column_types: &HashMap<String, String>,
+    binds: &mut Vec<SqlValue>,
+) -> Result<String, AppError> {
+    binds.push(SqlValue::Text(
+        Value::Object(cursor.after.clone()).to_string(),
+    ));
+    let record = format!(
+        "json_populate_record(NULL::{}, ${}::json)",
+        collection.table,
+        binds.len()
+    );
+
+    let mut alternatives = Vec::new();
+    let mut equalities: Vec<String> = Vec::new();
+    for key in sort_keys {
+        let column = &key.property;
+        let value = cursor.after.get(column).ok_or_else(|| {
+            AppError::BadRequest("Invalid cursor: cursor does not match sortby".to_string())
+        })?;
+        if let Some(data_type) = column_types.get(column)
+            && !fits_column(value, data_type)
+        {
+            return Err(AppError::BadRequest(format!(
+                "Invalid cursor: the value of '{}' is not a {}",
+                column, data_type
+            )));
+        }
+        let cursor_value = format!("({}).{}", record, column);
+
+        let after = match (value.is_null(), key.descending) {
+            (true, false) => None,
+            (true, true) => Some(format!("{} IS NOT NULL", column)),
+            (false, false) => Some(format!(
+                "({} > {} OR {} IS NULL)",
+                column, cursor_value, column
+            )),
+            (false, true) => Some(format!("{} < {}", column, cursor_value)),
+        };
+        if let Some(after) = after {
+            let mut conditions = equalities.clone();
+            conditions.push(after);
+            alternatives.push(conditions.join(" AND "));
+        }
+
+        equalities.push(if value.is_null() {
+            format!("{} IS NULL", column)
+        } else {
+            format!("{} = {}", column, cursor_value)
+        });
+    }
+
+    Ok(if alternatives.is_empty() {
+        "FALSE".to_string()
+    } else {
+        format!("({})", alternatives.join(" OR "))
+    })
+}
+
+/// Builds the equality condition of a property query parameter, binding the value with the type
+/// of the property's column.
+fn get_property_filter_sql(
+    property: &str,
+    data_type: Option<&str>,
+    value: &str,
+    binds: &mut Vec<SqlValue>,
+) -> Result<String, String> {
+    let invalid = |expected: &str| {
+        format!(
+            "Invalid value '{}' for property '{}', expected {}",
+            value, property, expected
+        )
+    };
+    let bound = match data_type {
+        Some("smallint" | "integer" | "bigint") => {
+            SqlValue::Int(value.parse().map_err(|_| invalid("an integer"))?)
+        }
+        Some("numeric" | "real" | "double precision") => {
+            SqlValue::Float(value.parse().map_err(|_| invalid("a number"))?)
+        }
+        Some("boolean") => SqlValue::Bool(value.parse().map_err(|_| invalid("true or false"))?),
+        Some("date") if !is_date(value) => return Err(invalid("a date such as 2024-01-31")),
+        Some("timestamp without time zone" | "timestamp with time zone")
+            if !is_timestamp(value) =>
+        {
+            return Err(invalid("a timestamp such as 2024-01-31T12:00:00Z"));
+        }
+        Some("uuid") if !filter::is_uuid(value) => return Err(invalid("a UUID")),
+        _ => SqlValue::Text(value.to_string()),
+    };
+    binds.push(bound);
+    let placeholder = format!("${}", binds.len());
+
+    Ok(match data_type {
+        Some(
+            "smallint" | "integer" | "bigint" | "numeric" | "real" | "double precision" | "boolean"
+            | "text" | "character varying" | "character",
+        ) => format!("{} = {}", property, placeholder),
+        // Types without a usable name, and columns that could not be looked up, compare as text.
+        None | Some("USER-DEFINED" | "ARRAY") => {
+            format!("CAST({} AS text) = {}", property, placeholder)
+        }
+        Some(data_type) => format!("{} = CAST({} AS {})", property, placeholder, data_type),
+    })
+}
+
+/// Whether `value` is a valid date in the `YYYY-MM-DD` form.
+fn is_date(value: &str) -> bool {
+    let bytes = value.as_bytes();
+    if bytes.len() != 10
+        || bytes[4] != b'-'
+        || bytes[7] != b'-'
+        || !bytes
+            .iter()
+            .enumerate()
+            .all(|(i, b)| i == 4 || i == 7 || b.is_ascii_digit())
+    {
+        return false;
+    }
+    let number = |range: std::ops::Range<usize>| value[range].parse::<u32>().unwrap_or(0);
+    let (year, month, day) = (number(0..4), number(5..7), number(8..10));
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days = match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if leap => 29,
+        2 => 28,
+        _ => return false,
+    };
+    (1..=days).contains(&day)
+}
+
+/// Whether `value` is a valid RFC 3339 timestamp, a date alone or a timestamp without offset.
+fn is_timestamp(value: &str) -> bool {
+    if value.len() < 10 || !value.is_char_boundary(10) || !is_date(&value[..10]) {
+        return false;
+    }
+    let Some(time) = value[10..].strip_prefix(['T', 't', ' ']) else {
+        return value.len() == 10;
+    };
+    let (time, offset) = match time.find(['Z', 'z', '+', '-']) {
+        Some(i) => time.split_at(i),
+        None => (time, ""),
+    };
+    let two_digits = |part: &str, max: u32| {
+        part.len() == 2
+            && part.bytes().all(|b| b.is_ascii_digit())
+            && part.parse::<u32>().is_ok_and(|n| n <= max)
+    };
+    let (time, fraction) = time.split_once('.').unwrap_or((time, "0"));
+    let valid_time = matches!(
+        time.split(':').collect::<Vec<_>>()[..],
+        [hours, minutes, seconds]
+            if two_digits(hours, 23) && two_digits(minutes, 59) && two_digits(seconds, 60)
+    ) && !fraction.is_empty()
+        && fraction.bytes().all(|b| b.is_ascii_digit());
+    let valid_offset = match offset {
+        "" | "Z" | "z" => true,
+        _ => {
+            matches!(
+                offset[1..].split(':').collect::<Vec<_>>()[..],
+                [hours, minutes] if two_digits(hours, 23) && two_digits(minutes, 59)
+            ) || matches!(
+                // Offsets of local mean times, from before time zones, have seconds.
+                offset[1..].split(':').collect::<Vec<_>>()[..],
+                [hours, minutes, seconds]
+                    if two_digits(hours, 23) && two_digits(minutes, 59) && two_digits(seconds, 59)
+            )
+        }
+    };
+    valid_time && valid_offset
+}
+
+/// Whether a JSON value, as `json_build_object` writes the values of a column, can be cast back
+/// to the column's type. Types that are not checked are left to the database.
+fn fits_column(value: &Value, data_type: &str) -> bool {
+    let infinite = |s: &str| matches!(s, "infinity" | "-infinity");
+    match (data_type, value) {
+        (_, Value::Null) => true,
+        ("smallint" | "integer" | "bigint", value) => value.is_i64(),
+        ("numeric" | "real" | "double precision", Value::Number(_)) => true,
+        ("numeric" | "real" | "double precision", Value::String(s)) => {
+            matches!(s.as_str(), "NaN" | "Infinity" | "-Infinity")
+        }
+        ("boolean", value) => value.is_boolean(),
+        ("text" | "character varying" | "character", value) => value.is_string(),
+        ("date", Value::String(s)) => is_date(s) || infinite(s),
+        ("timestamp without time zone" | "timestamp with time zone", Value::String(s)) => {
+            is_timestamp(s) || infinite(s)
+        }
+        ("uuid", Value::String(s)) => filter::is_uuid(s),
+        (
+            "numeric"
+            | "real"
+            | "double precision"
+            | "date"
+            | "timestamp without time zone"
+            | "timestamp with time zone"
+            | "uuid",
+            _,
+        ) => false,
+        _ => true,
+    }
+}
+
+/// Builds the condition selecting the features whose temporal information intersects `datetime`.
+/// Interval columns that are NULL are unbounded on that side.
+fn get_datetime_sql(
+    temporal: &TemporalConfig,
+    datetime: &Datetime,
+    binds: &mut Vec<SqlValue>,
+) -> String {
+    let mut bind = |value: &str| {
+        binds.push(SqlValue::Text(value.to_string()));
+        format!("CAST(${} AS timestamptz)", binds.len())
+    };
+    let (start, end) = match datetime {
+        Datetime::Instant(instant) => {
+            let instant = bind(instant);
+            (Some(instant.clone()), Some(instant))
+        }
+        Datetime::Interval { start, end } => (
+            start.as_deref().map(&mut bind),
+            end.as_deref().map(&mut bind),
+        ),
+    };
+
+    let mut conditions = Vec::new();
+    match temporal {
+        TemporalConfig::Instant { instant } => {
+            if let Some(start) = start {
+                conditions.push(format!("{} >= {}", instant, start));
+            }
+            if let Some(end) = end {
+                conditions.push(format!("{} <= {}", instant, end));
+            }
+        }
+        TemporalConfig::Interval {
+            start: start_column,
+            end: end_column,
+        } => {
+            if let Some(end) = end {
+                conditions.push(format!(
+                    "({} IS NULL OR {} <= {})",
+                    start_column, start_column, end
+                ));
+            }
+            if let Some(start) = start {
+                conditions.push(format!(
+                    "({} IS NULL OR {} >= {})",
+                    end_column, end_column, start
+                ));
+            }
+        }
+    }
+    conditions.join(" AND ")
+}
+
+/// Builds the query of a collection's temporal extent as RFC 3339 strings, along with the
+/// number of features. An end is open when a feature is unbounded on that side.
+fn build_temporal_extent_sql(collection: &CollectionConfig, temporal: &TemporalConfig) -> String {
+    let (start_column, end_column) = match temporal {
+        TemporalConfig::Instant { instant } => (instant, instant),
+        TemporalConfig::Interval { start, end } => (start, end),
+    };
+    format!(
+        "SELECT count(*), CASE WHEN count(*) > count({start}) THEN NULL ELSE to_json(min({start})) #>> '{{}}' END, CASE WHEN count(*) > count({end}) THEN NULL ELSE to_json(max({end})) #>> '{{}}' END from {table}",
+        start = start_column,
+        end = end_column,
+        table = collection.table
+    )
+}
+
+/// Builds the query of the corners, in CRS84, of the `box2d` computed by `extent` in the storage
+/// CRS.
+fn build_bbox_sql(collection: &CollectionConfig, extent: &str) -> String {
+    let storage_srid = collection.storage_crs().srid();
+    let mut envelope = format!("ST_SetSRID({}::geometry, {})", extent, storage_srid);
+    if storage_srid != 4326 {
+        envelope = format!("ST_Transform({}, 4326)", envelope);
+    }
+    format!(
+        "SELECT ST_XMin(envelope), ST_YMin(envelope), ST_XMax(envelope), ST_YMax(envelope) FROM (SELECT {} AS envelope",
+        envelope
+    )
+}
+
+/// Builds the query of a collection's bounding box estimated from the table statistics, which
+/// is NULL when the table has not been analyzed. The schema, table and geometry column are bound
+/// to `$1`, `$2` and `$3`.
+fn build_estimated_extent_sql(collection: &CollectionConfig) -> String {
+    format!(
+        "{}) AS extent",
+        build_bbox_sql(
+            collection,
+            "ST_EstimatedExtent(COALESCE($1, current_schema())::text, $2, $3)"
+        )
+    )
+}
+
+/// Builds the query of a collection's exact bounding box, scanning all its geometries.
+fn build_spatial_extent_sql(collection: &CollectionConfig) -> String {
+    format!(
+        "{} FROM {}) AS extent",
+        build_bbox_sql(
+            collection,
+            &format!("ST_Extent({})", collection.geometry_column)
+        ),
+        collection.table
+    )
+}
+
+/// Maps errors of writes caused by the written values, such as constraint violations or values
+/// of the wrong type, to client errors.
+fn map_write_error(e: sqlx::Error) -> AppError {
+    if let sqlx::Error::Database(db_error) = &e
+        && let Some(code) = db_error.code()
+    {
+        if code == "23505" {
+            return AppError::Conflict(format!("Conflicting feature: {}", db_error.message()));
+        }
+        if code.starts_with("22") || code.starts_with("23") {
+            return AppError::BadRequest(format!("Invalid feature: {}", db_error.message()));
+        }
+    }
+    e.into()
+}
+
+fn precondition_failed(id: &str) -> AppError {
+    AppError::PreconditionFailed(format!(
+        "Feature {} has been modified since it was read",
+        id
+    ))
+}
+
+fn feature_not_found(collection_id: &str, id: &str) -> AppError {
+    AppError::NotFound(format!(
+        "Feature {} not found in collection {}",
+        id, collection_id
+    ))
+}
+
+/// Maps database errors caused by a user supplied filter or cursor (e.g. comparing a text column
+/// with a number, or a malformed date) to 400 instead of 500.
+fn map_query_error(e: sqlx::Error, params: &GetItemsParams) -> AppError {
+    let filtered =
+        params.filter.is_some() || params.datetime.is_some() || !params.property_filters.is_empty();
+    if (filtered || params.cursor.is_some())
+        && let sqlx::Error::Database(db_error) = &e
+        && db_error
+            .code()
+            .is_some_and(|code| code.starts_with("22") || code == "42883" || code == "42804")
+    {
+        let input = if filtered { "filter" } else { "cursor" };
+        return AppError::BadRequest(format!("Invalid {}: {}", input, db_error.message()));
+    }
+    e.into()
+}
+
+/// Validates the requested subset of properties, defaulting to all configured properties.
+fn get_selected_properties(
+    collection: &CollectionConfig,
+    requested: Option<Vec<String>>,
+) -> Result<Vec<&str>, AppError> {
+    let Some(requested) = requested else {
+        return Ok(collection.properties.iter().map(String::as_str).collect());
+    };
+    requested
+        .iter()
+        .map(|property| {
+            collection
+                .properties
+                .iter()
+                .find(|p| *p == property)
+                .map(String::as_str)
+                .ok_or_else(|| {
+                    AppError::BadRequest(format!(
+                        "Unknown property '{}', available properties are: {}",
+                        property,
+                        collection.properties.join(", ")
+                    ))
+                })
+        })
+        .collect()
+}
+
+fn get_properties_columns_sql(properties: &[&str]) -> String {
+    properties
+        .iter()
+        .map(|p| format!("'{}', {}", p, p))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Builds the GeoJSON geometry expression, reprojecting from the storage CRS when needed.
+fn get_geometry_sql(collection: &CollectionConfig, crs: &Crs) -> String {
+    let mut geometry = collection.geometry_column.clone();
+    if crs.srid() != collection.storage_crs().srid() {
+        geometry = format!("ST_Transform({}, {})", geometry, crs.srid());
+    }
+    if crs.is_lat_lon() {
+        geometry = format!("ST_FlipCoordinates({})", geometry);
+    }
+    format!("ST_AsGeoJSON({})::jsonb", geometry)
+}
+
+/// Selects the geometry in `crs`, or NULL without computing any GeoJSON when it is skipped.
+fn get_geometry_column_sql(collection: &CollectionConfig, crs: Option<&Crs>) -> String {
+    crs.map_or_else(
+        || "NULL::jsonb".to_string(),
+        |crs| get_geometry_sql(collection, crs),
+    )
+}
+
+/// Returns the bbox as `minx, miny, maxx, maxy` in PostGIS axis order.
+fn get_bbox_envelope(bbox: &[f64], bbox_crs: Option<&Crs>) -> [f64; 4] {
+    if bbox_crs.is_some_and(Crs::is_lat_lon) {
+        [bbox[1], bbox[0], bbox[3], bbox[2]]
+    } else {
+        [bbox[0], bbox[1], bbox[2], bbox[3]]
+    }
+}
+
+/// Selects the ID as `bigint` or `text`, the two types feature IDs are decoded from.
+fn get_id_sql(collection: &CollectionConfig) -> String {
+    match collection.id_type() {
+        IdType::Integer => format!("{}::bigint", collection.id_column),
+        IdType::Text | IdType::Uuid => format!("{}::text", collection.id_column),
+    }
+}
+
+/// Parses a feature ID from a request path into a value of the ID column's type.
+fn parse_feature_id(collection: &CollectionConfig, id: &str) -> Result<SqlValue, String> {
+    match collection.id_type() {
+        IdType::Integer => id
+            .parse()
+            .map(SqlValue::Int)
+            .map_err(|_| format!("Invalid feature ID '{}', expected an integer", id)),
+        IdType::Text | IdType::Uuid => Ok(SqlValue::Text(id.to_string())),
+    }
+}
+
+/// Builds the entity tag of the row of `relation`: the version column as text when one is
+/// configured, a hash of the whole row otherwise.
+fn get_etag_sql(collection: &CollectionConfig, relation: &str) -> String {
+    match &collection.version_column {
+        Some(version_column) => format!("CAST({}.{} AS text)", relation, version_column),
+        None => format!("md5(CAST({}.* AS text))", relation),
+    }
+}
+
+/// Refers to a bound feature ID, casting it to the type of the ID column when needed.
+fn get_id_placeholder_sql(collection: &CollectionConfig, placeholder: usize) -> String {
+    match collection.id_type() {
+        IdType::Uuid => format!("CAST(${} AS uuid)", placeholder),
+        IdType::Integer | IdType::Text => format!("${}", placeholder),
+    }
+}
+
+/// `crs` is `None` when the geometry is skipped.
+fn build_single_feature_sql(
+    collection: &CollectionConfig,
+    crs: Option<&Crs>,
+    properties: &[&str],
+) -> String {
+    format!(
+        "SELECT '{}' as type, {} as geometry, json_build_object({}) as properties, {} as id, {} as etag from {} WHERE {} = {}",
+        "Feature",
+        get_geometry_column_sql(collection, crs),
+        get_properties_columns_sql(properties),
+        get_id_sql(collection),
+        get_etag_sql(collection, &collection.table),
+        collection.table,
+        collection.id_column,
+        get_id_placeholder_sql(collection, 1)
+    )
+}
+
+/// Builds the object holding the sort key values of a row, from which the next cursor is made.
+fn get_sort_key_sql(sort_keys: &[SortKey]) -> String {
+    let values = sort_keys
+        .iter()
+        .map(|key| format!("'{}', {}", key.property, key.property))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!("json_build_object({})", values)
+}
+
+/// Joins the FROM clause with the optional WHERE clause.
+fn get_from_sql(collection: &CollectionConfig, query_parts: &FeatureQueryParts<'_>) -> String {
+    if query_parts.where_sql.is_empty() {
+        collection.table.clone()
+    } else {
+        format!("{} {}", collection.table, query_parts.where_sql)
+    }
+}
+
+fn build_feature_list_sql(
+    collection: &CollectionConfig,
+    query_parts: &FeatureQueryParts<'_>,
+) -> String {
+    format!(
+        "SELECT '{}' as type, {} as geometry, json_build_object({}) as properties, {} as id, {} as sort_key from {} order by {} LIMIT ${} OFFSET ${}",
+        "Feature",
+        get_geometry_column_sql(collection, query_parts.geometry_crs.as_ref()),
+        get_properties_columns_sql(&query_parts.properties),
+        get_id_sql(collection),
+        get_sort_key_sql(&query_parts.sort_keys),
+        get_from_sql(collection, query_parts),
+        query_parts.order_sql,
+        query_parts.placeholder_count + 1,
+        query_parts.placeholder_count + 2
+    )
+}
+
+fn build_count_sql(collection: &CollectionConfig, query_parts: &FeatureQueryParts<'_>) -> String {
+    format!(
+        "SELECT count(*) from {}",
+        get_from_sql(collection, query_parts)
+    )
+}
+
+/// Lists the columns of a table; unqualified table names resolve against the current schema.
+const COLUMN_TYPES_SQL: &str = "SELECT column_name::text, data_type::text FROM information_schema.columns WHERE table_schema = COALESCE($1, current_schema()) AND table_name = $2";
+
+const CRS_DEFINITION_SQL: &str = "SELECT srtext::text FROM spatial_ref_sys WHERE srid = $1";
+
+/// Splits a possibly schema-qualified table name into its schema and table parts.
+fn split_table_name(table: &str) -> (Option<&str>, &str) {
+    match table.split_once('.') {
+        Some((schema, table)) => (Some(schema), table),
+        None => (None, table),
+    }
+}
+
+/// Maps a PostgreSQL data type, as reported by `information_schema.columns`, to a JSON Schema
+/// type and format.
+fn get_json_schema_type(data_type: &str) -> (Option<&'static str>, Option<&'static str>) {
+    match data_type {
+        "smallint" | "integer" | "bigint" => (Some("integer"), None),
+        "numeric" | "real" | "double precision" => (Some("number"), None),
+        "boolean" => (Some("boolean"), None),
+        "text" | "character varying" | "character" => (Some("string"), None),
+        "uuid" => (Some("string"), Some("uuid")),
+        "date" => (Some("string"), Some("date")),
+        "timestamp without time zone" | "timestamp with time zone" => {
+            (Some("string"), Some("date-time"))
+        }
+        "time without time zone" | "time with time zone" => (Some("string"), Some("time")),
+        "json" | "jsonb" => (Some("object"), None),
+        "ARRAY" => (Some("array"), None),
+        _ => (None, None),
+    }
+}
+
+async fn fetch_column_types(
+    pool: &PgPool,
+    collection: &CollectionConfig,
+) -> Result<HashMap<String, String>, sqlx::Error> {
+    let (schema, table) = split_table_name(&collection.table);
+    let rows: Vec<(String, String)> = sqlx::query_as(COLUMN_TYPES_SQL)
+        .bind(schema)
+        .bind(table)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(rows.into_iter().collect())
+}
+
+/// Fills in the ID type of the collections that do not configure one, from the type of their
+/// ID column. Collections whose ID column cannot be found keep the integer default.
+pub async fn detect_id_types(pool: &PgPool, config: &mut AppConfig) -> Result<(), sqlx::Error> {
+    for (collection_id, collection) in config.collections.iter_mut() {
+        if collection.id_type.is_some() {
+            continue;
+        }
+        let column_types = fetch_column_types(pool, collection).await?;
+        match column_types.get(&collection.id_column) {
+            Some(data_type) => match IdType::from_data_type(data_type) {
+                Some(id_type) => collection.id_type = Some(id_type),
+                None => {
+                    tracing::warn!(
+                        "ID column {} of collection {} has unsupported type {}, treating IDs as text",
+                        collection.id_column,
+                        collection_id,
+                        data_type
+                    );
+                    collection.id_type = Some(IdType::Text);
+                }
+            },
+            None => tracing::warn!(
+                "ID column {} of collection {} not found, assuming integer IDs",
+                collection.id_column,
+                collection_id
+            ),
+        }
+    }
+    Ok(())
+}
+
+fn row_to_feature(row: &PgRow, collection: &CollectionConfig) -> Result<Feature, AppError> {
+    let geometry: Option<Value> = row.get("geometry");
+    let properties: Value = row.get("properties");
+    let id = match collection.id_type() {
+        IdType::Integer => Id::Number(row.get::<i64, _>("id").into()),
+        IdType::Text | IdType::Uuid => Id::String(row.get("id")),
+    };
+
+    let feature = geojson::Feature {
+        bbox: None,
+        geometry: geometry
+            .map(geojson::Geometry::from_json_value)
+            .transpose()
+            .map_err(|e| AppError::Internal(e.to_string()))?,
+        id: Some(id),
+        properties: Some(
+            serde_json::from_value(properties).map_err(|e| AppError::Internal(e.to_string()))?,
+        ),
+        foreign_members: None,
+    };
+    Ok(feature)
+}
+
+/// The name of the server-side cursor pages of features are read through.
+const FEATURE_CURSOR: &str = "features";
+
+/// The number of rows fetched at a time from the cursor of a page, which bounds the memory a
+/// page takes however large it is.
+const FETCH_SIZE: usize = 100;
+
+/// Reads a page of features from a server-side cursor, fetching the next batch of rows only
+/// once the previous one is consumed, so a slow client holds the query back instead of piling up
+/// rows.
+struct FeatureCursor {
+    transaction: Transaction<'static, Postgres>,
+    rows: std::vec::IntoIter<PgRow>,
+    exhausted: bool,
+    collection: CollectionConfig,
+    params: GetItemsParams,
+    number_matched: u64,
+    /// The position of the next feature within all matched features.
+    position: u64,
+}
+
+impl FeatureCursor {
+    async fn fetch(&mut self) -> Result<(), AppError> {
+        let rows = sqlx::query(&format!("FETCH {} FROM {}", FETCH_SIZE, FEATURE_CURSOR))
+            .fetch_all(&mut *self.transaction)
+            .await
+            .map_err(|e| map_query_error(e, &self.params))?;
+        self.exhausted = rows.len() < FETCH_SIZE;
+        self.rows = rows.into_iter();
+        Ok(())
+    }
+
+    async fn next(mut self) -> Result<Option<(StreamedFeature, Self)>, AppError> {
+        let row = match self.rows.next() {
+            Some(row) => row,
+            None if self.exhausted => return Ok(None),
+            None => {
+                self.fetch().await?;
+                match self.rows.next() {
+                    Some(row) => row,
+                    None => return Ok(None),
+                }
+            }
+        };
+        let feature = row_to_feature(&row, &self.collection)?;
+        self.position += 1;
+        let next_cursor = match row.get("sort_key") {
+            Value::Object(after) if self.number_matched > self.position => Some(Cursor {
+                offset: self.position,
+                sortby: self.params.sortby.clone(),
+                after,
+            }),
+            _ => None,
+        };
+        Ok(Some((
+            StreamedFeature {
+                feature,
+                next_cursor,
+            },
+            self,
+        )))
+    }
+}
+
+/// Reads features in a task of their own, handing them over one at a time as the stream is
+/// consumed. When the stream is dropped, or not read for `idle_timeout` because the client stopped
+/// reading the response, the features are dropped, which releases the connection of a cursor,
+/// and the stream fails.
+fn read_in_background<T: Send + 'static>(
+    features: BoxStream<'static, Result<T, AppError>>,
+    idle_timeout: Duration,
+) -> BoxStream<'static, Result<T, AppError>> {
+    // `None` marks the end of the features, a channel closing without it was cut short.
+    let (sender, receiver) = mpsc::channel(1);
+    tokio::spawn(async move {
+        let mut features = features;
+        loop {
+            let item = features.next().await;
+            let last = !matches!(item, Some(Ok(_)));
+            match tokio::time::timeout(idle_timeout, sender.send(item)).await {
+                Ok(Ok(())) if !last => {}
+                Ok(_) => return,
+                Err(_) => {
+                    tracing::warn!(
+                        "Released the features of a response not read for {:?}",
+                        idle_timeout
+                    );
+                    return;
+                }
+            }
+        }
+    });
+
+    stream::unfold(Some(receiver), |receiver| async move {
+        let mut receiver = receiver?;
+        match receiver.recv().await {
+            Some(Some(Ok(feature))) => Some((Ok(feature), Some(receiver))),
+            Some(Some(Err(error))) => Some((Err(error), None)),
+            Some(None) => None,
+            None => Some((
+                Err(AppError::Internal(
+                    "The response was not read in time".to_string(),
+                )),
+                None,
+            )),
+        }
+    })
+    .boxed()
+}
+
+/// An extent computed from the data, along with when it was computed.
+struct CachedExtent {
+    computed_at: Instant,
+    extent: Option<Extent>,
+}
+
+pub struct Postgis {
+    pool: PgPool,
+    config: Arc<AppConfig>,
+    extents: Mutex<HashMap<String, CachedExtent>>,
+}
+
+impl Postgis {
+    pub fn new(pool: PgPool, config: Arc<AppConfig>) -> Self {
+        Self {
+            pool,
+            config,
+            extents: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn get_collection(&self, collection_id: &str) -> Result<&CollectionConfig, AppError> {
+        self.config
+            .collections
+            .get(collection_id)
+            .ok_or_else(|| AppError::NotFound(format!("Collection {} not found", collection_id)))
+    }
+
+    /// Fetches the data type of every column of the collection's table.
+    async fn fetch_column_types(
+        &self,
+        collection: &CollectionConfig,
+    ) -> Result<HashMap<String, String>, AppError> {
+        fetch_column_types(&self.pool, collection)
+            .await
+            .map_err(AppError::from)
+    }
+
+    async fn fetch_total_count(
+        &self,
+        query_parts: &FeatureQueryParts<'_>,
+    ) -> Result<i64, AppError> {
+        let count_sql = build_count_sql(query_parts.collection, query_parts);
+
+        sqlx::query_scalar_with(&count_sql, query_parts.arguments())
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|e| map_query_error(e, query_parts.params))
+    }
+
+    /// Declares the cursor of a page of features and fetches its first rows, so that errors
+    /// caused by the request are reported before any feature is sent.
+    async fn open_feature_cursor(
+        &self,
+        query_parts: &FeatureQueryParts<'_>,
+        number_matched: u64,
+    ) -> Result<FeatureCursor, AppError> {
+        let features_sql = build_feature_list_sql(query_parts.collection, query_parts);
+        let mut arguments = query_parts.arguments();
+        // With a cursor, the keyset condition already skips the previous pages.
+        let offset = if query_parts.cursor.is_some() {
+            0
+        } else {
+            query_parts.offset()
+        };
+        // Downloads ask for more features than the maximum page size.
+        let (default_limit, _) = self.config.limits(Some(query_parts.collection));
+        arguments
+            .add(query_parts.params.limit.unwrap_or(default_limit) as i64)
+            .and_then(|_| arguments.add(offset as i64))
+            .expect("primitive values always encode");
+
+        let mut transaction = self.pool.begin().await?;
+        sqlx::query_with(
+            &format!(
+                "DECLARE {} NO SCROLL CURSOR FOR {}",
+                FEATURE_CURSOR, features_sql
+            ),
+            arguments,
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| map_query_error(e, query_parts.params))?;
+
+        let mut cursor = FeatureCursor {
+            transaction,
+            rows: Vec::new().into_iter(),
+            exhausted: false,
+            collection: query_parts.collection.clone(),
+            params: query_parts.params.clone(),
+            number_matched,
+            position: query_parts.offset(),
+        };
+        cursor.fetch().await?;
+        Ok(cursor)
+    }
+
+    /// Computes the bounding box of a collection, from the table statistics when available to
+    /// avoid scanning large tables.
+    async fn fetch_spatial_extent(
+        &self,
+        collection: &CollectionConfig,
+    ) -> Result<Option<SpatialExtent>, AppError> {
+        type Bbox = (Option<f64>, Option<f64>, Option<f64>, Option<f64>);
+
+        let (schema, table) = split_table_name(&collection.table);
+        // Estimating fails on views and other relations without statistics.
+        let estimated: Option<Bbox> = sqlx::query_as(&build_estimated_extent_sql(collection))
+            .bind(schema)
+            .bind(table)
+            .bind(&collection.geometry_column)
+            .fetch_one(&self.pool)
+            .await
+            .ok();
+        let bbox = match estimated {
+            Some(bbox @ (Some(_), Some(_), Some(_), Some(_))) => bbox,
+            _ => {
+                sqlx::query_as(&build_spatial_extent_sql(collection))
+                    .fetch_one(&self.pool)
+                    .await?
+            }
+        };
+
+        Ok(match bbox {
+            (Some(min_x), Some(min_y), Some(max_x), Some(max_y)) => {
+                Some(SpatialExtent::new([min_x, min_y, max_x, max_y]))
+            }
+            _ => None,
+        })
+    }
+
+    /// Computes the interval covered by the features of a collection with temporal properties.
+    async fn fetch_temporal_extent(
+        &self,
+        collection: &CollectionConfig,
+    ) -> Result<Option<TemporalExtent>, AppError> {
+        let Some(temporal) = &collection.temporal else {
+            return Ok(None);
+        };
+
+        let (count, start, end): (i64, Option<String>, Option<String>) =
+            sqlx::query_as(&build_temporal_extent_sql(collection, temporal))
+                .fetch_one(&self.pool)
+                .await?;
+
+        Ok((count > 0).then(|| TemporalExtent::new(start, end)))
+    }
+
+    /// Computes the parts of a collection's extent that are not configured.
+    async fn compute_extent(
+        &self,
+        collection: &CollectionConfig,
+    ) -> Result<Option<Extent>, AppError> {
+        let spatial = match collection.extent.spatial() {
+            Some(spatial) => Some(spatial),
+            None => self.fetch_spatial_extent(collection).await?,
+        };
+        let temporal = match collection.extent.temporal() {
+            Some(temporal) => Some(temporal),
+            None => self.fetch_temporal_extent(collection).await?,
+        };
+
+        Ok((spatial.is_some() || temporal.is_some()).then_some(Extent { spatial, temporal }))
+    }
+
+    /// Fetches the current ETag of a feature, `None` when the feature does not exist.
+    async fn fetch_etag(
+        &self,
+        collection: &CollectionConfig,
+        feature_id: &SqlValue,
+    ) -> Result<Option<String>, AppError> {
+        let mut arguments = PgArguments::default();
+        feature_id.add_to(&mut arguments);
+        let row = sqlx::query_with(&build_single_feature_sql(collection, None, &[]), arguments)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.map(|row| row.get("etag")))
+    }
+
+    /// Explains why a write matched no row: the feature either does not exist or was modified
+    /// since the client read it.
+    async fn write_failure(
+        &self,
+        collection: &CollectionConfig,
+        collection_id: &str,
+        id: &str,
+        feature_id: &SqlValue,
+    ) -> AppError {
+        match self.fetch_etag(collection, feature_id).await {
+            Ok(Some(_)) => precondition_failed(id),
+            Ok(None) => feature_not_found(collection_id, id),
+            Err(e) => e,
+        }
+    }
+
+    /// Runs an update of a single feature returning its new ETag.
+    async fn execute_feature_update(
+        &self,
+        sql: &str,
+        arguments: PgArguments,
+        collection: &CollectionConfig,
+        collection_id: &str,
+        id: &str,
+        feature_id: &SqlValue,
+    ) -> Result<String, AppError> {
+        let etag = sqlx::query_scalar_with(sql, arguments)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(map_write_error)?;
+        match etag {
+            Some(etag) => Ok(etag),
+            None => Err(self
+                .write_failure(collection, collection_id, id, feature_id)
+                .await),
+        }
+    }
+}
+
+#[async_trait]
+impl Storage for Postgis {
+    async fn stream_features(
+        &self,
+        collection_id: &str,
+        params: &GetItemsParams,
+    ) -> Result<FeatureStream, AppError> {
+        let collection = self.get_collection(collection_id)?;
+        let column_types = if params.property_filters.is_empty()
+            && params.filter.is_none()
+            && params.cursor.is_none()
+        {
+            HashMap::new()
+        } else {
+            self.fetch_column_types(collection).await?
+        };
+
+        let items_params_for_count = GetItemsParams {
+            bbox: params.bbox.clone(),
+            bbox_crs: params.bbox_crs.clone(),
+            filter: params.filter.clone(),
+            filter_lang: params.filter_lang,
+            property_filters: params.property_filters.clone(),
+            datetime: params.datetime.clone(),
+            ..Default::default()
+        };
+        let query_parts_for_count =
+            FeatureQueryParts::new(collection, &items_params_for_count, &column_types)?;
+        let total_count = self.fetch_total_count(&query_parts_for_count).await? as u64;
+
+        let query_parts = FeatureQueryParts::new(collection, params, &column_types)?;
+        let cursor = self.open_feature_cursor(&query_parts, total_count).await?;
+
+        Ok(FeatureStream {
+            number_matched: total_count,
+            offset: query_parts.offset(),
+            features: read_in_background(
+                stream::try_unfold(cursor, FeatureCursor::next).boxed(),
+                self.config.stream_idle_timeout(),
+            ),
+        })
+    }
+
+    async fn get_feature(
+        &self,
+        collection_id: &str,
+        id: &str,
+        params: &GetItemParams,
+    ) -> Result<FeatureWithEtag, AppError> {
+        let collection = self.get_collection(collection_id)?;
+
+        let feature_id = parse_feature_id(collection, id).map_err(AppError::BadRequest)?;
+
+        let properties = get_selected_properties(collection, params.properties())?;
+        let crs = if params.skip_geometry.unwrap_or(false) {
+            None
+        } else {
+            Some(params.crs.clone().unwrap_or_else(Crs::crs84))
+        };
+        let feature_sql = build_single_feature_sql(collection, crs.as_ref(), &properties);
+
+        let mut arguments = PgArguments::default();
+        feature_id.add_to(&mut arguments);
+
+        let row = sqlx::query_with(&feature_sql, arguments)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| match &e {
+                // 22P02 is raised for IDs that are not valid UUIDs.
+                sqlx::Error::Database(db_error) if db_error.code().as_deref() == Some("22P02") => {
+                    AppError::BadRequest(format!("Invalid feature ID '{}', expected a UUID", id))
+                }
+                _ => e.into(),
+            })?
+            .ok_or_else(|| feature_not_found(collection_id, id))?;
+
+        Ok(FeatureWithEtag {
+            feature: row_to_feature(&row, collection)?,
+            etag: row.get("etag"),
+        })
+    }
+
+    async fn get_extent(&self, collection_id: &str) -> Result<Option<Extent>, AppError> {
+        let collection = self.get_collection(collection_id)?;
+        {
+            let extents = self.extents.lock().unwrap_or_else(PoisonError::into_inner);
+            if let Some(cached) = extents.get(collection_id)
+                && cached.computed_at.elapsed() < self.config.extent_refresh_interval()
+            {
+                return Ok(cached.extent.clone());
+            }
+        }
+
+        let extent = self.compute_extent(collection).await?;
+        self.extents
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(
+                collection_id.to_string(),
+                CachedExtent {
+                    computed_at: Instant::now(),
+                    extent: extent.clone(),
+                },
+            );
+        Ok(extent)
+    }
+
+    async fn get_queryables(
+        &self,
+        collection_id: &str,
+    ) -> Result<Vec<QueryableProperty>, AppError> {
+        let collection = self.get_collection(collection_id)?;
+        let column_types = self.fetch_column_types(collection).await?;
+
+        let mut geometry = QueryableProperty::new("geometry", None, Some("geometry-any"));
+        geometry.role = Some("primary-geometry".to_string());
+
+        let properties = collection.properties.iter().map(|name| {
+            let (type_, format) = column_types
+                .get(name)
+                .map_or((None, None), |data_type| get_json_schema_type(data_type));
+            QueryableProperty::new(name, type_, format)
+        });
+
+        Ok(std::iter::once(geometry).chain(properties).collect())
+    }
+
+    async fn get_crs_definition(&self, crs: &Crs) -> Result<Option<String>, AppError> {
+        sqlx::query_scalar(CRS_DEFINITION_SQL)
+            .bind(crs.srid())
+            .fetch_optional(&self.pool)
+            .await
+            .map(Option::flatten)
+            .map_err(AppError::from)
+    }
+
+    async fn get_tile(
+        &self,
+        collection_id: &str,
+        scheme: TilingScheme,
+        z: u8,
+        x: u32,
+        y: u32,
+    ) -> Result<Vec<u8>, AppError> {
+        let collection = self.get_collection(collection_id)?;
+        let properties =
+            get_selected_properties(collection, Some(collection.tile_properties().to_vec()))?;
+        let [min_x, min_y, max_x, max_y] = scheme.tile_bounds(z, x, y);
+
+        let tile: Option<Vec<u8>> =
+            sqlx::query_scalar(&tiles::build_tile_sql(collection, scheme, &properties))
+                .bind(min_x)
+                .bind(min_y)
+                .bind(max_x)
+                .bind(max_y)
+                .bind(collection_id)
+                .fetch_one(&self.pool)
+                .await?;
+        Ok(tile.unwrap_or_default())
+    }
+
+    async fn create_feature(
+        &self,
+        collection_id: &str,
+        feature: &geojson::Feature,
+        crs: &Crs,
+    ) -> Result<(String, String), AppError> {
+        let collection = self.get_collection(collection_id)?;
+        let properties = feature.properties.clone().unwrap_or_default();
+        let (columns, record) =
+            write::build_record(collection, &properties, feature.id.as_ref())
+                .map_err(|e| AppError::BadRequest(format!("Invalid feature: {}", e)))?;
+
+        let geometry_crs = feature.geometry.as_ref().map(|_| crs);
+        let sql = write::build_insert_sql(collection, &columns, geometry_crs);
+        let mut arguments = PgArguments::default();
+        SqlValue::Text(record.to_string()).add_to(&mut arguments);
+        if let Some(geometry) = &feature.geometry {
+            SqlValue::Text(geometry.to_string()).add_to(&mut arguments);
+        }
+
+        sqlx::query_as_with(&sql, arguments)
+            .fetch_one(&self.pool)
+            .await
+            .map_err(map_write_error)
+    }
+
+    async fn replace_feature(
+        &self,
+        collection_id: &str,
+        id: &str,
+        feature: &geojson::Feature,
+        crs: &Crs,
+        if_match: Option<&str>,
+    ) -> Result<String, AppError> {
+        let collection = self.get_collection(collection_id)?;
+        let feature_id = parse_feature_id(collection, id).map_err(AppError::BadRequest)?;
+        let properties = feature.properties.clone().unwrap_or_default();
+        let (_, record) = write::build_record(collection, &properties, None)
+            .map_err(|e| AppError::BadRequest(format!("Invalid feature: {}", e)))?;
+
+        // Properties missing from the feature are cleared, as the record sets them to NULL.
+        let columns: Vec<&str> = collection.properties.iter().map(String::as_str).collect();
+        let geometry = if feature.geometry.is_some() {
+            write::GeometryChange::Set
+        } else {
+            write::GeometryChange::Clear
+        };
+        let sql = write::build_update_sql(collection, &columns, geometry, crs, if_match.is_some())
+            .expect("the geometry is always written");
+
+        let mut arguments = PgArguments::default();
+        SqlValue::Text(record.to_string()).add_to(&mut arguments);
+        feature_id.add_to(&mut arguments);
+        if let Some(geometry) = &feature.geometry {
+            SqlValue::Text(geometry.to_string()).add_to(&mut arguments);
+        }
+        if let Some(etag) = if_match {
+            SqlValue::Text(etag.to_string()).add_to(&mut arguments);
+        }
+        self.execute_feature_update(&sql, arguments, collection, collection_id, id, &feature_id)
+            .await
+    }
+
+    async fn update_feature(
+        &self,
+        collection_id: &str,
+        id: &str,
+        patch: &FeaturePatch,
+        crs: &Crs,
+        if_match: Option<&str>,
+    ) -> Result<String, AppError> {
+        let collection = self.get_collection(collection_id)?;
+        let feature_id = parse_feature_id(collection, id).map_err(AppError::BadRequest)?;
+        let (columns, record) = write::build_record(collection, &patch.properties, None)
+            .map_err(|e| AppError::BadRequest(format!("Invalid patch: {}", e)))?;
+
+        let geometry = match &patch.geometry {
+            None => write::GeometryChange::Keep,
+            Some(None) => write::GeometryChange::Clear,
+            Some(Some(_)) => write::GeometryChange::Set,
+        };
+        let Some(sql) =
+            write::build_update_sql(collection, &columns, geometry, crs, if_match.is_some())
+        else {
+            // Nothing to change, but the preconditions still apply.
+            return match self.fetch_etag(collection, &feature_id).await? {
+                None => Err(feature_not_found(collection_id, id)),
+                Some(etag) if if_match.is_some_and(|expected| expected != etag) => {
+                    Err(precondition_failed(id))
+                }
+                Some(etag) => Ok(etag),
+            };
+        };
+
+        let mut arguments = PgArguments::default();
+        SqlValue::Text(record.to_string()).add_to(&mut arguments);
+        feature_id.add_to(&mut arguments);
+        if let Some(Some(geometry)) = &patch.geometry {
+            SqlValue::Text(geometry.to_string()).add_to(&mut arguments);
+        }
+        if let Some(etag) = if_match {
+            SqlValue::Text(etag.to_string()).add_to(&mut arguments);
+        }
+        self.execute_feature_update(&sql, arguments, collection, collection_id, id, &feature_id)
+            .await
+    }
+
+    async fn delete_feature(
+        &self,
+        collection_id: &str,
+        id: &str,
+        if_match: Option<&str>,
+    ) -> Result<(), AppError> {
+        let collection = self.get_collection(collection_id)?;
+        let feature_id = parse_feature_id(collection, id).map_err(AppError::BadRequest)?;
+
+        let mut arguments = PgArguments::default();
+        feature_id.add_to(&mut arguments);
+        if let Some(etag) = if_match {
+            SqlValue::Text(etag.to_string()).add_to(&mut arguments);
+        }
+        let result = sqlx::query_with(
+            &write::build_delete_sql(collection, if_match.is_some()),
+            arguments,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(map_write_error)?;
+
+        if result.rows_affected() == 0 {
+            return Err(self
+                .write_failure(collection, collection_id, id, &feature_id)
+                .await);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CollectionConfig;
+    use crate::models::GetItemsParams;
+    use std::collections::BTreeMap;
+
+    fn get_test_collection() -> CollectionConfig {
+        toml::from_str(
+            r#"
+            table = "naturalearth_lowres"
+            id_column = "ogc_fid"
+            geometry_column = "wkb_geometry"
+            properties = ["name", "pop_est"]
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_feature_query_parts_new_no_bbox() {
+        let collection = get_test_collection();
+        let params = GetItemsParams {
+            limit: Some(10),
+            offset: Some(0),
+            ..Default::default()
+        };
+        let query_parts = FeatureQueryParts::new(&collection, &params, &HashMap::new()).unwrap();
+
+        assert_eq!(query_parts.where_sql, "");
+        assert_eq!(query_parts.placeholder_count, 0);
+    }
+
+    #[test]
+    fn test_feature_query_parts_new_with_bbox() {
+        let collection = get_test_collection();
+        let params_with_bbox = GetItemsParams {
+            limit: Some(10),
+            offset: Some(0),
+            bbox: Some(vec![0.0, 0.0, 10.0, 10.0]),
+            ..Default::default()
+        };
+        let query_parts_with_bbox =
+            FeatureQueryParts::new(&collection, &params_with_bbox, &HashMap::new()).unwrap();
+
+        assert_eq!(
+            query_parts_with_bbox.where_sql,
+            "WHERE ST_Intersects(wkb_geometry, ST_MakeEnvelope($1, $2, $3, $4, 4326))"
+        );
+        assert_eq!(query_parts_with_bbox.placeholder_count, 4);
+    }
+
+    #[test]
+    fn test_get_properties_columns_sql() {
+        let collection = get_test_collection();
+        let properties = get_selected_properties(&collection, None).unwrap();
+        let sql = get_properties_columns_sql(&properties);
+        assert_eq!(sql, "'name', name, 'pop_est', pop_est");
+    }
+
+    #[test]
+    fn test_id_type_from_config() {
+        for (value, id_type) in [
+            ("integer", IdType::Integer),
+            ("bigint", IdType::Integer),
+            ("text", IdType::Text),
+            ("uuid", IdType::Uuid),
+        ] {
+            let collection: CollectionConfig = toml::from_str(&format!(
+                r#"
+                table = "t"
+                id_column = "id"
+                id_type = "{}"
+                geometry_column = "geom"
+                properties = []
+                "#,
+                value
+            ))
+            .unwrap();
+            assert_eq!(collection.id_type(), id_type);
+        }
+    }
+
+    #[test]
+    fn test_id_type_from_data_type() {
+        assert_eq!(IdType::from_data_type("integer"), Some(IdType::Integer));
+        assert_eq!(IdType::from_data_type("bigint"), Some(IdType::Integer));
+        assert_eq!(
+            IdType::from_data_type("character varying"),
+            Some(IdType::Text)
+        );
+        assert_eq!(IdType::from_data_type("uuid"), Some(IdType::Uuid));
+        assert_eq!(IdType::from_data_type("numeric"), None);
+    }
+
+    #[test]
+    fn test_parse_feature_id() {
+        let mut collection = get_test_collection();
+        assert_eq!(
+            parse_feature_id(&collection, "9007199254740993"),
+            Ok(SqlValue::Int(9007199254740993))
+        );
+        assert_eq!(
+            parse_feature_id(&collection, "abc"),
+            Err("Invalid feature ID 'abc', expected an integer".to_string())
+        );
+
+        collection.id_type = Some(IdType::Text);
+        assert_eq!(
+            parse_feature_id(&collection, "CHE"),
+            Ok(SqlValue::Text("CHE".to_string()))
+        );
+
+        collection.id_type = Some(IdType::Uuid);
+        assert_eq!(
+            parse_feature_id(&collection, "0b5c8a52-5b0e-4d53-9d8b-2f5c1d4c0a7e"),
+            Ok(SqlValue::Text(
+                "0b5c8a52-5b0e-4d53-9d8b-2f5c1d4c0a7e".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_build_single_feature_sql_per_id_type() {
+        let mut collection = get_test_collection();
+
+        collection.id_type = Some(IdType::Text);
+        let sql = build_single_feature_sql(&collection, Some(&Crs::crs84()), &["name", "pop_est"]);
+        assert!(sql.contains("ogc_fid::text as id"));
+        assert!(sql.ends_with("WHERE ogc_fid = $1"));
+
+        collection.id_type = Some(IdType::Uuid);
+        let sql = build_single_feature_sql(&collection, Some(&Crs::crs84()), &["name", "pop_est"]);
+        assert!(sql.contains("ogc_fid::text as id"));
+        assert!(sql.ends_with("WHERE ogc_fid = CAST($1 AS uuid)"));
+    }
+
+    #[test]
+    fn test_build_single_feature_sql() {
+        let collection = get_test_collection();
+        let sql = build_single_feature_sql(&collection, Some(&Crs::crs84()), &["name", "pop_est"]);
+        let expected_sql = "SELECT 'Feature' as type, ST_AsGeoJSON(wkb_geometry)::jsonb as geometry, json_build_object('name', name, 'pop_est', pop_est) as properties, ogc_fid::bigint as id, md5(CAST(naturalearth_lowres.* AS text)) as etag from naturalearth_lowres WHERE ogc_fid = $1";
+        assert_eq!(sql, expected_sql);
+    }
+
+    #[test]
+    fn test_build_feature_list_sql() {
+        let collection = get_test_collection();
+        let params = GetItemsParams {
+            limit: Some(10),
+            offset: Some(0),
+            ..Default::default()
+        };
+        let query_parts = FeatureQueryParts::new(&collection, &params, &HashMap::new()).unwrap();
+        let sql = build_feature_list_sql(&collection, &query_parts);
+        let expected_sql = "SELECT 'Feature' as type, ST_AsGeoJSON(wkb_geometry)::jsonb as geometry, json_build_object('name', name, 'pop_est', pop_est) as properties, ogc_fid::bigint as id, json_build_object('ogc_fid', ogc_fid) as sort_key from naturalearth_lowres order by ogc_fid LIMIT $1 OFFSET $2";
+        assert_eq!(sql, expected_sql);
+    }
+
+    #[test]
+    fn test_build_feature_list_sql_with_bbox() {
+        let collection = get_test_collection();
+        let params = GetItemsParams {
+            limit: Some(10),
+            offset: Some(0),
+            bbox: Some(vec![0.0, 0.0, 10.0, 10.0]),
+            ..Default::default()
+        };
+        let query_parts = FeatureQueryParts::new(&collection, &params, &HashMap::new()).unwrap();
+        let sql = build_feature_list_sql(&collection, &query_parts);
+        let expected_sql = "SELECT 'Feature' as type, ST_AsGeoJSON(wkb_geometry)::jsonb as geometry, json_build_object('name', name, 'pop_est', pop_est) as properties, ogc_fid::bigint as id, json_build_object('ogc_fid', ogc_fid) as sort_key from naturalearth_lowres WHERE ST_Intersects(wkb_geometry, ST_MakeEnvelope($1, $2, $3, $4, 4326)) order by ogc_fid LIMIT $5 OFFSET $6";
+        assert_eq!(sql, expected_sql);
+    }
+
+    #[test]
+    fn test_build_count_sql() {
+        let collection = get_test_collection();
+        let params = GetItemsParams {
+            limit: Some(10),
+            offset: Some(0),
+            ..Default::default()
+        };
+        let query_parts = FeatureQueryParts::new(&collection, &params, &HashMap::new()).unwrap();
+        let sql = build_count_sql(&collection, &query_parts);
+        let expected_sql = "SELECT count(*) from naturalearth_lowres";
+        assert_eq!(sql, expected_sql);
+    }
+
+    #[test]
+    fn test_build_single_feature_sql_with_crs() {
+        let mut collection = get_test_collection();
+        collection.storage_crs = Crs::from_uri("EPSG:2056");
+        let sql = build_single_feature_sql(
+            &collection,
+            Some(&Crs::from_uri("http://www.opengis.net/def/crs/EPSG/0/25832").unwrap()),
+            &["name", "pop_est"],
+        );
+        let expected_sql = "SELECT 'Feature' as type, ST_AsGeoJSON(ST_Transform(wkb_geometry, 25832))::jsonb as geometry, json_build_object('name', name, 'pop_est', pop_est) as properties, ogc_fid::bigint as id, md5(CAST(naturalearth_lowres.* AS text)) as etag from naturalearth_lowres WHERE ogc_fid = $1";
+        assert_eq!(sql, expected_sql);
+    }
+
+    #[test]
+    fn test_get_geometry_sql_with_lat_lon_crs() {
+        let collection = get_test_collection();
+        let sql = get_geometry_sql(&collection, &Crs::from_uri("EPSG:4326").unwrap());
+        assert_eq!(sql, "ST_AsGeoJSON(ST_FlipCoordinates(wkb_geometry))::jsonb");
+    }
+
+    #[test]
+    fn test_feature_query_parts_new_with_bbox_crs() {
+        let collection = get_test_collection();
+        let params = GetItemsParams {
+            bbox: Some(vec![2600000.0, 1200000.0, 2610000.0, 1210000.0]),
+            bbox_crs: Crs::from_uri("http://www.opengis.net/def/crs/EPSG/0/2056"),
+            ..Default::default()
+        };
+        let query_parts = FeatureQueryParts::new(&collection, &params, &HashMap::new()).unwrap();
+
+        assert_eq!(
+            query_parts.where_sql,
+            "WHERE ST_Intersects(wkb_geometry, ST_Transform(ST_MakeEnvelope($1, $2, $3, $4, 2056), 4326))"
+        );
+    }
+
+    #[test]
+    fn test_get_bbox_envelope_swaps_lat_lon() {
+        let bbox = vec![43.2, 5.3, 51.6, 13.8];
+        assert_eq!(get_bbox_envelope(&bbox, None), [43.2, 5.3, 51.6, 13.8]);
+        assert_eq!(
+            get_bbox_envelope(&bbox, Crs::from_uri("EPSG:4326").as_ref()),
+            [5.3, 43.2, 13.8, 51.6]
+        );
+    }
+
+    #[test]
+    fn test_supported_crs_includes_crs84_and_storage_crs() {
+        let mut collection = get_test_collection();
+        collection.storage_crs = Crs::from_uri("EPSG:2056");
+        collection.crs = vec![Crs::from_uri("EPSG:2056").unwrap(), Crs::crs84()];
+        let uris: Vec<_> = collection
+            .supported_crs()
+            .iter()
+            .map(|crs| crs.uri().to_string())
+            .collect();
+        assert_eq!(
+            uris,
+            vec![
+                "http://www.opengis.net/def/crs/OGC/1.3/CRS84",
+                "http://www.opengis.net/def/crs/EPSG/0/2056"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_split_table_name() {
+        assert_eq!(
+            split_table_name("public.countries"),
+            (Some("public"), "countries")
+        );
+        assert_eq!(split_table_name("countries"), (None, "countries"));
+    }
+
+    #[test]
+    fn test_get_json_schema_type() {
+        assert_eq!(get_json_schema_type("bigint"), (Some("integer"), None));
+        assert_eq!(
+            get_json_schema_type("double precision"),
+            (Some("number"), None)
+        );
+        assert_eq!(
+            get_json_schema_type("timestamp with time zone"),
+            (Some("string"), Some("date-time"))
+        );
+        assert_eq!(get_json_schema_type("USER-DEFINED"), (None, None));
+    }
+
+    #[test]
+    fn test_build_feature_list_sql_with_sortby() {
+        let collection = get_test_collection();
+        let params = GetItemsParams {
+            sortby: Some("-pop_est, +name".to_string()),
+            ..Default::default()
+        };
+        let query_parts = FeatureQueryParts::new(&collection, &params, &HashMap::new()).unwrap();
+        assert_eq!(query_parts.order_sql, "pop_est DESC, name, ogc_fid");
+
+        let sql = build_feature_list_sql(&collection, &query_parts);
+        assert!(sql.ends_with("order by pop_est DESC, name, ogc_fid LIMIT $1 OFFSET $2"));
+    }
+
+    #[test]
+    fn test_feature_query_parts_rejects_unknown_sortables() {
+        let mut collection = get_test_collection();
+        collection.sortables = Some(vec!["name".to_string()]);
+        let params = GetItemsParams {
+            sortby: Some("pop_est".to_string()),
+            ..Default::default()
+        };
+        let Err(AppError::BadRequest(message)) =
+            FeatureQueryParts::new(&collection, &params, &HashMap::new())
+        else {
+            panic!("sorting by a non-sortable property must fail");
+        };
+        assert_eq!(
+            message,
+            "Cannot sort by 'pop_est', sortable properties are: name"
+        );
+    }
+
+    fn get_test_cursor(sortby: Option<&str>, after: Value) -> String {
+        let Value::Object(after) = after else {
+            panic!("cursor values must be an object");
+        };
+        Cursor {
+            offset: 20,
+            sortby: sortby.map(str::to_string),
+            after,
+        }
+        .encode()
+    }
+
+    #[test]
+    fn test_feature_query_parts_with_cursor() {
+        let collection = get_test_collection();
+        let params = GetItemsParams {
+            sortby: Some("-pop_est,name".to_string()),
+            cursor: Some(get_test_cursor(
+                Some("-pop_est,name"),
+                serde_json::json!({"pop_est": 1000, "name": null, "ogc_fid": 7}),
+            )),
+            ..Default::default()
+        };
+        let query_parts = FeatureQueryParts::new(&collection, &params, &HashMap::new()).unwrap();
+
+        let record = "(json_populate_record(NULL::naturalearth_lowres, $1::json))";
+        assert_eq!(
+            query_parts.where_sql,
+            format!(
+                "WHERE (pop_est < {record}.pop_est OR pop_est = {record}.pop_est AND name IS NULL AND (ogc_fid > {record}.ogc_fid OR ogc_fid IS NULL))"
+            )
+        );
+        assert_eq!(query_parts.offset(), 20);
+        assert_eq!(
+            query_parts.binds,
+            vec![SqlValue::Text(
+                r#"{"name":null,"ogc_fid":7,"pop_est":1000}"#.to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_feature_query_parts_rejects_mismatching_cursor() {
+        let collection = get_test_collection();
+        let params = GetItemsParams {
+            sortby: Some("name".to_string()),
+            cursor: Some(get_test_cursor(None, serde_json::json!({"ogc_fid": 7}))),
+            ..Default::default()
+        };
+        let Err(AppError::BadRequest(message)) =
+            FeatureQueryParts::new(&collection, &params, &HashMap::new())
+        else {
+            panic!("a cursor created for another sortby must be rejected");
+        };
+        assert_eq!(
+            message,
+            "Invalid cursor: cursor was created for a different sortby"
+        );
+
+        let params = GetItemsParams {
+            cursor: Some("not a cursor".to_string()),
+            ..Default::default()
+        };
+        assert!(FeatureQueryParts::new(&collection, &params, &HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn test_feature_query_parts_rejects_cursor_values_of_another_type() {
+        let collection = get_test_collection();
+        let params = GetItemsParams {
+            sortby: Some("-pop_est".to_string()),
+            cursor: Some(get_test_cursor(
+                Some("-pop_est"),
+                serde_json::json!({"pop_est": "many", "ogc_fid": 7}),
+            )),
+            ..Default::default()
+        };
+        let column_types = HashMap::from([
+            ("ogc_fid".to_string(), "integer".to_string()),
+            ("pop_est".to_string(), "bigint".to_string()),
+        ]);
+        let Err(AppError::BadRequest(message)) =
+            FeatureQueryParts::new(&collection, &params, &column_types)
+        else {
+            panic!("a cursor value of another type must be rejected");
+        };
+        assert_eq!(
+            message,
+            "Invalid cursor: the value of 'pop_est' is not a bigint"
+        );
+
+        assert!(fits_column(
+            &serde_json::json!("2024-01-31T12:00:00+00:00"),
+            "timestamp with time zone"
+        ));
+        assert!(fits_column(&serde_json::json!("infinity"), "date"));
+        assert!(fits_column(&serde_json::json!("NaN"), "double precision"));
+        assert!(!fits_column(&serde_json::json!(1.5), "integer"));
+        assert!(!fits_column(&serde_json::json!("1234"), "uuid"));
+    }
+
+    #[test]
+    fn test_feature_query_parts_with_property_filters() {
+        let collection = get_test_collection();
+        let params = GetItemsParams {
+            property_filters: BTreeMap::from([
+                ("name".to_string(), "Chad".to_string()),
+                ("pop_est".to_string(), "15946876".to_string()),
+            ]),
+            ..Default::default()
+        };
+        let column_types = HashMap::from([
+            ("name".to_string(), "character varying".to_string()),
+            ("pop_est".to_string(), "bigint".to_string()),
+        ]);
+        let query_parts = FeatureQueryParts::new(&collection, &params, &column_types).unwrap();
+        assert_eq!(query_parts.where_sql, "WHERE name = $1 AND pop_est = $2");
+        assert_eq!(
+            query_parts.binds,
+            vec![SqlValue::Text("Chad".to_string()), SqlValue::Int(15946876)]
+        );
+
+        let column_types = HashMap::from([("pop_est".to_string(), "integer".to_string())]);
+        let query_parts = FeatureQueryParts::new(&collection, &params, &column_types).unwrap();
+        assert_eq!(
+            query_parts.where_sql,
+            "WHERE CAST(name AS text) = $1 AND pop_est = $2"
+        );
+    }
+
+    #[test]
+    fn test_get_property_filter_sql_binds_typed_values() {
+        let mut binds = Vec::new();
+        assert_eq!(
+            get_property_filter_sql("active", Some("boolean"), "true", &mut binds),
+            Ok("active = $1".to_string())
+        );
+        assert_eq!(
+            get_property_filter_sql("area", Some("double precision"), "1.5", &mut binds),
+            Ok("area = $2".to_string())
+        );
+        assert_eq!(
+            get_property_filter_sql("updated", Some("date"), "2024-01-01", &mut binds),
+            Ok("updated = CAST($3 AS date)".to_string())
+        );
+        assert_eq!(
+            binds,
+            vec![
+                SqlValue::Bool(true),
+                SqlValue::Float(1.5),
+                SqlValue::Text("2024-01-01".to_string())
+            ]
+        );
+        assert_eq!(
+            get_property_filter_sql("pop_est", Some("integer"), "many", &mut binds),
+            Err("Invalid value 'many' for property 'pop_est', expected an integer".to_string())
+        );
+        assert_eq!(
+            get_property_filter_sql("updated", Some("date"), "2024-02-30", &mut binds),
+            Err(
+                "Invalid value '2024-02-30' for property 'updated', expected a date such as 2024-01-31"
+                    .to_string()
+            )
+        );
+        assert!(
+            get_property_filter_sql(
+                "created",
+                Some("timestamp with time zone"),
+                "soon",
+                &mut binds
+            )
+            .is_err()
+        );
+        assert!(get_property_filter_sql("uid", Some("uuid"), "1234", &mut binds).is_err());
+        assert_eq!(binds.len(), 3);
+    }
+
+    #[test]
+    fn test_is_date_and_timestamp() {
+        assert!(is_date("2024-02-29"));
+        assert!(!is_date("2023-02-29"));
+        assert!(!is_date("2024-13-01"));
+        assert!(!is_date("2024-1-01"));
+        assert!(is_timestamp("2024-01-31"));
+        assert!(is_timestamp("2024-01-31T12:00:00Z"));
+        assert!(is_timestamp("2024-01-31 12:00:00.123+01:00"));
+        assert!(is_timestamp("2024-01-31T12:00:00"));
+        assert!(!is_timestamp("2024-01-31T24:00:00Z"));
+        assert!(!is_timestamp("2024-01-31T12:00Z"));
+        assert!(!is_timestamp("2024-01-31T12:00:00+1"));
+        assert!(!is_timestamp("2024-01-31T12:00:00."));
+        assert!(!is_timestamp("2024-01-31é"));
+    }
+
+    #[test]
+    fn test_feature_query_parts_rejects_unknown_parameters() {
+        let collection = get_test_collection();
+        let params = GetItemsParams {
+            property_filters: BTreeMap::from([("secret".to_string(), "1".to_string())]),
+            ..Default::default()
+        };
+        let Err(AppError::BadRequest(message)) =
+            FeatureQueryParts::new(&collection, &params, &HashMap::new())
+        else {
+            panic!("unknown query parameters must be rejected");
+        };
+        assert_eq!(
+            message,
+            "Unknown query parameter 'secret', features can be filtered by: name, pop_est"
+        );
+    }
+
+    #[test]
+    fn test_get_datetime_sql_instant_column() {
+        let temporal = TemporalConfig::Instant {
+            instant: "observed_at".to_string(),
+        };
+        let mut binds = Vec::new();
+        let sql = get_datetime_sql(
+            &temporal,
+            &Datetime::Instant("2024-01-01T00:00:00Z".to_string()),
+            &mut binds,
+        );
+        assert_eq!(
+            sql,
+            "observed_at >= CAST($1 AS timestamptz) AND observed_at <= CAST($1 AS timestamptz)"
+        );
+        assert_eq!(
+            binds,
+            vec![SqlValue::Text("2024-01-01T00:00:00Z".to_string())]
+        );
+
+        let sql = get_datetime_sql(
+            &temporal,
+            &Datetime::Interval {
+                start: None,
+                end: Some("2024-01-01".to_string()),
+            },
+            &mut binds,
+        );
+        assert_eq!(sql, "observed_at <= CAST($2 AS timestamptz)");
+    }
+
+    #[test]
+    fn test_get_datetime_sql_interval_columns() {
+        let temporal = TemporalConfig::Interval {
+            start: "valid_from".to_string(),
+            end: "valid_to".to_string(),
+        };
+        let mut binds = Vec::new();
+        let sql = get_datetime_sql(
+            &temporal,
+            &Datetime::Interval {
+                start: Some("2023-01-01".to_string()),
+                end: Some("2024-01-01".to_string()),
+            },
+            &mut binds,
+        );
+        assert_eq!(
+            sql,
+            "(valid_from IS NULL OR valid_from <= CAST($2 AS timestamptz)) AND (valid_to IS NULL OR valid_to >= CAST($1 AS timestamptz))"
+        );
+
+        let sql = get_datetime_sql(
+            &temporal,
+            &Datetime::Interval {
+                start: Some("2024-01-01".to_string()),
+                end: None,
+            },
+            &mut binds,
+        );
+        assert_eq!(
+            sql,
+            "(valid_to IS NULL OR valid_to >= CAST($3 AS timestamptz))"
+        );
+    }
+
+    #[test]
+    fn test_feature_query_parts_with_datetime() {
+        let mut collection = get_test_collection();
+        let params = GetItemsParams {
+            datetime: Some("2024-01-01".to_string()),
+            ..Default::default()
+        };
+        let Err(AppError::BadRequest(message)) =
+            FeatureQueryParts::new(&collection, &params, &HashMap::new())
+        else {
+            panic!("datetime requires a temporal configuration");
+        };
+        assert_eq!(
+            message,
+            "Invalid datetime: the collection has no temporal properties"
+        );
+
+        collection.temporal = Some(TemporalConfig::Instant {
+            instant: "updated".to_string(),
+        });
+        let query_parts = FeatureQueryParts::new(&collection, &params, &HashMap::new()).unwrap();
+        assert_eq!(
+            query_parts.where_sql,
+            "WHERE updated >= CAST($1 AS timestamptz) AND updated <= CAST($1 AS timestamptz)"
+        );
+    }
+
+    #[test]
+    fn test_temporal_config_and_extent_sql() {
+        let collection: CollectionConfig = toml::from_str(
+            r#"
+            table = "permits"
+            id_column = "id"
+            geometry_column = "geom"
+            properties = []
+            temporal = { start = "valid_from", end = "valid_to" }
+            "#,
+        )
+        .unwrap();
+        let temporal = collection.temporal.as_ref().unwrap();
+        assert_eq!(
+            temporal,
+            &TemporalConfig::Interval {
+                start: "valid_from".to_string(),
+                end: "valid_to".to_string()
+            }
+        );
+        assert_eq!(
+            build_temporal_extent_sql(&collection, temporal),
+            "SELECT count(*), CASE WHEN count(*) > count(valid_from) THEN NULL ELSE to_json(min(valid_from)) #>> '{}' END, CASE WHEN count(*) > count(valid_to) THEN NULL ELSE to_json(max(valid_to)) #>> '{}' END from permits"
+        );
+    }
+
+    #[test]
+    fn test_build_spatial_extent_sql() {
+        let mut collection = get_test_collection();
+        assert_eq!(
+            build_spatial_extent_sql(&collection),
+            "SELECT ST_XMin(envelope), ST_YMin(envelope), ST_XMax(envelope), ST_YMax(envelope) FROM (SELECT ST_SetSRID(ST_Extent(wkb_geometry)::geometry, 4326) AS envelope FROM naturalearth_lowres) AS extent"
+        );
+
+        collection.storage_crs = Crs::from_uri("EPSG:2056");
+        assert_eq!(
+            build_estimated_extent_sql(&collection),
+            "SELECT ST_XMin(envelope), ST_YMin(envelope), ST_XMax(envelope), ST_YMax(envelope) FROM (SELECT ST_Transform(ST_SetSRID(ST_EstimatedExtent(COALESCE($1, current_schema())::text, $2, $3)::geometry, 2056), 4326) AS envelope) AS extent"
+        );
+    }
+
+    #[test]
+    fn test_configured_extent() {
+        let collection: CollectionConfig = toml::from_str(
+            r#"
+            table = "permits"
+            id_column = "id"
+            geometry_column = "geom"
+            properties = []
+            extent = { bbox = [5.9, 45.8, 10.5, 47.8], interval = ["2020-01-01T00:00:00Z", ".."] }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            collection.extent.spatial(),
+            Some(SpatialExtent::new([5.9, 45.8, 10.5, 47.8]))
+        );
+        assert_eq!(
+            collection.extent.temporal(),
+            Some(TemporalExtent::new(
+                Some("2020-01-01T00:00:00Z".to_string()),
+                None
+            ))
+        );
+        assert_eq!(get_test_collection().extent.temporal(), None);
+    }
+
+    #[test]
+    fn test_feature_query_parts_with_property_selection() {
+        let collection = get_test_collection();
+        let params = GetItemsParams {
+            properties: Some("pop_est".to_string()),
+            skip_geometry: Some(true),
+            ..Default::default()
+        };
+        let query_parts = FeatureQueryParts::new(&collection, &params, &HashMap::new()).unwrap();
+        let sql = build_feature_list_sql(&collection, &query_parts);
+        assert!(sql.starts_with(
+            "SELECT 'Feature' as type, NULL::jsonb as geometry, json_build_object('pop_est', pop_est) as properties,"
+        ));
+
+        let params = GetItemsParams {
+            properties: Some("name,secret".to_string()),
+            ..Default::default()
+        };
+        let Err(AppError::BadRequest(message)) =
+            FeatureQueryParts::new(&collection, &params, &HashMap::new())
+        else {
+            panic!("unknown properties must be rejected");
+        };
+        assert_eq!(
+            message,
+            "Unknown property 'secret', available properties are: name, pop_est"
+        );
+    }
+
+    #[test]
+    fn test_build_single_feature_sql_without_geometry() {
+        let collection = get_test_collection();
+        let sql = build_single_feature_sql(&collection, None, &[]);
+        assert_eq!(
+            sql,
+            "SELECT 'Feature' as type, NULL::jsonb as geometry, json_build_object() as properties, ogc_fid::bigint as id, md5(CAST(naturalearth_lowres.* AS text)) as etag from naturalearth_lowres WHERE ogc_fid = $1"
+        );
+    }
+
+    #[test]
+    fn test_get_etag_sql() {
+        let mut collection = get_test_collection();
+        assert_eq!(
+            get_etag_sql(&collection, "target"),
+            "md5(CAST(target.* AS text))"
+        );
+        collection.version_column = Some("updated_at".to_string());
+        assert_eq!(
+            get_etag_sql(&collection, "target"),
+            "CAST(target.updated_at AS text)"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_read_in_background() {
+        let idle_timeout = Duration::from_millis(50);
+        let features =
+            read_in_background(stream::iter([Ok(1), Ok(2), Ok(3)]).boxed(), idle_timeout);
+        assert_eq!(
+            features.map(Result::unwrap).collect::<Vec<_>>().await,
+            [1, 2, 3]
+        );
+
+        let failing = stream::iter([Ok(1), Err(AppError::BadRequest("bad".to_string())), Ok(3)]);
+        let results: Vec<_> = read_in_background(failing.boxed(), idle_timeout)
+            .collect()
+            .await;
+        assert_eq!(
+            results,
+            [Ok(1), Err(AppError::BadRequest("bad".to_string()))]
+        );
+
+        let mut features =
+            read_in_background(stream::iter([Ok(1), Ok(2), Ok(3)]).boxed(), idle_timeout);
+        assert_eq!(features.next().await, Some(Ok(1)));
+        tokio::time::sleep(idle_timeout * 4).await;
+        // The feature handed over before the timeout is still read.
+        assert_eq!(features.next().await, Some(Ok(2)));
+        assert!(matches!(
+            features.next().await,
+            Some(Err(AppError::Internal(_)))
+        ));
+        assert_eq!(features.next().await, None);
+    }
+}
@@ -0,0 +1,94 @@
+/*
+ * SQL of the vector tiles (OGC API Tiles), encoded by PostGIS.
+ *
+ * The tile bounds are bound to `$1` to `$4` in the CRS of the tile matrix set and the layer name
+ * to `$5`. Geometries are clipped to the tile with a margin, so that lines and outlines crossing
+ * tile borders are drawn without seams.
+ */
+use crate::config::{CollectionConfig, IdType};
+use crate::models::TilingScheme;
+
+/// The size of the tiles in the units of their geometries.
+pub(super) const TILE_EXTENT: u32 = 4096;
+
+/// The margin around the tiles geometries are kept in, in tile units.
+pub(super) const TILE_BUFFER: u32 = 64;
+
+/// Builds the query of a tile of a collection holding `properties`. Integer IDs become the IDs
+/// of the tile features, other IDs are kept as an `id` attribute.
+pub(super) fn build_tile_sql(
+    collection: &CollectionConfig,
+    scheme: TilingScheme,
+    properties: &[&str],
+) -> String {
+    let tile_srid = scheme.crs().srid();
+    let storage_srid = collection.storage_crs().srid();
+    let envelope = format!("ST_MakeEnvelope($1, $2, $3, $4, {})", tile_srid);
+    let mut geometry = collection.geometry_column.clone();
+    let mut bounds = format!(
+        "ST_Expand({}, ($3 - $1) * {} / {})",
+        envelope, TILE_BUFFER, TILE_EXTENT
+    );
+    if tile_srid != storage_srid {
+        bounds = format!("ST_Transform({}, {})", bounds, storage_srid);
+        // Clipping first keeps the parts near the poles, which Web Mercator cannot project, out.
+        geometry = format!(
+            "ST_Transform(ST_ClipByBox2D({}, {}), {})",
+            geometry, bounds, tile_srid
+        );
+    }
+    let (id_sql, feature_id) = match collection.id_type() {
+        IdType::Integer => (format!("{}::bigint", collection.id_column), ", 'id'"),
+        IdType::Text | IdType::Uuid => (format!("{}::text", collection.id_column), ""),
+    };
+    let columns: String = properties
+        .iter()
+        .map(|property| format!(", {}", property))
+        .collect();
+
+    format!(
+        "SELECT ST_AsMVT(tile, $5, {extent}, 'mvt_geom'{feature_id}) FROM (SELECT ST_AsMVTGeom({geometry}, {envelope}, {extent}, {buffer}, true) AS mvt_geom, {id_sql} AS id{columns} FROM {table} WHERE ST_Intersects({geometry_column}, {bounds})) AS tile",
+        extent = TILE_EXTENT,
+        buffer = TILE_BUFFER,
+        table = collection.table,
+        geometry_column = collection.geometry_column,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Crs;
+
+    fn get_test_collection() -> CollectionConfig {
+        toml::from_str(
+            r#"
+            table = "countries"
+            id_column = "id"
+            geometry_column = "geom"
+            properties = ["name", "pop_est"]
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_build_tile_sql() {
+        let collection = get_test_collection();
+        assert_eq!(
+            build_tile_sql(&collection, TilingScheme::WorldCrs84Quad, &["name"]),
+            "SELECT ST_AsMVT(tile, $5, 4096, 'mvt_geom', 'id') FROM (SELECT ST_AsMVTGeom(geom, ST_MakeEnvelope($1, $2, $3, $4, 4326), 4096, 64, true) AS mvt_geom, id::bigint AS id, name FROM countries WHERE ST_Intersects(geom, ST_Expand(ST_MakeEnvelope($1, $2, $3, $4, 4326), ($3 - $1) * 64 / 4096))) AS tile"
+        );
+    }
+
+    #[test]
+    fn test_build_tile_sql_reprojected() {
+        let mut collection = get_test_collection();
+        collection.storage_crs = Crs::from_uri("EPSG:2056");
+        collection.id_type = Some(IdType::Text);
+        assert_eq!(
+            build_tile_sql(&collection, TilingScheme::WebMercatorQuad, &[]),
+            "SELECT ST_AsMVT(tile, $5, 4096, 'mvt_geom') FROM (SELECT ST_AsMVTGeom(ST_Transform(ST_ClipByBox2D(geom, ST_Transform(ST_Expand(ST_MakeEnvelope($1, $2, $3, $4, 3857), ($3 - $1) * 64 / 4096), 2056)), 3857), ST_MakeEnvelope($1, $2, $3, $4, 3857), 4096, 64, true) AS mvt_geom, id::text AS id FROM countries WHERE ST_Intersects(geom, ST_Transform(ST_Expand(ST_MakeEnvelope($1, $2, $3, $4, 3857), ($3 - $1) * 64 / 4096), 2056))) AS tile"
+        );
+    }
+}
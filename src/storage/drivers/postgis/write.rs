@@ -0,0 +1,290 @@
+/*
+ * SQL of the feature transactions (OGC API Features Part 4).
+ *
+ * The property values of a feature are bound as a single JSON object and converted to the
+ * column types with `json_populate_record`, so any column PostgreSQL can read from JSON is
+ * writable without knowing its type up front. Only configured columns are written.
+ */
+use super::{get_etag_sql, get_id_placeholder_sql};
+use crate::config::CollectionConfig;
+use crate::models::Crs;
+use geojson::feature::Id;
+use serde_json::{Map, Value};
+
+/// How an update changes the geometry column.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum GeometryChange {
+    Keep,
+    Clear,
+    /// Sets the geometry to the GeoJSON bound to `$3`.
+    Set,
+}
+
+/// Validates the properties of a feature and builds the record they are written from, returning
+/// the written columns along with the record. The ID is only written when the client gives one,
+/// so that column defaults apply otherwise.
+pub(super) fn build_record<'a>(
+    collection: &'a CollectionConfig,
+    properties: &Map<String, Value>,
+    id: Option<&Id>,
+) -> Result<(Vec<&'a str>, Value), String> {
+    let mut columns = Vec::new();
+    let mut record = Map::new();
+    for (name, value) in properties {
+        let column = collection
+            .properties
+            .iter()
+            .find(|property| *property == name)
+            .ok_or_else(|| {
+                format!(
+                    "unknown property '{}', writable properties are: {}",
+                    name,
+                    collection.properties.join(", ")
+                )
+            })?;
+        columns.push(column.as_str());
+        record.insert(column.clone(), value.clone());
+    }
+    if let Some(id) = id {
+        let value = match id {
+            Id::String(id) => Value::String(id.clone()),
+            Id::Number(id) => Value::Number(id.clone()),
+        };
+        columns.push(collection.id_column.as_str());
+        record.insert(collection.id_column.clone(), value);
+    }
+    Ok((columns, Value::Object(record)))
+}
+
+/// Builds the expression reading the GeoJSON geometry bound to `placeholder`, given in `crs`,
+/// into the storage CRS.
+pub(super) fn get_geometry_input_sql(
+    collection: &CollectionConfig,
+    crs: &Crs,
+    placeholder: usize,
+) -> String {
+    let mut geometry = format!("ST_GeomFromGeoJSON(${})", placeholder);
+    if crs.is_lat_lon() {
+        geometry = format!("ST_FlipCoordinates({})", geometry);
+    }
+    geometry = format!("ST_SetSRID({}, {})", geometry, crs.srid());
+    let storage_srid = collection.storage_crs().srid();
+    if crs.srid() != storage_srid {
+        geometry = format!("ST_Transform({}, {})", geometry, storage_srid);
+    }
+    geometry
+}
+
+/// Builds the insert of a feature returning its ID as text and its ETag. The record is bound to
+/// `$1` and, when `crs` is given, the geometry to `$2`.
+pub(super) fn build_insert_sql(
+    collection: &CollectionConfig,
+    columns: &[&str],
+    crs: Option<&Crs>,
+) -> String {
+    let mut targets: Vec<String> = columns.iter().map(|c| c.to_string()).collect();
+    let mut values: Vec<String> = columns.iter().map(|c| format!("record.{}", c)).collect();
+    if let Some(crs) = crs {
+        targets.push(collection.geometry_column.clone());
+        values.push(get_geometry_input_sql(collection, crs, 2));
+    }
+
+    let returning = format!(
+        "RETURNING {}::text, {}",
+        collection.id_column,
+        get_etag_sql(collection, &collection.table)
+    );
+    if targets.is_empty() {
+        return format!(
+            "INSERT INTO {} DEFAULT VALUES {}",
+            collection.table, returning
+        );
+    }
+    format!(
+        "INSERT INTO {} ({}) SELECT {} FROM json_populate_record(NULL::{}, $1::json) AS record {}",
+        collection.table,
+        targets.join(", "),
+        values.join(", "),
+        collection.table,
+        returning
+    )
+}
+
+/// Builds the update of a feature returning its new ETag, with the record bound to `$1`, the ID
+/// to `$2`, the geometry to `$3` and then the expected ETag when `if_match` is set. Returns
+/// `None` when there is nothing to change.
+pub(super) fn build_update_sql(
+    collection: &CollectionConfig,
+    columns: &[&str],
+    geometry: GeometryChange,
+    crs: &Crs,
+    if_match: bool,
+) -> Option<String> {
+    let mut assignments: Vec<String> = columns
+        .iter()
+        .map(|c| format!("{} = record.{}", c, c))
+        .collect();
+    match geometry {
+        GeometryChange::Keep => {}
+        GeometryChange::Clear => assignments.push(format!("{} = NULL", collection.geometry_column)),
+        GeometryChange::Set => assignments.push(format!(
+            "{} = {}",
+            collection.geometry_column,
+            get_geometry_input_sql(collection, crs, 3)
+        )),
+    }
+    if assignments.is_empty() {
+        return None;
+    }
+
+    let etag = get_etag_sql(collection, "target");
+    let mut condition = format!(
+        "target.{} = {}",
+        collection.id_column,
+        get_id_placeholder_sql(collection, 2)
+    );
+    if if_match {
+        let placeholder = if geometry == GeometryChange::Set {
+            4
+        } else {
+            3
+        };
+        condition = format!("{} AND {} = ${}", condition, etag, placeholder);
+    }
+    Some(format!(
+        "UPDATE {} AS target SET {} FROM json_populate_record(NULL::{}, $1::json) AS record WHERE {} RETURNING {}",
+        collection.table,
+        assignments.join(", "),
+        collection.table,
+        condition,
+        etag
+    ))
+}
+
+/// Builds the delete of a feature with the ID bound to `$1` and, when `if_match` is set, the
+/// expected ETag to `$2`.
+pub(super) fn build_delete_sql(collection: &CollectionConfig, if_match: bool) -> String {
+    let mut sql = format!(
+        "DELETE FROM {} WHERE {} = {}",
+        collection.table,
+        collection.id_column,
+        get_id_placeholder_sql(collection, 1)
+    );
+    if if_match {
+        sql = format!(
+            "{} AND {} = $2",
+            sql,
+            get_etag_sql(collection, &collection.table)
+        );
+    }
+    sql
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::IdType;
+    use serde_json::json;
+
+    fn get_test_collection() -> CollectionConfig {
+        toml::from_str(
+            r#"
+            table = "naturalearth_lowres"
+            id_column = "ogc_fid"
+            geometry_column = "wkb_geometry"
+            properties = ["name", "pop_est"]
+            writable = true
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_build_record() {
+        let collection = get_test_collection();
+        let properties = json!({"pop_est": 5, "name": "Chad"});
+        let (columns, record) =
+            build_record(&collection, properties.as_object().unwrap(), None).unwrap();
+        assert_eq!(columns, ["name", "pop_est"]);
+        assert_eq!(record, json!({"name": "Chad", "pop_est": 5}));
+
+        let (columns, record) = build_record(
+            &collection,
+            &Map::new(),
+            Some(&Id::String("TCD".to_string())),
+        )
+        .unwrap();
+        assert_eq!(columns, ["ogc_fid"]);
+        assert_eq!(record, json!({"ogc_fid": "TCD"}));
+
+        let properties = json!({"secret": 1});
+        assert_eq!(
+            build_record(&collection, properties.as_object().unwrap(), None).unwrap_err(),
+            "unknown property 'secret', writable properties are: name, pop_est"
+        );
+    }
+
+    #[test]
+    fn test_build_insert_sql() {
+        let collection = get_test_collection();
+        assert_eq!(
+            build_insert_sql(&collection, &["name"], Some(&Crs::crs84())),
+            "INSERT INTO naturalearth_lowres (name, wkb_geometry) SELECT record.name, ST_SetSRID(ST_GeomFromGeoJSON($2), 4326) FROM json_populate_record(NULL::naturalearth_lowres, $1::json) AS record RETURNING ogc_fid::text, md5(CAST(naturalearth_lowres.* AS text))"
+        );
+        assert_eq!(
+            build_insert_sql(&collection, &[], None),
+            "INSERT INTO naturalearth_lowres DEFAULT VALUES RETURNING ogc_fid::text, md5(CAST(naturalearth_lowres.* AS text))"
+        );
+    }
+
+    #[test]
+    fn test_get_geometry_input_sql_reprojects() {
+        let mut collection = get_test_collection();
+        collection.storage_crs = Crs::from_uri("EPSG:2056");
+        assert_eq!(
+            get_geometry_input_sql(&collection, &Crs::from_uri("EPSG:4326").unwrap(), 3),
+            "ST_Transform(ST_SetSRID(ST_FlipCoordinates(ST_GeomFromGeoJSON($3)), 4326), 2056)"
+        );
+    }
+
+    #[test]
+    fn test_build_update_sql() {
+        let mut collection = get_test_collection();
+        assert_eq!(
+            build_update_sql(
+                &collection,
+                &["name", "pop_est"],
+                GeometryChange::Set,
+                &Crs::crs84(),
+                false
+            )
+            .unwrap(),
+            "UPDATE naturalearth_lowres AS target SET name = record.name, pop_est = record.pop_est, wkb_geometry = ST_SetSRID(ST_GeomFromGeoJSON($3), 4326) FROM json_populate_record(NULL::naturalearth_lowres, $1::json) AS record WHERE target.ogc_fid = $2 RETURNING md5(CAST(target.* AS text))"
+        );
+
+        collection.id_type = Some(IdType::Uuid);
+        assert_eq!(
+            build_update_sql(&collection, &[], GeometryChange::Clear, &Crs::crs84(), true).unwrap(),
+            "UPDATE naturalearth_lowres AS target SET wkb_geometry = NULL FROM json_populate_record(NULL::naturalearth_lowres, $1::json) AS record WHERE target.ogc_fid = CAST($2 AS uuid) AND md5(CAST(target.* AS text)) = $3 RETURNING md5(CAST(target.* AS text))"
+        );
+        assert_eq!(
+            build_update_sql(&collection, &[], GeometryChange::Keep, &Crs::crs84(), true),
+            None
+        );
+    }
+
+    #[test]
+    fn test_build_delete_sql() {
+        let mut collection = get_test_collection();
+        assert_eq!(
+            build_delete_sql(&collection, false),
+            "DELETE FROM naturalearth_lowres WHERE ogc_fid = $1"
+        );
+
+        collection.version_column = Some("revision".to_string());
+        assert_eq!(
+            build_delete_sql(&collection, true),
+            "DELETE FROM naturalearth_lowres WHERE ogc_fid = $1 AND CAST(naturalearth_lowres.revision AS text) = $2"
+        );
+    }
+}
@@ -1,4 +1,4 @@
 pub mod drivers;
 mod store;
 
-pub use store::{FeaturesWithCount, Storage};
+pub use store::{FeatureStream, FeatureWithEtag, FeaturesWithCount, Storage, StreamedFeature};
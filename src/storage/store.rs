@@ -1,34 +1,160 @@
-use crate::models::GetItemsParams;
+use crate::error::AppError;
+use crate::models::{
+    Crs, Cursor, Extent, FeaturePatch, GetItemParams, GetItemsParams, QueryableProperty,
+    TilingScheme,
+};
 use async_trait::async_trait;
-use axum::http::StatusCode;
+use futures_util::{TryStreamExt, stream::BoxStream};
 
 pub struct FeaturesWithCount {
     pub features: Vec<geojson::Feature>,
     pub number_matched: u64,
     pub number_returned: u64,
+    /// The position of the first returned feature within all matched features.
+    pub offset: u64,
+    /// The encoded cursor of the next page, if there is one.
+    pub next_cursor: Option<String>,
 }
 
 impl FeaturesWithCount {
-    pub fn new(features: Vec<geojson::Feature>, number_matched: u64, number_returned: u64) -> Self {
+    pub fn new(
+        features: Vec<geojson::Feature>,
+        number_matched: u64,
+        number_returned: u64,
+        offset: u64,
+        next_cursor: Option<String>,
+    ) -> Self {
         Self {
             features,
             number_matched,
             number_returned,
+            offset,
+            next_cursor,
         }
     }
 }
 
+/// A feature read from a [`FeatureStream`].
+pub struct StreamedFeature {
+    pub feature: geojson::Feature,
+    /// The cursor of the page following this feature, when more features match.
+    pub next_cursor: Option<Cursor>,
+}
+
+/// A page of features read from the storage as they are consumed, so that pages of any size are
+/// served without holding them in memory.
+pub struct FeatureStream {
+    pub number_matched: u64,
+    /// The position of the first feature of the page within all matched features.
+    pub offset: u64,
+    pub features: BoxStream<'static, Result<StreamedFeature, AppError>>,
+}
+
+/// A feature along with the entity tag of its current version.
+pub struct FeatureWithEtag {
+    pub feature: geojson::Feature,
+    pub etag: String,
+}
+
 #[async_trait]
 pub trait Storage: Send + Sync {
+    /// Returns a page of features, read as the stream is consumed. The limit of `params` is
+    /// applied as given, the callers lowering it to the maximum page size where it applies.
+    async fn stream_features(
+        &self,
+        collection_id: &str,
+        params: &GetItemsParams,
+    ) -> Result<FeatureStream, AppError>;
+
+    /// Returns a whole page of features, for the encodings that need all of them at once.
     async fn get_features(
         &self,
         collection_id: &str,
         params: &GetItemsParams,
-    ) -> Result<FeaturesWithCount, (StatusCode, String)>;
+    ) -> Result<FeaturesWithCount, AppError> {
+        let mut page = self.stream_features(collection_id, params).await?;
+        let mut features = Vec::new();
+        let mut next_cursor = None;
+        while let Some(streamed) = page.features.try_next().await? {
+            features.push(streamed.feature);
+            next_cursor = streamed.next_cursor;
+        }
+
+        let number_returned = features.len() as u64;
+        Ok(FeaturesWithCount::new(
+            features,
+            page.number_matched,
+            number_returned,
+            page.offset,
+            next_cursor.map(|cursor| cursor.encode()),
+        ))
+    }
 
     async fn get_feature(
         &self,
         collection_id: &str,
         id: &str,
-    ) -> Result<geojson::Feature, (StatusCode, String)>;
+        params: &GetItemParams,
+    ) -> Result<FeatureWithEtag, AppError>;
+
+    /// Returns the bounding box and the interval covered by the features of a collection, unless
+    /// configured. Computed extents may be cached for a while.
+    async fn get_extent(&self, collection_id: &str) -> Result<Option<Extent>, AppError>;
+
+    /// Returns the properties that can be used in filters, starting with the geometry.
+    async fn get_queryables(&self, collection_id: &str)
+    -> Result<Vec<QueryableProperty>, AppError>;
+
+    /// Returns the WKT definition of a CRS, if the database knows it.
+    async fn get_crs_definition(&self, crs: &Crs) -> Result<Option<String>, AppError>;
+
+    /// Returns the Mapbox Vector Tile of the features of a collection in a tile, with a single
+    /// layer named after the collection. The tile is empty when no feature intersects it.
+    async fn get_tile(
+        &self,
+        collection_id: &str,
+        scheme: TilingScheme,
+        z: u8,
+        x: u32,
+        y: u32,
+    ) -> Result<Vec<u8>, AppError>;
+
+    /// Inserts a feature whose geometry is given in `crs`, and returns its ID and ETag.
+    async fn create_feature(
+        &self,
+        collection_id: &str,
+        feature: &geojson::Feature,
+        crs: &Crs,
+    ) -> Result<(String, String), AppError>;
+
+    /// Replaces the geometry and all properties of a feature, and returns its new ETag.
+    ///
+    /// Like the other writes, it only happens if the current ETag of the feature is `if_match`,
+    /// failing with 412 otherwise. `None` matches any version.
+    async fn replace_feature(
+        &self,
+        collection_id: &str,
+        id: &str,
+        feature: &geojson::Feature,
+        crs: &Crs,
+        if_match: Option<&str>,
+    ) -> Result<String, AppError>;
+
+    /// Changes the geometry and properties given in the patch, leaving the others untouched, and
+    /// returns the new ETag.
+    async fn update_feature(
+        &self,
+        collection_id: &str,
+        id: &str,
+        patch: &FeaturePatch,
+        crs: &Crs,
+        if_match: Option<&str>,
+    ) -> Result<String, AppError>;
+
+    async fn delete_feature(
+        &self,
+        collection_id: &str,
+        id: &str,
+        if_match: Option<&str>,
+    ) -> Result<(), AppError>;
 }